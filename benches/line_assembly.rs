@@ -0,0 +1,141 @@
+//! Throughput of splitting and throttling a synthetic log.
+//!
+//! Run with `cargo bench --bench line_assembly`. Each case prints MB/s over
+//! the same input; `per-line copy` replays the pre-memchr assembler so a
+//! regression shows up as the gap closing. `reader path` goes through
+//! `Follower::reader`, with its reader thread and channel, as stdin does.
+//! Without `--bench` (as under
+//! `cargo test --all-targets`) each case runs once on a small input as a smoke test.
+
+use butt::{
+    Event, FollowOptions, Follower, LineAssembler, LineSource, Pattern, ThrottleConfig, Throttler,
+};
+use std::hint::black_box;
+use std::io::{BufReader, Cursor};
+use std::time::{Duration, Instant};
+
+const CHUNK_BYTES: usize = 64 * 1024;
+const MAX_LINE_BYTES: usize = 16 * 1024;
+const MAX_BUFFER_BYTES: usize = 1024 * 1024;
+
+fn synthetic_log(size: usize) -> Vec<u8> {
+    let mut log = Vec::with_capacity(size + 256);
+    let mut n = 0_u64;
+    while log.len() < size {
+        let level = if n.is_multiple_of(1000) {
+            "ERROR"
+        } else {
+            "INFO"
+        };
+        let line = format!(
+            "2026-10-18T12:00:{:02}.{:06}Z {level} worker-{} handled request id={n:016x} status=200 latency_ms={}\n",
+            n % 60,
+            n % 1_000_000,
+            n % 32,
+            n % 997
+        );
+        log.extend_from_slice(line.as_bytes());
+        n += 1;
+    }
+    log
+}
+
+/// The assembler as it was before: rescan from the start, drain and decode every line.
+fn per_line_copy(input: &[u8]) -> usize {
+    let mut pending = Vec::new();
+    let mut count = 0;
+    for chunk in input.chunks(CHUNK_BYTES) {
+        pending.extend_from_slice(chunk);
+        while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+            let mut line = pending.drain(..=pos).collect::<Vec<u8>>();
+            line.pop();
+            black_box(String::from_utf8_lossy(&line).to_string());
+            count += 1;
+        }
+    }
+    count
+}
+
+fn borrowed_lines(input: &[u8]) -> usize {
+    let mut assembler = LineAssembler::new(MAX_LINE_BYTES, MAX_BUFFER_BYTES);
+    let mut count = 0;
+    for chunk in input.chunks(CHUNK_BYTES) {
+        assembler.push_with(chunk, |line| {
+            black_box(line);
+            count += 1;
+        });
+    }
+    count
+}
+
+fn decoded_batches(input: &[u8]) -> usize {
+    let mut assembler = LineAssembler::new(MAX_LINE_BYTES, MAX_BUFFER_BYTES);
+    let mut count = 0;
+    for chunk in input.chunks(CHUNK_BYTES) {
+        for line in assembler.push(chunk).lines.texts() {
+            black_box(&line);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn reader_path(input: &'static [u8]) -> usize {
+    let options = FollowOptions {
+        max_line_bytes: MAX_LINE_BYTES,
+        max_buffer_bytes: MAX_BUFFER_BYTES,
+        ..FollowOptions::default()
+    };
+    let reader = BufReader::with_capacity(CHUNK_BYTES, Cursor::new(input));
+    let mut follower = Follower::reader(reader, "bench", options);
+    let mut count = 0;
+    while let Some(batch) = follower.poll(Duration::from_secs(1)).expect("poll") {
+        for line in batch.lines.iter() {
+            black_box(line);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn throttled(input: &[u8]) -> usize {
+    let mut assembler = LineAssembler::new(MAX_LINE_BYTES, MAX_BUFFER_BYTES);
+    let mut throttler = Throttler::new(ThrottleConfig::new(Duration::from_secs(2)))
+        .with_pattern(Some(Pattern::new("ERROR|FATAL", false).expect("pattern")));
+    let mut events: Vec<Event> = Vec::new();
+    for chunk in input.chunks(CHUNK_BYTES) {
+        assembler.push_with(chunk, |line| {
+            throttler.observe(line, &mut events);
+        });
+        throttler.tick(&mut events);
+        events.clear();
+    }
+    throttler.stats().lines as usize
+}
+
+fn bench(name: &str, input: &'static [u8], rounds: usize, run: fn(&'static [u8]) -> usize) {
+    let mut best = Duration::MAX;
+    let mut lines = 0;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        lines = black_box(run(black_box(input)));
+        best = best.min(start.elapsed());
+    }
+    let mb_per_sec = input.len() as f64 / 1e6 / best.as_secs_f64();
+    println!("{name:<16} {lines:>9} lines  {best:>10.2?}  {mb_per_sec:>8.0} MB/s");
+}
+
+fn main() {
+    let (size, rounds) = if std::env::args().any(|arg| arg == "--bench") {
+        (64 * 1024 * 1024, 5)
+    } else {
+        (256 * 1024, 1)
+    };
+    // Leaked so the reader thread in `reader path` can borrow it.
+    let input: &'static [u8] = synthetic_log(size).leak();
+    bench("per-line copy", input, rounds, per_line_copy);
+    bench("borrowed lines", input, rounds, borrowed_lines);
+    bench("decoded batches", input, rounds, decoded_batches);
+    bench("reader path", input, rounds, reader_path);
+    bench("throttled", input, rounds, throttled);
+}
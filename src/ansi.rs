@@ -0,0 +1,177 @@
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::ops::Range;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// What to do with ANSI escape sequences that arrive in input lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum AnsiMode {
+    /// Remove every escape sequence before matching and printing.
+    Strip,
+    /// Keep color and style (SGR) sequences even when colors are disabled;
+    /// escape everything else.
+    Keep,
+    /// Keep color and style (SGR) sequences when colors are enabled; escape
+    /// everything else.
+    #[default]
+    Sanitize,
+}
+
+/// A run of text or one complete escape sequence within a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    Text(Range<usize>),
+    Escape {
+        span: Range<usize>,
+        /// Whether it is a Select Graphic Rendition (`ESC [ ... m`) sequence.
+        sgr: bool,
+    },
+}
+
+/// Splits `line` into text and escape sequences: CSI (`ESC [`), OSC
+/// (`ESC ]`, ended by BEL or `ESC \`), DCS/SOS/PM/APC strings and two-byte
+/// escapes. An unterminated sequence runs to the end of the line; a lone ESC
+/// is left in the text.
+pub fn pieces(line: &[u8]) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut text_start = 0;
+    let mut at = 0;
+    while let Some(offset) = memchr::memchr(ESC, &line[at..]) {
+        let start = at + offset;
+        let Some((end, sgr)) = sequence_end(line, start) else {
+            at = start + 1;
+            continue;
+        };
+        if text_start < start {
+            pieces.push(Piece::Text(text_start..start));
+        }
+        pieces.push(Piece::Escape {
+            span: start..end,
+            sgr,
+        });
+        text_start = end;
+        at = end;
+    }
+    if text_start < line.len() {
+        pieces.push(Piece::Text(text_start..line.len()));
+    }
+    pieces
+}
+
+/// Where the escape sequence starting at `line[start]` ends, and whether it is SGR.
+fn sequence_end(line: &[u8], start: usize) -> Option<(usize, bool)> {
+    let kind = *line.get(start + 1)?;
+    let body = start + 2;
+    match kind {
+        b'[' => Some(
+            match line[body..]
+                .iter()
+                .position(|byte| (0x40..=0x7e).contains(byte))
+            {
+                Some(offset) => (body + offset + 1, line[body + offset] == b'm'),
+                None => (line.len(), false),
+            },
+        ),
+        b']' | b'P' | b'X' | b'^' | b'_' => {
+            let mut at = body;
+            while at < line.len() {
+                match line[at] {
+                    BEL if kind == b']' => return Some((at + 1, false)),
+                    ESC if line.get(at + 1) == Some(&b'\\') => return Some((at + 2, false)),
+                    _ => at += 1,
+                }
+            }
+            Some((line.len(), false))
+        }
+        // Intermediate bytes (as in `ESC ( B`) come before the final byte.
+        0x20..=0x2f => {
+            let end = line[body..]
+                .iter()
+                .position(|byte| (0x30..=0x7e).contains(byte))
+                .map_or(line.len(), |offset| body + offset + 1);
+            Some((end, false))
+        }
+        0x30..=0x7e => Some((start + 2, false)),
+        _ => None,
+    }
+}
+
+/// Whether the SGR sequence `sequence` resets every attribute (`ESC [ m`, `ESC [ 0 m`).
+pub fn is_reset(sequence: &[u8]) -> bool {
+    sequence
+        .get(2..sequence.len().saturating_sub(1))
+        .is_some_and(|params| params.iter().all(|&byte| byte == b'0' || byte == b';'))
+}
+
+/// `line` without any escape sequences. Borrowed when there are none.
+pub fn strip(line: &[u8]) -> Cow<'_, [u8]> {
+    let pieces = pieces(line);
+    if !pieces
+        .iter()
+        .any(|piece| matches!(piece, Piece::Escape { .. }))
+    {
+        return Cow::Borrowed(line);
+    }
+    let mut out = Vec::with_capacity(line.len());
+    for piece in pieces {
+        if let Piece::Text(span) = piece {
+            out.extend_from_slice(&line[span]);
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_csi_osc_and_two_byte_sequences() {
+        let line = b"a\x1b[1;31mb\x1b]0;title\x07c\x1b(Bd\x1b[2K";
+        assert_eq!(
+            pieces(line),
+            vec![
+                Piece::Text(0..1),
+                Piece::Escape {
+                    span: 1..8,
+                    sgr: true
+                },
+                Piece::Text(8..9),
+                Piece::Escape {
+                    span: 9..19,
+                    sgr: false
+                },
+                Piece::Text(19..20),
+                Piece::Escape {
+                    span: 20..23,
+                    sgr: false
+                },
+                Piece::Text(23..24),
+                Piece::Escape {
+                    span: 24..28,
+                    sgr: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_sequences_and_keeps_lone_escapes() {
+        assert_eq!(
+            strip(b"\x1b[31mERROR\x1b[0m: \x1b]8;;http://x\x1b\\link\x1b]8;;\x1b\\").as_ref(),
+            b"ERROR: link"
+        );
+        assert_eq!(strip(b"trailing \x1b").as_ref(), b"trailing \x1b");
+        assert!(matches!(strip(b"plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn recognizes_resets() {
+        assert!(is_reset(b"\x1b[m"));
+        assert!(is_reset(b"\x1b[0m"));
+        assert!(is_reset(b"\x1b[00;0m"));
+        assert!(!is_reset(b"\x1b[0;31m"));
+    }
+}
@@ -0,0 +1,811 @@
+use crate::cli::{Args, SummaryFormat};
+use crate::keys::{KeyAction, KeyControls};
+use crate::signals;
+use crate::status::{StatusBar, StatusView, TerminalFeatures};
+use crate::summary;
+use butt::hook::{idle_vars, match_vars, missed_vars};
+use butt::progress::ProgressChange;
+use butt::timestamp::{LagChange, LagMonitor};
+use butt::{
+    Alert, Batch, Clock, ColorSupport, Event, Expectation, HeartbeatFile, Highlighter, HookKind,
+    HookOptions, HookReport, Hooks, LineSource, MetricsServer, Pattern, ProgressTracker, Sink,
+    TeeSink, TeeTarget, ThrottleConfig, Throttler, TimestampParser, Watchdog, Webhook,
+    WebhookOptions,
+};
+use butt::{AnsiMode, ansi, syslog, text};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long exit waits for a slow tee to write out its queue.
+const TEE_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long exit waits for queued webhook alerts to be delivered.
+const WEBHOOK_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prints events to stdout, keeping the status line out of the way, copies
+/// them to the `--tee-emitted` target, and runs the `--on-idle` and
+/// `--on-missed` hooks and webhook. Emitted `--progress` lines get their rate and ETA appended.
+struct TerminalSink {
+    highlighter: Highlighter,
+    status: Option<StatusBar>,
+    tee: Option<TeeSink>,
+    hooks: Hooks,
+    /// Hook problems waiting to be printed as diagnostics.
+    hook_reports: Vec<HookReport>,
+    /// Alerts for the webhook, if one is configured, until counters are added.
+    alerts: Option<Vec<Alert>>,
+    progress: Option<ProgressTracker>,
+    /// The throttler clock's time, set by [`App`] before it calls into the
+    /// throttler, so progress rates follow the same clock as throttling.
+    now: Instant,
+    /// The first missed notice, for `--exit-on-missed`.
+    missed: Option<String>,
+    source: String,
+}
+
+impl TerminalSink {
+    fn print_line(&mut self, text: &str) {
+        self.print_bytes(text.as_bytes());
+    }
+
+    /// Print one line as-is, so input that is not UTF-8 reaches stdout unchanged.
+    fn print_bytes(&mut self, text: &[u8]) {
+        let mut out = io::stdout().lock();
+        if let Some(status) = &mut self.status {
+            status.clear(&mut out);
+        }
+        let _ = out.write_all(text);
+        let _ = out.write_all(b"\n");
+        let _ = out.flush();
+    }
+
+    fn clear_status(&mut self) {
+        if let Some(status) = &mut self.status {
+            let mut out = io::stdout().lock();
+            status.clear(&mut out);
+            let _ = out.flush();
+        }
+    }
+}
+
+impl Sink for TerminalSink {
+    fn emit(&mut self, event: &Event) {
+        let mut text = self.highlighter.render_bytes(event);
+        if let (Event::Line(line), Some(progress)) = (event, &self.progress)
+            && let Some(note) = progress.annotate(&line.bytes, self.now)
+        {
+            text.extend_from_slice(b"  ");
+            text.extend_from_slice(note.as_bytes());
+        }
+        self.print_bytes(&text);
+        if let Some(tee) = &mut self.tee {
+            tee.emit(event);
+        }
+        if let Event::Idle { interval } = event
+            && self.hooks.has_command(HookKind::Idle)
+        {
+            let vars = idle_vars(&self.source, *interval);
+            self.hook_reports
+                .extend(self.hooks.trigger(HookKind::Idle, &vars, Instant::now()));
+        }
+        if let (Event::Idle { interval }, Some(alerts)) = (event, &mut self.alerts) {
+            alerts.push(Alert::idle(*interval));
+        }
+        if let Event::Missed { pattern, within } = event {
+            if self.hooks.has_command(HookKind::Missed) {
+                let vars = missed_vars(&self.source, pattern, *within);
+                self.hook_reports.extend(self.hooks.trigger(
+                    HookKind::Missed,
+                    &vars,
+                    Instant::now(),
+                ));
+            }
+            if let Some(alerts) = &mut self.alerts {
+                alerts.push(Alert::missed(pattern, *within));
+            }
+            self.missed.get_or_insert_with(|| {
+                format!(
+                    "no line matching '{pattern}' for {} seconds",
+                    within.as_secs()
+                )
+            });
+        }
+    }
+}
+
+struct App<'a, C: Clock> {
+    args: &'a Args,
+    throttler: Throttler<C>,
+    output: TerminalSink,
+    /// Most recent input lines, kept for `--tail-on-exit`.
+    recent_lines: VecDeque<Vec<u8>>,
+    keys: Option<KeyControls>,
+    /// Receives every input line before throttling (`--tee-all`).
+    tee_all: Option<TeeSink>,
+    webhook: Option<Webhook>,
+    metrics: Option<MetricsServer>,
+    heartbeat: Option<HeartbeatFile>,
+    watchdog: Option<Watchdog>,
+    /// Whether the last heartbeat and watchdog writes failed, so errors are reported once.
+    heartbeat_failing: bool,
+    watchdog_failing: bool,
+    /// Reads line timestamps for lag tracking and `--listen` ordering.
+    timestamps: Option<TimestampParser>,
+    lag: LagMonitor,
+}
+
+impl<'a, C: Clock> App<'a, C> {
+    fn new(
+        args: &'a Args,
+        regex: Option<Pattern>,
+        colors_enabled: bool,
+        terminal: TerminalFeatures,
+        source: String,
+        outputs: Outputs,
+        clock: C,
+    ) -> Self {
+        let mut config = ThrottleConfig::new(Duration::from_secs(args.line_seconds));
+        if let Some(idle) = args.idle_seconds {
+            config = config.idle_interval(Duration::from_secs(idle));
+        }
+        let started = clock.now();
+        Self {
+            args,
+            throttler: Throttler::with_clock(config, clock)
+                .with_pattern(regex.clone())
+                .with_expectations(
+                    args.expect
+                        .iter()
+                        .map(|pattern| Expectation::new(pattern.clone(), args.within))
+                        .collect(),
+                ),
+            output: TerminalSink {
+                highlighter: Highlighter::new(regex, args.color, colors_enabled)
+                    .with_color_support(ColorSupport::detect())
+                    .with_scope(args.highlight)
+                    .with_group_styles(args.group_color.clone().unwrap_or_default())
+                    .with_ansi(args.ansi),
+                status: terminal
+                    .status_bar
+                    .then(|| StatusBar::new(source.clone(), colors_enabled)),
+                tee: outputs.tee_emitted,
+                hooks: Hooks::new(HookOptions {
+                    debounce: Duration::from_secs(args.hook_debounce_seconds),
+                    max_running: args.hook_max_running,
+                    timeout: Duration::from_secs(args.hook_timeout_seconds),
+                })
+                .with_command(HookKind::Match, args.on_match.clone())
+                .with_command(HookKind::Idle, args.on_idle.clone())
+                .with_command(HookKind::Missed, args.on_missed.clone()),
+                hook_reports: Vec::new(),
+                alerts: outputs.webhook.as_ref().map(|_| Vec::new()),
+                progress: args.progress.clone().map(|progress| {
+                    progress.with_stall_after(args.stall_seconds.map(Duration::from_secs))
+                }),
+                missed: None,
+                now: started,
+                source,
+            },
+            recent_lines: VecDeque::with_capacity(args.tail_on_exit.unwrap_or(0)),
+            keys: if terminal.keyboard {
+                KeyControls::start()
+            } else {
+                None
+            },
+            tee_all: outputs.tee_all,
+            webhook: outputs.webhook,
+            metrics: outputs.metrics,
+            heartbeat: outputs.heartbeat,
+            watchdog: outputs.watchdog,
+            heartbeat_failing: false,
+            watchdog_failing: false,
+            timestamps: args
+                .timestamps
+                .clone()
+                .or_else(|| args.max_lag.map(|_| TimestampParser::auto())),
+            lag: LagMonitor::new(args.max_lag.map(Duration::from_secs)),
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.throttler.clock().now()
+    }
+
+    fn diagnostic(&mut self, message: &str) {
+        self.output.clear_status();
+        eprintln!("[butt] {message}");
+        let _ = io::stderr().flush();
+    }
+
+    fn refresh_status(&mut self, now: Instant) {
+        if let Some(status) = &mut self.output.status
+            && status.is_due(now)
+        {
+            let view = StatusView {
+                stats: self.throttler.stats(),
+                next_emit: self.throttler.next_emit_in(),
+                line_seconds: self.throttler.config().line_interval.as_secs(),
+                paused: self.throttler.is_paused(),
+                prompt: self.keys.as_ref().and_then(KeyControls::prompt),
+            };
+            status.draw(&mut io::stdout().lock(), &view, now);
+        }
+    }
+
+    /// The missed notice that ends the run under `--exit-on-missed`.
+    fn missed_exit(&self) -> Option<&str> {
+        self.args
+            .exit_on_missed
+            .then_some(self.output.missed.as_deref())
+            .flatten()
+    }
+
+    /// Apply pending keystrokes and signals. Returns `true` when butt should exit.
+    fn poll_controls(&mut self) -> bool {
+        if signals::shutdown_requested() {
+            return true;
+        }
+        if signals::take_summary_request() {
+            self.print_summary(self.args.summary);
+        }
+
+        while let Some(action) = self.keys.as_mut().and_then(KeyControls::next_action) {
+            let line_seconds = self.throttler.config().line_interval.as_secs();
+            match action {
+                KeyAction::IncreaseInterval => self.set_line_seconds(line_seconds + 1),
+                KeyAction::DecreaseInterval => {
+                    self.set_line_seconds(line_seconds.saturating_sub(1).max(1))
+                }
+                KeyAction::TogglePause => {
+                    let paused = !self.throttler.is_paused();
+                    self.throttler.set_paused(paused);
+                }
+                KeyAction::Flush => {
+                    self.output.now = self.now();
+                    self.throttler.flush(&mut self.output);
+                }
+                KeyAction::SetRegex(pattern) => self.set_regex(&pattern),
+                KeyAction::Quit => return true,
+            }
+            self.output.clear_status();
+        }
+        self.refresh_status(self.now());
+        false
+    }
+
+    fn set_line_seconds(&mut self, line_seconds: u64) {
+        self.throttler
+            .set_line_interval(Duration::from_secs(line_seconds));
+    }
+
+    fn set_regex(&mut self, pattern: &str) {
+        let regex = if pattern.is_empty() {
+            None
+        } else {
+            match Pattern::new(pattern, self.args.regex_case_insensitive) {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    self.diagnostic(&format!("invalid regex '{pattern}': {err}"));
+                    return;
+                }
+            }
+        };
+        self.output.highlighter.set_pattern(regex.clone());
+        self.throttler.set_pattern(regex);
+    }
+
+    fn print_summary(&mut self, format: SummaryFormat) {
+        let now = self.now();
+        let report = match format {
+            SummaryFormat::Text => summary::render_text(self.throttler.stats(), now),
+            SummaryFormat::Json => summary::render_json(self.throttler.stats(), now),
+            SummaryFormat::Off => return,
+        };
+        self.output.clear_status();
+        eprintln!("{report}");
+        let _ = io::stderr().flush();
+    }
+
+    /// Flush the pending line, print the exit tail and summary, and clear the status line.
+    fn finish(&mut self) {
+        self.output.now = self.now();
+        self.throttler.flush(&mut self.output);
+
+        if !self.recent_lines.is_empty() {
+            self.output
+                .print_line(&format!("[last {} lines]", self.recent_lines.len()));
+            for line in std::mem::take(&mut self.recent_lines) {
+                let text = self.output.highlighter.highlight_bytes(&line);
+                self.output.print_bytes(&text);
+            }
+        }
+
+        self.output.clear_status();
+        let running = self.output.hooks.running();
+        if running > 0 {
+            self.diagnostic(&format!(
+                "waiting up to {}s for {running} running hook(s)",
+                self.args.hook_timeout_seconds
+            ));
+        }
+        let reports = self.output.hooks.finish();
+        self.output.hook_reports.extend(reports);
+        self.report_hooks();
+        self.close_webhook();
+        self.close_tees();
+        if let Some(watchdog) = &self.watchdog {
+            let _ = watchdog.notify("STOPPING=1");
+        }
+        self.print_summary(self.args.summary);
+    }
+
+    fn report_hooks(&mut self) {
+        for report in std::mem::take(&mut self.output.hook_reports) {
+            self.diagnostic(&report.to_string());
+        }
+    }
+
+    /// Queue collected alerts with the current counters.
+    fn send_alerts(&mut self) {
+        let (Some(webhook), Some(alerts)) = (&mut self.webhook, &mut self.output.alerts) else {
+            return;
+        };
+        let counters = self.throttler.stats().into();
+        for mut alert in alerts.drain(..) {
+            alert.counters = counters;
+            webhook.send(alert);
+        }
+    }
+
+    fn report_webhook(&mut self) {
+        let Some(webhook) = &self.webhook else {
+            return;
+        };
+        for failure in webhook.take_failures() {
+            self.diagnostic(&failure.to_string());
+        }
+    }
+
+    fn close_webhook(&mut self) {
+        self.send_alerts();
+        let Some(webhook) = &mut self.webhook else {
+            return;
+        };
+        let finished = webhook.close(WEBHOOK_CLOSE_TIMEOUT);
+        let (url, dropped) = (webhook.url().to_string(), webhook.dropped());
+        self.report_webhook();
+        if !finished {
+            self.diagnostic(&format!(
+                "webhook {url} still delivering at exit; remaining alerts are lost"
+            ));
+        }
+        if dropped > 0 {
+            self.diagnostic(&format!(
+                "webhook {url} dropped {dropped} alert(s) while its queue was full"
+            ));
+        }
+    }
+
+    /// Report tees whose writer stopped with an error.
+    fn check_tees(&mut self) {
+        let failed: Vec<String> = [&self.tee_all, &self.output.tee]
+            .into_iter()
+            .flatten()
+            .filter_map(|tee| {
+                tee.take_error()
+                    .map(|err| format!("tee to {} failed: {err}", tee.target()))
+            })
+            .collect();
+        for message in failed {
+            self.diagnostic(&message);
+        }
+    }
+
+    fn close_tees(&mut self) {
+        self.check_tees();
+        let mut messages = Vec::new();
+        for tee in [&mut self.tee_all, &mut self.output.tee]
+            .into_iter()
+            .flatten()
+        {
+            if !tee.close(TEE_CLOSE_TIMEOUT) {
+                messages.push(format!(
+                    "tee to {} did not finish writing; remaining lines are lost",
+                    tee.target()
+                ));
+            }
+            if let Some(err) = tee.take_error() {
+                messages.push(format!("tee to {} failed: {err}", tee.target()));
+            }
+            if tee.dropped() > 0 {
+                messages.push(format!(
+                    "tee to {} dropped {} line(s) it could not keep up with",
+                    tee.target(),
+                    tee.dropped()
+                ));
+            }
+        }
+        for message in messages {
+            self.diagnostic(&message);
+        }
+    }
+
+    /// Report the first of a run of failed writes, and the recovery; returns
+    /// whether `what` is now failing.
+    fn report_liveness(&mut self, what: &str, failing: bool, result: io::Result<()>) -> bool {
+        match result {
+            Err(err) => {
+                if !failing {
+                    self.diagnostic(&format!("{what} failed: {err}"));
+                }
+                true
+            }
+            Ok(()) => {
+                if failing {
+                    self.diagnostic(&format!("{what} recovered"));
+                }
+                false
+            }
+        }
+    }
+
+    fn beat(&mut self) {
+        let now = self.now();
+        let Some(heartbeat) = &mut self.heartbeat else {
+            return;
+        };
+        let result = heartbeat
+            .beat(self.throttler.stats(), &self.output.source, now)
+            .map(|_| ());
+        let what = format!("heartbeat file {}", heartbeat.path().display());
+        self.heartbeat_failing = self.report_liveness(&what, self.heartbeat_failing, result);
+    }
+
+    fn remember_line(&mut self, line: &[u8], peer: Option<&str>) {
+        let Some(capacity) = self.args.tail_on_exit else {
+            return;
+        };
+        // Reuse the evicted line's allocation once the window is full.
+        let mut slot = if self.recent_lines.len() == capacity {
+            self.recent_lines.pop_front().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        slot.clear();
+        if let Some(peer) = peer {
+            slot.extend_from_slice(format!("[{peer}] ").as_bytes());
+        }
+        slot.extend_from_slice(line);
+        self.recent_lines.push_back(slot);
+    }
+
+    fn observe(&mut self, batch: Batch) {
+        if batch.bytes > 0 {
+            let now = self.now();
+            self.throttler.stats_mut().record_bytes(batch.bytes, now);
+        }
+        for notice in &batch.notices {
+            self.throttler.stats_mut().record_notice(notice);
+            self.diagnostic(&notice.to_string());
+        }
+        let had_input = batch.bytes > 0 || !batch.lines.is_empty();
+        match self.timestamps.take() {
+            Some(parser) => {
+                self.observe_timestamped(&batch, &parser);
+                self.timestamps = Some(parser);
+            }
+            None => {
+                for (index, line) in batch.lines.iter().enumerate() {
+                    self.observe_line(line, batch.peer(index));
+                }
+            }
+        }
+        if had_input {
+            self.beat();
+        }
+        self.send_alerts();
+    }
+
+    /// Track lag for each line and, with `--listen`, observe lines in the
+    /// order they were logged.
+    fn observe_timestamped(&mut self, batch: &Batch, parser: &TimestampParser) {
+        let now = SystemTime::now();
+        let stamps: Vec<Option<SystemTime>> = batch
+            .lines
+            .iter()
+            .map(|line| parser.parse(line, now))
+            .collect();
+        let mut order: Vec<usize> = (0..batch.lines.len()).collect();
+        if self.args.listen.is_some() {
+            // Peers send independently, so lines arriving together may be out of
+            // order. Only this batch is sorted; holding lines back for later
+            // batches would delay output. Lines without a timestamp stay behind
+            // the line before them.
+            let mut last = None;
+            let keys: Vec<Option<SystemTime>> = stamps
+                .iter()
+                .map(|stamp| {
+                    last = stamp.or(last);
+                    last
+                })
+                .collect();
+            order.sort_by_key(|&index| keys[index]);
+        }
+        for index in order {
+            if let Some(stamp) = stamps[index] {
+                self.record_lag(LagMonitor::lag(stamp, now));
+            }
+            if let Some(line) = batch.lines.get(index) {
+                self.observe_line(line, batch.peer(index));
+            }
+        }
+    }
+
+    fn record_lag(&mut self, lag: Duration) {
+        self.throttler.stats_mut().record_lag(lag);
+        let Some(max_lag) = self.args.max_lag else {
+            return;
+        };
+        match self.lag.observe(lag) {
+            Some(LagChange::Exceeded(lag)) => self.diagnostic(&format!(
+                "lines are arriving {}s after their timestamps (over --max-lag {max_lag}s); the producer may be buffering or stuck",
+                lag.as_secs()
+            )),
+            Some(LagChange::Recovered(lag)) => {
+                self.diagnostic(&format!("lag back to {}s", lag.as_secs()))
+            }
+            None => {}
+        }
+    }
+
+    /// Observe one input line from `peer`, if the source names senders. Only
+    /// what is written out carries the `[peer] ` label; matching sees the line.
+    fn observe_line(&mut self, line: &[u8], peer: Option<&str>) {
+        if let Some(tee) = &mut self.tee_all {
+            match peer {
+                Some(peer) => tee.write_line([format!("[{peer}] ").as_bytes(), line].concat()),
+                None => tee.write_line(line),
+            }
+        }
+        self.throttler.set_peer(peer);
+        // Binary data is summarized rather than matched or printed; `--tee-all` keeps it raw.
+        let summary;
+        let line = if text::is_binary(line) {
+            summary = text::binary_summary(line.len());
+            summary.as_bytes()
+        } else {
+            line
+        };
+        let stripped;
+        let line = if self.args.ansi == AnsiMode::Strip {
+            stripped = ansi::strip(line);
+            &*stripped
+        } else {
+            line
+        };
+        // Syslog framing is text, so only `--syslog` decodes lines before matching.
+        let decoded = self
+            .args
+            .syslog
+            .then(|| syslog::reformat(&String::from_utf8_lossy(line)))
+            .flatten();
+        let (text, severity) = match decoded {
+            Some((text, severity)) => (Cow::Owned(text.into_bytes()), Some(severity)),
+            None => (Cow::Borrowed(line), None),
+        };
+        self.remember_line(&text, peer);
+        self.observe_progress(&text);
+        let reason = severity
+            .filter(|severity| severity.at_least(self.args.urgent_severity))
+            .map(|severity| format!("severity {severity}"));
+        let urgent = match &reason {
+            Some(reason) => {
+                self.throttler
+                    .observe_urgent(&*text, reason, &mut self.output);
+                true
+            }
+            None => self.throttler.observe(&*text, &mut self.output),
+        };
+        if urgent {
+            self.alert_match(&text, peer, reason.as_deref());
+        }
+    }
+
+    fn observe_progress(&mut self, line: &[u8]) {
+        let now = self.now();
+        self.output.now = now;
+        let change = self
+            .output
+            .progress
+            .as_mut()
+            .and_then(|progress| progress.observe(line, now));
+        if let Some(change) = change {
+            self.report_progress(change);
+        }
+    }
+
+    fn report_progress(&mut self, change: ProgressChange) {
+        match change {
+            ProgressChange::Stalled(reading, still) => self.diagnostic(&format!(
+                "progress stalled at {reading} for {}s (--stall-seconds {})",
+                still.as_secs(),
+                self.args.stall_seconds.unwrap_or_default()
+            )),
+            ProgressChange::Resumed(reading) => {
+                self.diagnostic(&format!("progress moving again at {reading}"))
+            }
+        }
+    }
+
+    /// Run `--on-match` and alert the webhook for an urgent line.
+    ///
+    /// A line from a named peer is labelled `[peer] ` in the alert, and the
+    /// hook gets the peer as its source.
+    fn alert_match(&mut self, line: &[u8], peer: Option<&str>, reason: Option<&str>) {
+        let hook = self.output.hooks.has_command(HookKind::Match);
+        if !hook && self.output.alerts.is_none() {
+            return;
+        }
+        let pattern = self.throttler.pattern();
+        let Some(label) = reason.or(pattern.map(Pattern::as_str)) else {
+            return;
+        };
+        let text = String::from_utf8_lossy(line);
+        if let Some(alerts) = &mut self.output.alerts {
+            let groups = pattern
+                .map(|pattern| pattern.named_groups(line))
+                .unwrap_or_default();
+            let text = match peer {
+                Some(peer) => Cow::Owned(format!("[{peer}] {text}")),
+                None => Cow::Borrowed(&*text),
+            };
+            alerts.push(Alert::matched(&text, label).with_groups(groups));
+        }
+        if hook {
+            let source = peer.unwrap_or(&self.output.source);
+            let vars = match_vars(source, &text, pattern, reason);
+            // Hooks are real processes, so their debounce and timeout use wall-clock time.
+            let report = self
+                .output
+                .hooks
+                .trigger(HookKind::Match, &vars, Instant::now());
+            self.output.hook_reports.extend(report);
+        }
+    }
+
+    fn tick(&mut self) {
+        let now = self.now();
+        self.output.now = now;
+        self.throttler.tick(&mut self.output);
+        let stall = self
+            .output
+            .progress
+            .as_mut()
+            .and_then(|progress| progress.check_stall(now));
+        if let Some(change) = stall {
+            self.report_progress(change);
+        }
+        let reports = self.output.hooks.poll(Instant::now());
+        self.output.hook_reports.extend(reports);
+        self.report_hooks();
+        self.send_alerts();
+        self.report_webhook();
+        self.check_tees();
+        if let Some(metrics) = &self.metrics {
+            metrics.update(self.throttler.stats());
+        }
+        let now = self.now();
+        if let Some(watchdog) = &mut self.watchdog {
+            let result = watchdog.tick(self.throttler.stats().is_idle(now), now);
+            self.watchdog_failing =
+                self.report_liveness("sd_notify", self.watchdog_failing, result);
+        }
+        self.refresh_status(self.now());
+    }
+}
+
+/// The `--tee-all` and `--tee-emitted` sinks, the `--webhook` sender, the
+/// `--metrics-addr` server, and the `--heartbeat-file` and `--sd-notify`
+/// liveness signals.
+struct Outputs {
+    tee_all: Option<TeeSink>,
+    tee_emitted: Option<TeeSink>,
+    webhook: Option<Webhook>,
+    metrics: Option<MetricsServer>,
+    heartbeat: Option<HeartbeatFile>,
+    watchdog: Option<Watchdog>,
+}
+
+impl Outputs {
+    fn open(args: &Args, webhook: Option<WebhookOptions>, source: &str) -> io::Result<Self> {
+        let open = |target: TeeTarget| {
+            TeeSink::open(target.clone()).map_err(|err| {
+                io::Error::new(err.kind(), format!("cannot open tee {target}: {err}"))
+            })
+        };
+        let metrics = match &args.metrics_addr {
+            Some(addr) => {
+                let server = MetricsServer::bind(addr).map_err(|err| {
+                    io::Error::new(err.kind(), format!("cannot serve metrics on {addr}: {err}"))
+                })?;
+                eprintln!(
+                    "[butt] serving metrics on http://{}/metrics",
+                    server.local_addr()
+                );
+                Some(server)
+            }
+            None => None,
+        };
+        let watchdog = if args.sd_notify {
+            let watchdog = Watchdog::from_env()
+                .map_err(|err| io::Error::new(err.kind(), format!("cannot sd_notify: {err}")))?;
+            match &watchdog {
+                Some(watchdog) => {
+                    watchdog.notify(&format!("READY=1\nSTATUS=following {source}"))?
+                }
+                None => eprintln!("[butt] --sd-notify: NOTIFY_SOCKET is not set, not notifying"),
+            }
+            watchdog
+        } else {
+            None
+        };
+        Ok(Self {
+            tee_all: args
+                .tee_all
+                .clone()
+                .map(|path| open(TeeTarget::File(path)))
+                .transpose()?,
+            tee_emitted: args.tee_emitted.clone().map(open).transpose()?,
+            webhook: args
+                .webhook
+                .as_deref()
+                .zip(webhook)
+                .map(|(url, options)| Webhook::start(url, source, options)),
+            metrics,
+            heartbeat: args
+                .heartbeat_file
+                .as_ref()
+                .map(|path| HeartbeatFile::new(path, args.heartbeat_format)),
+            watchdog,
+        })
+    }
+}
+
+/// Follow `source` until it ends or the user asks to quit, timing output with `clock`.
+pub(crate) fn run(
+    args: &Args,
+    mut source: impl LineSource,
+    regex: Option<Pattern>,
+    colors_enabled: bool,
+    terminal: TerminalFeatures,
+    webhook: Option<WebhookOptions>,
+    clock: impl Clock,
+) -> io::Result<()> {
+    let poll = Duration::from_millis(args.poll_millis);
+    let label = source.label();
+    let outputs = Outputs::open(args, webhook, &label)?;
+    let mut app = App::new(args, regex, colors_enabled, terminal, label, outputs, clock);
+
+    loop {
+        if app.poll_controls() {
+            app.finish();
+            return Ok(());
+        }
+        app.tick();
+        if let Some(notice) = app.missed_exit() {
+            let message = format!("{notice}; exiting (--exit-on-missed)");
+            app.finish();
+            return Err(io::Error::other(message));
+        }
+
+        match source.poll(poll)? {
+            Some(batch) => app.observe(batch),
+            None => {
+                app.finish();
+                return Ok(());
+            }
+        }
+    }
+}
@@ -1,33 +1,24 @@
+use butt::syslog::Severity;
+use butt::{
+    AnsiMode, Encoding, GroupStyles, HeartbeatFormat, HighlightScope, ListenAddr, Pattern,
+    ProgressTracker, Style, TeeTarget, TimestampParser, WebhookFormat,
+};
+use clap::builder::BoolishValueParser;
 use clap::{Parser, ValueEnum, value_parser};
 use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Clone, ValueEnum)]
-pub(crate) enum HighlightColor {
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Magenta,
-    Cyan,
-}
-
-impl HighlightColor {
-    pub(crate) fn ansi_code(&self) -> &'static str {
-        match self {
-            Self::Red => "31",
-            Self::Green => "32",
-            Self::Yellow => "33",
-            Self::Blue => "34",
-            Self::Magenta => "35",
-            Self::Cyan => "36",
-        }
-    }
-
-    pub(crate) fn paint(&self, input: &str) -> String {
-        format!("\x1b[{}m{}\x1b[0m", self.ansi_code(), input)
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SummaryFormat {
+    Text,
+    Json,
+    Off,
 }
 
+/// Every option can also be set with a `BUTT_*` environment variable named after
+/// the field (e.g. `BUTT_LINE_SECONDS`). Flags accept `true`/`false`, `1`/`0`,
+/// `yes`/`no` or `on`/`off`; list-valued options take comma-separated values,
+/// except `BUTT_EXPECT`, which holds a single regex.
 #[derive(Debug, Parser)]
 #[command(
     name = "butt",
@@ -36,12 +27,174 @@ impl HighlightColor {
 )]
 pub(crate) struct Args {
     /// File to follow. If omitted, reads from stdin.
+    #[arg(env = "BUTT_PATH")]
     pub(crate) path: Option<PathBuf>,
 
+    /// Accept lines on udp://HOST:PORT, tcp://HOST:PORT or unix:///PATH instead of PATH or stdin; with --timestamps, lines are reordered only within each poll.
+    #[arg(long, env = "BUTT_LISTEN", value_name = "URL", conflicts_with = "path")]
+    pub(crate) listen: Option<ListenAddr>,
+
+    /// Decode syslog (RFC 5424/3164) frames and show them as `host app: msg`.
+    #[arg(
+        long,
+        env = "BUTT_SYSLOG",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
+    pub(crate) syslog: bool,
+
+    /// With --syslog, messages at this severity or worse are urgent.
+    #[arg(
+        long = "urgent-severity",
+        env = "BUTT_URGENT_SEVERITY",
+        value_enum,
+        value_name = "LEVEL",
+        default_value = "err"
+    )]
+    pub(crate) urgent_severity: Severity,
+
+    /// Append every input line, unthrottled, to this file.
+    #[arg(long = "tee-all", env = "BUTT_TEE_ALL", value_name = "FILE")]
+    pub(crate) tee_all: Option<PathBuf>,
+
+    /// Also send emitted output to FILE, udp://HOST:PORT or exec:COMMAND (lines on its stdin).
+    #[arg(long = "tee-emitted", env = "BUTT_TEE_EMITTED", value_name = "TARGET")]
+    pub(crate) tee_emitted: Option<TeeTarget>,
+
+    /// Shell command run for each urgent line; details are in BUTT_HOOK_LINE, BUTT_HOOK_PATTERN, BUTT_HOOK_GROUP_* and BUTT_HOOK_SOURCE.
+    #[arg(long = "on-match", env = "BUTT_ON_MATCH", value_name = "CMD")]
+    pub(crate) on_match: Option<String>,
+
+    /// Shell command run for each idle notice, with BUTT_HOOK_IDLE_SECONDS and BUTT_HOOK_SOURCE set.
+    #[arg(long = "on-idle", env = "BUTT_ON_IDLE", value_name = "CMD")]
+    pub(crate) on_idle: Option<String>,
+
+    /// Shell command run for each missed notice, with BUTT_HOOK_PATTERN, BUTT_HOOK_WITHIN_SECONDS and BUTT_HOOK_SOURCE set.
+    #[arg(long = "on-missed", env = "BUTT_ON_MISSED", value_name = "CMD")]
+    pub(crate) on_missed: Option<String>,
+
+    /// Run each hook at most once per N seconds; triggers in between are skipped.
+    #[arg(
+        long = "hook-debounce-seconds",
+        env = "BUTT_HOOK_DEBOUNCE_SECONDS",
+        default_value_t = 10
+    )]
+    pub(crate) hook_debounce_seconds: u64,
+
+    /// Hooks allowed to run at once; further triggers are skipped.
+    #[arg(
+        long = "hook-max-running",
+        env = "BUTT_HOOK_MAX_RUNNING",
+        default_value_t = 4,
+        value_parser = parse_positive_usize
+    )]
+    pub(crate) hook_max_running: usize,
+
+    /// Kill hooks still running after N seconds.
+    #[arg(
+        long = "hook-timeout-seconds",
+        env = "BUTT_HOOK_TIMEOUT_SECONDS",
+        default_value_t = 30,
+        value_parser = value_parser!(u64).range(1..)
+    )]
+    pub(crate) hook_timeout_seconds: u64,
+
+    /// POST urgent lines and idle notices as JSON to this http:// or https:// URL.
+    #[arg(long, env = "BUTT_WEBHOOK", value_name = "URL", value_parser = butt::webhook::parse_url)]
+    pub(crate) webhook: Option<String>,
+
+    /// Webhook request body.
+    #[arg(
+        long = "webhook-format",
+        env = "BUTT_WEBHOOK_FORMAT",
+        value_enum,
+        default_value = "json"
+    )]
+    pub(crate) webhook_format: WebhookFormat,
+
+    /// Custom webhook body with {{text}}, {{source}}, {{pattern}}, {{count}}, {{events}}, {{lines}}, {{emitted}}, {{suppressed}} and {{urgent}}; @FILE reads it from a file.
+    #[arg(
+        long = "webhook-template",
+        env = "BUTT_WEBHOOK_TEMPLATE",
+        value_name = "TEMPLATE",
+        requires = "webhook"
+    )]
+    pub(crate) webhook_template: Option<String>,
+
+    /// Alerts within N seconds of the first are sent in one request.
+    #[arg(
+        long = "webhook-batch-seconds",
+        env = "BUTT_WEBHOOK_BATCH_SECONDS",
+        default_value_t = 5
+    )]
+    pub(crate) webhook_batch_seconds: u64,
+
+    /// Retries for a failed request, with exponential backoff from 1s up to 60s.
+    #[arg(
+        long = "webhook-retries",
+        env = "BUTT_WEBHOOK_RETRIES",
+        default_value_t = 5
+    )]
+    pub(crate) webhook_retries: u32,
+
+    /// Alerts queued while the endpoint is slow or failing; more are dropped.
+    #[arg(
+        long = "webhook-queue",
+        env = "BUTT_WEBHOOK_QUEUE",
+        default_value_t = 1000,
+        value_parser = parse_positive_usize
+    )]
+    pub(crate) webhook_queue: usize,
+
+    /// Serve Prometheus metrics at http://ADDR/metrics (e.g. 127.0.0.1:9187).
+    #[arg(long = "metrics-addr", env = "BUTT_METRICS_ADDR", value_name = "ADDR")]
+    pub(crate) metrics_addr: Option<String>,
+
+    /// Touch (or rewrite, see --heartbeat-format) this file whenever input arrives, at most once per second.
+    #[arg(
+        long = "heartbeat-file",
+        env = "BUTT_HEARTBEAT_FILE",
+        value_name = "PATH"
+    )]
+    pub(crate) heartbeat_file: Option<PathBuf>,
+
+    /// Heartbeat file contents: touch only updates the mtime, json writes a status object.
+    #[arg(
+        long = "heartbeat-format",
+        env = "BUTT_HEARTBEAT_FORMAT",
+        value_enum,
+        default_value = "touch",
+        requires = "heartbeat_file"
+    )]
+    pub(crate) heartbeat_format: HeartbeatFormat,
+
+    /// Notify systemd (READY=1, WATCHDOG=1 pings); pings pause while input is idle for --idle-seconds.
+    #[arg(
+        long = "sd-notify",
+        env = "BUTT_SD_NOTIFY",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
+    pub(crate) sd_notify: bool,
+
+    /// Apply settings from [profile.NAME] in ~/.config/butt/config.toml or ./.butt.toml.
+    #[arg(long, env = "BUTT_PROFILE")]
+    pub(crate) profile: Option<String>,
+
+    /// Print the effective settings and exit.
+    #[arg(
+        long = "print-config",
+        env = "BUTT_PRINT_CONFIG",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
+    pub(crate) print_config: bool,
+
     /// Print at most one input line per N seconds.
     #[arg(
         short = 'n',
         long = "line-seconds",
+        env = "BUTT_LINE_SECONDS",
         default_value_t = 5,
         value_parser = value_parser!(u64).range(1..)
     )]
@@ -51,29 +204,130 @@ pub(crate) struct Args {
     #[arg(
         short = 'i',
         long = "idle-seconds",
+        env = "BUTT_IDLE_SECONDS",
         value_parser = value_parser!(u64).range(1..)
     )]
     pub(crate) idle_seconds: Option<u64>,
 
+    /// Regex for a line that should keep appearing, such as a periodic
+    /// `health ok`; a notice is printed whenever none has matched for
+    /// --within. Repeat for several patterns, each with its own timer;
+    /// BUTT_EXPECT holds only one, since a regex may contain commas.
+    #[arg(long, env = "BUTT_EXPECT", value_name = "REGEX")]
+    pub(crate) expect: Vec<Pattern>,
+
+    /// How long an --expect pattern may go unmatched: seconds, or a number
+    /// with an s, m or h suffix.
+    #[arg(
+        long,
+        env = "BUTT_WITHIN",
+        value_name = "DURATION",
+        default_value = "60s",
+        value_parser = parse_duration
+    )]
+    pub(crate) within: Duration,
+
+    /// Exit with status 1 the first time an --expect pattern is missed.
+    #[arg(
+        long = "exit-on-missed",
+        env = "BUTT_EXIT_ON_MISSED",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false,
+        requires = "expect"
+    )]
+    pub(crate) exit_on_missed: bool,
+
+    /// Read the time each line was logged: auto, rfc3339, syslog, apache,
+    /// epoch-millis, or a strftime pattern such as `%d.%m.%Y %H:%M:%S`.
+    /// With --listen, lines received in the same poll are put in timestamp order;
+    /// lines from different polls keep their arrival order.
+    #[arg(long = "timestamps", env = "BUTT_TIMESTAMPS", value_name = "FORMAT")]
+    pub(crate) timestamps: Option<TimestampParser>,
+
+    /// Warn when lines arrive more than this many seconds after their
+    /// timestamp (implies `--timestamps auto` unless given).
+    #[arg(
+        long = "max-lag",
+        env = "BUTT_MAX_LAG",
+        value_name = "SECONDS",
+        value_parser = value_parser!(u64).range(1..)
+    )]
+    pub(crate) max_lag: Option<u64>,
+
+    /// Regex for progress lines; its groups capture the current count and
+    /// total (or name them `current`, `total` or `percent`). Emitted progress
+    /// lines show the rate and ETA.
+    #[arg(long, env = "BUTT_PROGRESS", value_name = "REGEX")]
+    pub(crate) progress: Option<ProgressTracker>,
+
+    /// Warn when the --progress count has not advanced for this many seconds.
+    #[arg(
+        long = "stall-seconds",
+        env = "BUTT_STALL_SECONDS",
+        value_name = "SECONDS",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "progress"
+    )]
+    pub(crate) stall_seconds: Option<u64>,
+
     /// Regex pattern to highlight.
-    #[arg(short, long)]
+    #[arg(short, long, env = "BUTT_REGEX")]
     pub(crate) regex: Option<String>,
 
     /// Make --regex matching case-insensitive.
-    #[arg(short = 'I', long = "case-insensitive", default_value_t = false)]
+    #[arg(
+        short = 'I',
+        long = "case-insensitive",
+        env = "BUTT_CASE_INSENSITIVE",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
     pub(crate) regex_case_insensitive: bool,
 
-    /// Highlight color for regex matches.
-    #[arg(short, long, value_enum, default_value = "yellow")]
-    pub(crate) color: HighlightColor,
+    /// Highlight style for regex matches: comma-separated attributes (bold,
+    /// dim, italic, underline, reverse) and colors (red, #ff8800,
+    /// color256:208, bg:yellow).
+    #[arg(
+        short,
+        long,
+        env = "BUTT_COLOR",
+        value_name = "STYLE",
+        default_value = "yellow"
+    )]
+    pub(crate) color: Style,
+
+    /// Paint only the matched text, or the whole matching line.
+    #[arg(
+        long = "highlight",
+        env = "BUTT_HIGHLIGHT",
+        value_enum,
+        default_value = "match"
+    )]
+    pub(crate) highlight: HighlightScope,
+
+    /// Styles for named capture groups of --regex, e.g. `status=red,latency=bold,cyan`.
+    #[arg(
+        long = "group-color",
+        env = "BUTT_GROUP_COLOR",
+        value_name = "NAME=STYLE,...",
+        requires = "regex"
+    )]
+    pub(crate) group_color: Option<GroupStyles>,
+
+    /// Escape sequences in input: remove them (and match on the plain text),
+    /// or keep their colors (always, or only when butt writes colors) and
+    /// escape the rest.
+    #[arg(long, env = "BUTT_ANSI", value_enum, default_value = "sanitize")]
+    pub(crate) ansi: AnsiMode,
 
     /// Poll interval in milliseconds.
-    #[arg(long = "poll-millis", default_value_t = 200)]
+    #[arg(long = "poll-millis", env = "BUTT_POLL_MILLIS", default_value_t = 200)]
     pub(crate) poll_millis: u64,
 
     /// Maximum pending in-memory bytes while assembling lines.
     #[arg(
         long = "max-buffer-bytes",
+        env = "BUTT_MAX_BUFFER_BYTES",
         default_value_t = 1_048_576,
         value_parser = parse_positive_usize
     )]
@@ -82,18 +336,68 @@ pub(crate) struct Args {
     /// Maximum bytes per line before truncation/drop.
     #[arg(
         long = "max-line-bytes",
+        env = "BUTT_MAX_LINE_BYTES",
         default_value_t = 65_536,
         value_parser = parse_positive_usize
     )]
     pub(crate) max_line_bytes: usize,
 
+    /// Character encoding of the input; lines are converted to UTF-8.
+    #[arg(
+        long = "encoding",
+        env = "BUTT_ENCODING",
+        value_enum,
+        default_value = "utf-8"
+    )]
+    pub(crate) encoding: Encoding,
+
     /// Refuse following files when PATH is a symlink.
-    #[arg(long = "no-follow-symlinks", default_value_t = false)]
+    #[arg(
+        long = "no-follow-symlinks",
+        env = "BUTT_NO_FOLLOW_SYMLINKS",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
     pub(crate) no_follow_symlinks: bool,
 
     /// Restrict followed file to this root directory (after canonicalization).
-    #[arg(long = "allowed-root")]
+    #[arg(long = "allowed-root", env = "BUTT_ALLOWED_ROOT")]
     pub(crate) allowed_root: Option<PathBuf>,
+
+    /// Disable the live status line shown when stdout is a terminal.
+    #[arg(
+        long = "no-status-bar",
+        env = "BUTT_NO_STATUS_BAR",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
+    pub(crate) no_status_bar: bool,
+
+    /// Disable keyboard controls (+/- rate, space pause, f flush, / regex, q quit).
+    #[arg(
+        long = "no-keyboard",
+        env = "BUTT_NO_KEYBOARD",
+        value_parser = BoolishValueParser::new(),
+        default_value_t = false
+    )]
+    pub(crate) no_keyboard: bool,
+
+    /// Summary printed to stderr on exit and on SIGUSR1.
+    #[arg(
+        long = "summary",
+        env = "BUTT_SUMMARY",
+        value_enum,
+        default_value = "text"
+    )]
+    pub(crate) summary: SummaryFormat,
+
+    /// Print the last N input lines when exiting.
+    #[arg(
+        long = "tail-on-exit",
+        env = "BUTT_TAIL_ON_EXIT",
+        value_parser = parse_positive_usize
+    )]
+    pub(crate) tail_on_exit: Option<usize>,
 }
 
 pub(crate) fn parse_positive_usize(input: &str) -> Result<usize, String> {
@@ -106,6 +410,28 @@ pub(crate) fn parse_positive_usize(input: &str) -> Result<usize, String> {
     Ok(parsed)
 }
 
+/// Seconds, optionally with an `s`, `m` or `h` suffix (`30`, `30s`, `5m`, `1h`).
+pub(crate) fn parse_duration(input: &str) -> Result<Duration, String> {
+    let (digits, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => input.split_at(at),
+        None => (input, "s"),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration '{input}'"))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("invalid duration '{input}': use s, m or h")),
+    };
+    match value.checked_mul(scale) {
+        Some(0) => Err("value must be >= 1s".to_string()),
+        Some(seconds) => Ok(Duration::from_secs(seconds)),
+        None => Err(format!("duration '{input}' is too large")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,6 +452,23 @@ mod tests {
         assert_eq!(without_path.path, None);
     }
 
+    #[test]
+    fn optional_features_are_off_by_default() {
+        let parsed = Args::parse_from(["butt"]);
+        assert_eq!(parsed.encoding, Encoding::Utf8);
+        assert_eq!(parsed.ansi, AnsiMode::Sanitize);
+        assert!(!parsed.no_status_bar);
+        assert!(!parsed.no_keyboard);
+        assert_eq!(parsed.summary, SummaryFormat::Text);
+        assert_eq!(parsed.tail_on_exit, None);
+        assert_eq!(parsed.listen, None);
+        assert!(!parsed.syslog);
+        assert_eq!(parsed.urgent_severity, Severity::Error);
+        assert_eq!(parsed.metrics_addr, None);
+        assert_eq!(parsed.heartbeat_file, None);
+        assert!(!parsed.sd_notify);
+    }
+
     #[test]
     fn parses_regex_case_insensitive_flag() {
         let parsed = Args::parse_from(["butt", "--regex", "err", "--case-insensitive"]);
@@ -133,6 +476,103 @@ mod tests {
         assert!(parsed.regex_case_insensitive);
     }
 
+    #[test]
+    fn parses_summary_format() {
+        let parsed = Args::parse_from(["butt", "--summary", "json"]);
+        assert_eq!(parsed.summary, SummaryFormat::Json);
+        assert!(Args::try_parse_from(["butt", "--summary", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn parses_listen_address_and_rejects_it_with_path() {
+        let parsed = Args::parse_from(["butt", "--listen", "udp://127.0.0.1:5140"]);
+        assert_eq!(
+            parsed.listen,
+            Some(ListenAddr::Udp("127.0.0.1:5140".to_string()))
+        );
+        assert!(Args::try_parse_from(["butt", "--listen", "ftp://host:1"]).is_err());
+        assert!(Args::try_parse_from(["butt", "app.log", "--listen", "tcp://:1"]).is_err());
+    }
+
+    #[test]
+    fn parses_tee_targets() {
+        let parsed = Args::parse_from([
+            "butt",
+            "--tee-all",
+            "all.log",
+            "--tee-emitted",
+            "exec:logger -t butt",
+        ]);
+        assert_eq!(parsed.tee_all, Some(PathBuf::from("all.log")));
+        assert_eq!(
+            parsed.tee_emitted,
+            Some(TeeTarget::Exec("logger -t butt".to_string()))
+        );
+        assert!(Args::try_parse_from(["butt", "--tee-emitted", "udp://"]).is_err());
+    }
+
+    #[test]
+    fn parses_hook_commands_and_limits() {
+        let defaults = Args::parse_from(["butt"]);
+        assert_eq!(defaults.on_match, None);
+        assert_eq!(defaults.hook_debounce_seconds, 10);
+        assert_eq!(defaults.hook_max_running, 4);
+        assert_eq!(defaults.hook_timeout_seconds, 30);
+
+        let parsed = Args::parse_from([
+            "butt",
+            "--on-match",
+            "notify-send \"$BUTT_HOOK_LINE\"",
+            "--hook-debounce-seconds",
+            "0",
+        ]);
+        assert_eq!(
+            parsed.on_match.as_deref(),
+            Some("notify-send \"$BUTT_HOOK_LINE\"")
+        );
+        assert_eq!(parsed.hook_debounce_seconds, 0);
+        assert!(Args::try_parse_from(["butt", "--hook-max-running", "0"]).is_err());
+        assert!(Args::try_parse_from(["butt", "--hook-timeout-seconds", "0"]).is_err());
+    }
+
+    #[test]
+    fn parses_heartbeat_options() {
+        let parsed = Args::parse_from([
+            "butt",
+            "--heartbeat-file",
+            "/run/butt/alive",
+            "--heartbeat-format",
+            "json",
+            "--sd-notify",
+        ]);
+        assert_eq!(
+            parsed.heartbeat_file,
+            Some(PathBuf::from("/run/butt/alive"))
+        );
+        assert_eq!(parsed.heartbeat_format, HeartbeatFormat::Json);
+        assert!(parsed.sd_notify);
+        assert!(Args::try_parse_from(["butt", "--heartbeat-format", "json"]).is_err());
+    }
+
+    #[test]
+    fn parses_webhook_url_and_format() {
+        let parsed = Args::parse_from([
+            "butt",
+            "--webhook",
+            "https://hooks.example.com/T0/B0",
+            "--webhook-format",
+            "slack",
+        ]);
+        assert_eq!(
+            parsed.webhook.as_deref(),
+            Some("https://hooks.example.com/T0/B0")
+        );
+        assert_eq!(parsed.webhook_format, WebhookFormat::Slack);
+        assert_eq!(parsed.webhook_batch_seconds, 5);
+        assert!(Args::try_parse_from(["butt", "--webhook", "hooks.example.com"]).is_err());
+        assert!(Args::try_parse_from(["butt", "--webhook-template", "{}"]).is_err());
+    }
+
     #[test]
     fn rejects_zero_line_seconds() {
         let parsed = Args::try_parse_from(["butt", "--line-seconds", "0"]);
@@ -151,6 +591,95 @@ mod tests {
         assert!(parsed.is_err());
     }
 
+    #[test]
+    fn rejects_zero_tail_on_exit() {
+        let parsed = Args::try_parse_from(["butt", "--tail-on-exit", "0"]);
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn parses_highlight_styles() {
+        let parsed = Args::try_parse_from([
+            "butt",
+            "--color",
+            "bold,#ff8800,bg:color256:17",
+            "--highlight",
+            "line",
+        ])
+        .expect("parse");
+        assert_eq!(parsed.color.to_string(), "bold,#ff8800,bg:color256:17");
+        assert_eq!(parsed.highlight, HighlightScope::Line);
+        assert!(Args::try_parse_from(["butt", "--color", "purple"]).is_err());
+
+        let parsed =
+            Args::try_parse_from(["butt", "-r", "(?P<s>.)", "--group-color", "s=bold,red"])
+                .expect("parse");
+        assert_eq!(
+            parsed.group_color.map(|groups| groups.to_string()),
+            Some("s=bold,red".to_string())
+        );
+        assert!(Args::try_parse_from(["butt", "--group-color", "s=red"]).is_err());
+    }
+
+    #[test]
+    fn parses_timestamp_formats() {
+        let parsed =
+            Args::try_parse_from(["butt", "--timestamps", "%d/%m/%Y %T", "--max-lag", "60"])
+                .expect("parse");
+        assert!(parsed.timestamps.is_some());
+        assert_eq!(parsed.max_lag, Some(60));
+        assert!(Args::try_parse_from(["butt", "--timestamps", "yesterday"]).is_err());
+        assert!(Args::try_parse_from(["butt", "--max-lag", "0"]).is_err());
+    }
+
+    #[test]
+    fn parses_progress_pattern_and_stall_time() {
+        let parsed = Args::try_parse_from([
+            "butt",
+            "--progress",
+            r"(\d+)/(\d+)",
+            "--stall-seconds",
+            "30",
+        ])
+        .expect("parse");
+        assert!(parsed.progress.is_some());
+        assert_eq!(parsed.stall_seconds, Some(30));
+        assert!(Args::try_parse_from(["butt", "--progress", r"\d+ done"]).is_err());
+        assert!(Args::try_parse_from(["butt", "--stall-seconds", "30"]).is_err());
+    }
+
+    #[test]
+    fn parses_repeated_expectations_and_durations() {
+        let parsed = Args::try_parse_from([
+            "butt",
+            "--expect",
+            "health ok",
+            "--expect",
+            r"tick \d+",
+            "--within",
+            "5m",
+        ])
+        .expect("parse");
+        assert_eq!(parsed.expect.len(), 2);
+        assert_eq!(parsed.within, Duration::from_secs(300));
+        assert_eq!(Args::parse_from(["butt"]).within, Duration::from_secs(60));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(Args::try_parse_from(["butt", "--expect", "("]).is_err());
+        assert!(Args::try_parse_from(["butt", "--exit-on-missed"]).is_err());
+    }
+
+    #[test]
+    fn parses_encoding_aliases() {
+        let parsed = Args::try_parse_from(["butt", "--encoding", "iso-8859-1"]).expect("parse");
+        assert_eq!(parsed.encoding, Encoding::Latin1);
+        let parsed = Args::try_parse_from(["butt", "--encoding", "utf-16le"]).expect("parse");
+        assert_eq!(parsed.encoding, Encoding::Utf16Le);
+        assert!(Args::try_parse_from(["butt", "--encoding", "ebcdic"]).is_err());
+    }
+
     #[test]
     fn rejects_zero_max_line_bytes() {
         let parsed = Args::try_parse_from(["butt", "--max-line-bytes", "0"]);
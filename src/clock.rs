@@ -0,0 +1,38 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Source of time for throttling decisions and poll waits.
+///
+/// Inject a custom clock to drive a [`Throttler`](crate::Throttler) or
+/// [`Follower`](crate::Follower) from simulated time.
+pub trait Clock {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Wait for `duration` to pass on this clock.
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall-clock time backed by [`Instant::now`] and [`thread::sleep`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration);
+    }
+}
@@ -0,0 +1,490 @@
+use crate::cli::Args;
+use clap::error::{ContextKind, ContextValue};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command, CommandFactory, FromArgMatches};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Argument ids that only make sense on the command line.
+const NON_PROFILE_IDS: [&str; 5] = ["path", "profile", "print_config", "help", "version"];
+
+/// Contents of one config file, remembered with its path for error messages.
+pub(crate) struct ConfigSource {
+    pub(crate) path: PathBuf,
+    pub(crate) text: String,
+}
+
+pub(crate) enum ResolveError {
+    Cli(clap::Error),
+    Config(String),
+}
+
+/// Parsed arguments plus enough bookkeeping to explain where each value came from.
+pub(crate) struct ResolvedArgs {
+    pub(crate) args: Args,
+    matches: ArgMatches,
+    from_profile: BTreeSet<String>,
+}
+
+/// Parse the process arguments, applying `--profile` settings from config files.
+///
+/// Exits the process on invalid arguments or config, and after `--print-config`.
+pub(crate) fn load_args() -> Args {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    match resolve(argv, read_config_sources) {
+        Ok(resolved) => {
+            if resolved.args.print_config {
+                print!("{}", resolved.render());
+                std::process::exit(0);
+            }
+            resolved.args
+        }
+        Err(ResolveError::Cli(err)) => err.exit(),
+        Err(ResolveError::Config(message)) => {
+            eprintln!("[butt] {message}");
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Config files in increasing priority: the user config, then `./.butt.toml`.
+pub(crate) fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = config_home {
+        paths.push(dir.join("butt").join("config.toml"));
+    }
+    paths.push(PathBuf::from(".butt.toml"));
+    paths
+}
+
+fn read_config_sources() -> Result<Vec<ConfigSource>, String> {
+    let mut sources = Vec::new();
+    for path in config_paths() {
+        match fs::read_to_string(&path) {
+            Ok(text) => sources.push(ConfigSource { path, text }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("cannot read config '{}': {err}", path.display())),
+        }
+    }
+    Ok(sources)
+}
+
+/// Resolve arguments with precedence CLI > env > profile > defaults.
+pub(crate) fn resolve(
+    argv: Vec<OsString>,
+    load_sources: impl FnOnce() -> Result<Vec<ConfigSource>, String>,
+) -> Result<ResolvedArgs, ResolveError> {
+    let matches =
+        Args::command().try_get_matches_from(&argv).map_err(|err| {
+            match env_var_error(&argv, &err) {
+                Some(message) => ResolveError::Config(message),
+                None => ResolveError::Cli(err),
+            }
+        })?;
+
+    let mut from_profile = BTreeSet::new();
+    let matches = match matches.get_one::<String>("profile") {
+        Some(name) => {
+            let sources = load_sources().map_err(ResolveError::Config)?;
+            let profile = find_profile(name, &sources).map_err(ResolveError::Config)?;
+            let (tokens, ids) = profile_tokens(&Args::command(), &profile, &matches)
+                .map_err(|err| ResolveError::Config(format!("profile '{name}': {err}")))?;
+            from_profile = ids;
+
+            let mut merged = Vec::with_capacity(argv.len() + tokens.len());
+            merged.extend(argv.first().cloned());
+            merged.extend(tokens);
+            merged.extend(argv.into_iter().skip(1));
+            Args::command()
+                .try_get_matches_from(merged)
+                .map_err(|err| {
+                    ResolveError::Config(format!("profile '{name}': {}", clap_message(&err)))
+                })?
+        }
+        None => matches,
+    };
+
+    let args = Args::from_arg_matches(&matches).map_err(ResolveError::Cli)?;
+    Ok(ResolvedArgs {
+        args,
+        matches,
+        from_profile,
+    })
+}
+
+/// Rephrase a clap error caused by a `BUTT_*` variable so it names the variable.
+///
+/// Clap validates environment values with the flag's own parser but reports the
+/// flag; re-parsing without that variable tells us whether it was the culprit.
+fn env_var_error(argv: &[OsString], err: &clap::Error) -> Option<String> {
+    let Some(ContextValue::String(invalid)) = err.get(ContextKind::InvalidArg) else {
+        return None;
+    };
+    let cmd = Args::command();
+    let arg = cmd.get_arguments().find(|arg| {
+        let name = match arg.get_long() {
+            Some(long) => format!("--{long}"),
+            None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+        };
+        invalid == &name || invalid.starts_with(&format!("{name} "))
+    })?;
+    let var = arg.get_env()?;
+    let value = std::env::var_os(var)?;
+
+    let id = arg.get_id().clone();
+    let without_env = Args::command()
+        .mut_arg(id, |arg| arg.env(None))
+        .try_get_matches_from(argv);
+    if without_env.is_err_and(|other| other.to_string() == err.to_string()) {
+        return None;
+    }
+    Some(format!(
+        "invalid {}='{}': {}",
+        var.to_string_lossy(),
+        value.to_string_lossy(),
+        clap_message(err)
+    ))
+}
+
+/// First line of a clap error, without the `error: ` prefix or usage text.
+fn clap_message(err: &clap::Error) -> String {
+    let rendered = err.to_string();
+    rendered
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("error: ")
+        .to_string()
+}
+
+fn find_profile(name: &str, sources: &[ConfigSource]) -> Result<Table, String> {
+    let mut merged: Option<Table> = None;
+    let mut available = BTreeSet::new();
+
+    for source in sources {
+        let table: Table = source
+            .text
+            .parse()
+            .map_err(|err| format!("invalid config '{}': {err}", source.path.display()))?;
+        let Some(profiles) = table.get("profile") else {
+            continue;
+        };
+        let Some(profiles) = profiles.as_table() else {
+            return Err(format!(
+                "invalid config '{}': 'profile' must be a table",
+                source.path.display()
+            ));
+        };
+        available.extend(profiles.keys().cloned());
+
+        if let Some(profile) = profiles.get(name) {
+            let Some(profile) = profile.as_table() else {
+                return Err(format!(
+                    "invalid config '{}': profile '{name}' must be a table",
+                    source.path.display()
+                ));
+            };
+            merged
+                .get_or_insert_with(Table::new)
+                .extend(profile.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+    }
+
+    merged.ok_or_else(|| {
+        if available.is_empty() {
+            format!("profile '{name}' not found (no profiles configured)")
+        } else {
+            let names: Vec<&str> = available.iter().map(String::as_str).collect();
+            format!(
+                "profile '{name}' not found (available: {})",
+                names.join(", ")
+            )
+        }
+    })
+}
+
+/// Turn profile settings into `--flag=value` tokens for options not already set.
+///
+/// The value shares the flag's token so one starting with `-` is not taken
+/// for another flag.
+fn profile_tokens(
+    cmd: &Command,
+    profile: &Table,
+    matches: &ArgMatches,
+) -> Result<(Vec<OsString>, BTreeSet<String>), String> {
+    let mut tokens = Vec::new();
+    let mut ids = BTreeSet::new();
+
+    for (key, value) in profile {
+        let long_name = key.replace('_', "-");
+        let Some(arg) = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long_name.as_str()))
+            .filter(|arg| !NON_PROFILE_IDS.contains(&arg.get_id().as_str()))
+        else {
+            return Err(format!("unknown setting '{key}'"));
+        };
+
+        let id = arg.get_id().as_str();
+        if matches!(
+            matches.value_source(id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let flag = OsString::from(format!("--{long_name}"));
+        let takes_value = arg.get_action().takes_values();
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for item in values {
+            match (item, takes_value) {
+                (Value::Boolean(enabled), false) => {
+                    if *enabled {
+                        tokens.push(flag.clone());
+                    }
+                }
+                (_, false) => return Err(format!("setting '{key}' must be true or false")),
+                (Value::String(text), true) => {
+                    tokens.push(OsString::from(format!("--{long_name}={text}")));
+                }
+                (Value::Integer(_) | Value::Float(_) | Value::Boolean(_), true) => {
+                    tokens.push(OsString::from(format!("--{long_name}={item}")));
+                }
+                (_, true) => return Err(format!("setting '{key}' has an unsupported value type")),
+            }
+        }
+        ids.insert(id.to_string());
+    }
+
+    Ok((tokens, ids))
+}
+
+impl ResolvedArgs {
+    /// Effective settings in config-file syntax, annotated with their source.
+    ///
+    /// Only settings given on the command line, in the environment or by a
+    /// profile are live lines, so the output works as a profile body; defaults
+    /// and command-line-only options are shown commented out.
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        match self.matches.get_one::<String>("profile") {
+            Some(name) => {
+                let _ = writeln!(out, "# effective settings (profile '{name}')");
+            }
+            None => {
+                let _ = writeln!(out, "# effective settings");
+            }
+        }
+
+        for arg in Args::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            if matches!(id, "help" | "version" | "print_config") {
+                continue;
+            }
+            let key = arg.get_long().unwrap_or(id);
+            let source = self.source_label(id);
+            let comment = if source == "default" || NON_PROFILE_IDS.contains(&id) {
+                "# "
+            } else {
+                ""
+            };
+            if !arg.get_action().takes_values() {
+                let enabled = self.matches.get_flag(id);
+                let _ = writeln!(out, "{comment}{key} = {enabled}  # {source}");
+                continue;
+            }
+            let Some(raw) = self.matches.get_raw(id) else {
+                let _ = writeln!(out, "# {key} is unset");
+                continue;
+            };
+
+            let values: Vec<String> = raw
+                .map(|value| render_value(&value.to_string_lossy()))
+                .collect();
+            let rendered = if values.len() == 1 {
+                values[0].clone()
+            } else {
+                format!("[{}]", values.join(", "))
+            };
+            let _ = writeln!(out, "{comment}{key} = {rendered}  # {source}");
+        }
+        out
+    }
+
+    fn source_label(&self, id: &str) -> &'static str {
+        if self.from_profile.contains(id) {
+            return "profile";
+        }
+        match self.matches.value_source(id) {
+            Some(ValueSource::CommandLine) => "cli",
+            Some(ValueSource::EnvVariable) => "env",
+            _ => "default",
+        }
+    }
+}
+
+fn render_value(raw: &str) -> String {
+    if raw == "true" || raw == "false" || raw.parse::<i64>().is_ok() {
+        raw.to_string()
+    } else {
+        Value::String(raw.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use butt::Pattern;
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        std::iter::once("butt")
+            .chain(args.iter().copied())
+            .map(OsString::from)
+            .collect()
+    }
+
+    fn sources(texts: &[&str]) -> Vec<ConfigSource> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(idx, text)| ConfigSource {
+                path: PathBuf::from(format!("config-{idx}.toml")),
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn resolve_with(args: &[&str], texts: &[&str]) -> Result<ResolvedArgs, String> {
+        let texts = sources(texts);
+        resolve(argv(args), || Ok(texts)).map_err(|err| match err {
+            ResolveError::Cli(err) => err.to_string(),
+            ResolveError::Config(message) => message,
+        })
+    }
+
+    const JAVA: &str = r#"
+        [profile.java-service]
+        line-seconds = 10
+        idle_seconds = 60
+        regex = "ERROR|FATAL"
+        color = "red"
+        case-insensitive = true
+    "#;
+
+    #[test]
+    fn applies_profile_settings() {
+        let resolved = resolve_with(&["--profile", "java-service"], &[JAVA]).expect("resolve");
+        assert_eq!(resolved.args.line_seconds, 10);
+        assert_eq!(resolved.args.idle_seconds, Some(60));
+        assert_eq!(resolved.args.regex.as_deref(), Some("ERROR|FATAL"));
+        assert!(resolved.args.regex_case_insensitive);
+    }
+
+    #[test]
+    fn command_line_overrides_profile() {
+        let resolved = resolve_with(
+            &["--profile", "java-service", "-n", "3", "app.log"],
+            &[JAVA],
+        )
+        .expect("resolve");
+        assert_eq!(resolved.args.line_seconds, 3);
+        assert_eq!(resolved.args.idle_seconds, Some(60));
+        assert_eq!(resolved.args.path, Some(PathBuf::from("app.log")));
+    }
+
+    #[test]
+    fn later_config_files_override_earlier_ones() {
+        let local = "[profile.java-service]\nline-seconds = 2\n";
+        let resolved =
+            resolve_with(&["--profile", "java-service"], &[JAVA, local]).expect("resolve");
+        assert_eq!(resolved.args.line_seconds, 2);
+        assert_eq!(resolved.args.regex.as_deref(), Some("ERROR|FATAL"));
+    }
+
+    #[test]
+    fn ignores_config_without_profile_flag() {
+        let resolved = resolve_with(&[], &["not = [valid"]).expect("resolve");
+        assert_eq!(resolved.args.line_seconds, 5);
+    }
+
+    #[test]
+    fn reports_unknown_profile_and_settings() {
+        let err = resolve_with(&["--profile", "missing"], &[JAVA]).err();
+        assert_eq!(
+            err.as_deref(),
+            Some("profile 'missing' not found (available: java-service)")
+        );
+
+        let err = resolve_with(&["--profile", "p"], &["[profile.p]\nbogus = 1\n"]).err();
+        assert_eq!(err.as_deref(), Some("profile 'p': unknown setting 'bogus'"));
+    }
+
+    #[test]
+    fn validates_profile_values_with_flag_parsers() {
+        let err = resolve_with(&["--profile", "p"], &["[profile.p]\nline-seconds = 0\n"])
+            .err()
+            .expect("zero line-seconds should fail");
+        assert!(err.contains("line-seconds"), "{err}");
+    }
+
+    #[test]
+    fn renders_effective_settings_with_sources() {
+        let resolved =
+            resolve_with(&["--profile", "java-service", "-n", "3"], &[JAVA]).expect("resolve");
+        let rendered = resolved.render();
+        assert!(rendered.contains("# effective settings (profile 'java-service')"));
+        assert!(rendered.contains("line-seconds = 3  # cli"));
+        assert!(rendered.contains("regex = \"ERROR|FATAL\"  # profile"));
+        assert!(rendered.contains("poll-millis = 200  # default"));
+        assert!(rendered.contains("# allowed-root is unset"));
+    }
+
+    #[test]
+    fn printed_config_loads_back_as_a_profile() {
+        let resolved = resolve_with(
+            &[
+                "-n",
+                "3",
+                "--regex=-->",
+                "--expect",
+                "a",
+                "--expect",
+                "b",
+                "--syslog",
+                "app.log",
+            ],
+            &[],
+        )
+        .expect("resolve");
+        let dump = format!("[profile.dump]\n{}", resolved.render());
+        assert!(dump.contains("\n# heartbeat-format = \"touch\"  # default"));
+        assert!(dump.contains("\n# path = \"app.log\"  # cli"));
+
+        let loaded = resolve_with(&["--profile", "dump"], &[&dump]).expect("load dump");
+        assert_eq!(loaded.args.line_seconds, 3);
+        assert_eq!(loaded.args.regex.as_deref(), Some("-->"));
+        assert_eq!(
+            loaded
+                .args
+                .expect
+                .iter()
+                .map(Pattern::as_str)
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert!(loaded.args.syslog);
+        assert_eq!(loaded.args.path, None);
+    }
+}
@@ -1,17 +1,22 @@
-use crate::cli::{Args, HighlightColor};
-use crate::limits::{append_with_buffer_cap, collect_complete_lines, start_stdin_reader};
-use crate::output::decorate_line;
-use regex::Regex;
+use crate::clock::{Clock, SystemClock};
+use crate::limits::{LineAssembler, Lines, start_chunk_reader};
+use crate::text::Encoding;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{self, Receiver, SyncSender};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
+/// Size of each read from a file or stdin.
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Reads per poll before handing a batch back, so a fast writer cannot starve ticks.
+const READ_AHEAD_CHUNKS: usize = 64;
+
 #[cfg(unix)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct FileIdentity {
@@ -27,82 +32,411 @@ fn file_identity(metadata: &fs::Metadata) -> FileIdentity {
     }
 }
 
-struct EmitState {
-    next_line_emit: Instant,
-    next_idle_emit: Option<Instant>,
-    last_output: Instant,
-    latest_line: Option<String>,
+/// Limits and safety checks applied while following a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowOptions {
+    pub max_line_bytes: usize,
+    pub max_buffer_bytes: usize,
+    /// Refuse to open the file when its path is a symlink.
+    pub no_follow_symlinks: bool,
+    /// Only open files under this directory; it should already be canonical.
+    pub allowed_root: Option<PathBuf>,
+    /// How the source's bytes are decoded; see [`Encoding`].
+    pub encoding: Encoding,
 }
 
-impl EmitState {
-    fn new(args: &Args) -> Self {
-        let now = Instant::now();
+impl Default for FollowOptions {
+    fn default() -> Self {
         Self {
-            next_line_emit: now + Duration::from_secs(args.line_seconds),
-            next_idle_emit: args
-                .idle_seconds
-                .map(|idle| now + Duration::from_secs(idle)),
-            last_output: now,
-            latest_line: None,
+            max_line_bytes: 65_536,
+            max_buffer_bytes: 1_048_576,
+            no_follow_symlinks: false,
+            allowed_root: None,
+            encoding: Encoding::Utf8,
         }
     }
+}
+
+impl FollowOptions {
+    /// A line assembler with these limits and encoding.
+    pub(crate) fn assembler(&self) -> LineAssembler {
+        LineAssembler::new(self.max_line_bytes, self.max_buffer_bytes).with_encoding(self.encoding)
+    }
+}
+
+/// Something worth telling the user about while following a source.
+#[derive(Debug)]
+pub enum Notice {
+    /// The file cannot be opened yet.
+    Waiting {
+        path: PathBuf,
+        error: io::Error,
+    },
+    BufferOverflow {
+        max_buffer_bytes: usize,
+    },
+    LinesTruncated {
+        count: usize,
+        max_line_bytes: usize,
+    },
+    ReadError(io::Error),
+    /// The file shrank, so it is being read again from the start.
+    Truncated,
+    /// The path now points at a different file, which was opened from the start.
+    Rotated {
+        path: PathBuf,
+    },
+    ReopenBlocked(io::Error),
+    ReopenFailed(io::Error),
+    /// A socket peer connected to a [`Listener`](crate::Listener).
+    Connected {
+        peer: String,
+    },
+    Disconnected {
+        peer: String,
+    },
+    /// A connection was closed at once because too many were open.
+    Refused {
+        peer: String,
+        max_connections: usize,
+    },
+}
+
+impl fmt::Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Waiting { path, error } => {
+                write!(f, "waiting for file '{}' ({error})", path.display())
+            }
+            Self::BufferOverflow { max_buffer_bytes } => write!(
+                f,
+                "buffer exceeded --max-buffer-bytes={max_buffer_bytes}, dropping buffered data"
+            ),
+            Self::LinesTruncated {
+                count,
+                max_line_bytes,
+            } => write!(
+                f,
+                "truncated/dropped {count} oversized line fragment(s) (max-line-bytes={max_line_bytes})"
+            ),
+            Self::ReadError(err) => write!(f, "read error: {err}"),
+            Self::Truncated => write!(f, "file truncated, reading from the start"),
+            Self::Rotated { path } => write!(
+                f,
+                "reopened '{}' after rotation/replacement",
+                path.display()
+            ),
+            Self::ReopenBlocked(err) => write!(f, "reopen blocked: {err}"),
+            Self::ReopenFailed(err) => write!(f, "reopen failed: {err}"),
+            Self::Connected { peer } => write!(f, "connection from {peer}"),
+            Self::Disconnected { peer } => write!(f, "{peer} disconnected"),
+            Self::Refused {
+                peer,
+                max_connections,
+            } => write!(
+                f,
+                "refused connection from {peer}: {max_connections} connections already open"
+            ),
+        }
+    }
+}
+
+/// Input gathered by one [`LineSource::poll`].
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub lines: Lines,
+    /// Raw bytes read, before line splitting or truncation.
+    pub bytes: usize,
+    pub notices: Vec<Notice>,
+    /// Which sender each run of lines came from, as the index of its first
+    /// line and the peer's name, for sources such as a
+    /// [`Listener`](crate::Listener) that have several. Empty otherwise.
+    pub peers: Vec<(usize, String)>,
+}
+
+impl Batch {
+    /// The sender of line `index`, if the source names one.
+    pub fn peer(&self, index: usize) -> Option<&str> {
+        let run = self.peers.partition_point(|(start, _)| *start <= index);
+        run.checked_sub(1).map(|run| self.peers[run].1.as_str())
+    }
 
-    fn mark_output_emitted(&mut self, now: Instant, args: &Args) {
-        self.last_output = now;
-        self.next_idle_emit = args
-            .idle_seconds
-            .map(|idle| now + Duration::from_secs(idle));
+    /// Feed `chunk` to `assembler`, noting any limits that were hit.
+    pub(crate) fn assemble(assembler: &mut LineAssembler, chunk: &[u8]) -> Self {
+        let mut batch = Self::default();
+        batch.absorb(assembler, chunk);
+        batch
     }
 
-    fn observe_input(
-        &mut self,
-        line: String,
-        args: &Args,
-        regex: Option<&Regex>,
-        color: &HighlightColor,
-        colors_enabled: bool,
-    ) {
-        let now = Instant::now();
-
-        if let Some(rgx) = regex
-            && rgx.is_match(&line)
+    /// Like [`assemble`](Self::assemble), adding to this batch.
+    pub(crate) fn absorb(&mut self, assembler: &mut LineAssembler, chunk: &[u8]) {
+        let lines = &mut self.lines;
+        lines.reserve(assembler.pending_len() + chunk.len());
+        let (buffer_overflowed, dropped_or_truncated) =
+            assembler.push_with(chunk, |line| lines.push(line));
+        self.bytes += chunk.len();
+        let max_buffer_bytes = assembler.max_buffer_bytes();
+        if buffer_overflowed
+            && !self
+                .notices
+                .iter()
+                .any(|notice| matches!(notice, Notice::BufferOverflow { .. }))
         {
-            println!("{}", decorate_line(&line, regex, color, colors_enabled));
-            let _ = io::stdout().flush();
-            self.mark_output_emitted(now, args);
-            self.latest_line = None;
-            self.next_line_emit = now + Duration::from_secs(args.line_seconds);
-            return;
+            self.notices
+                .push(Notice::BufferOverflow { max_buffer_bytes });
+        }
+        if dropped_or_truncated > 0 {
+            let existing = self.notices.iter_mut().find_map(|notice| match notice {
+                Notice::LinesTruncated { count, .. } => Some(count),
+                _ => None,
+            });
+            match existing {
+                Some(count) => *count += dropped_or_truncated,
+                None => self.notices.push(Notice::LinesTruncated {
+                    count: dropped_or_truncated,
+                    max_line_bytes: assembler.max_line_bytes(),
+                }),
+            }
+        }
+    }
+}
+
+/// A stream of lines that can be polled without blocking forever.
+pub trait LineSource {
+    /// Wait up to about `wait` for input. `Ok(None)` means the source has ended.
+    fn poll(&mut self, wait: Duration) -> io::Result<Option<Batch>>;
+
+    /// Short description of the source, such as a path or `stdin`.
+    fn label(&self) -> String;
+}
+
+/// Follows a file like `tail -F`, or reads lines from stdin or another reader.
+pub struct Follower<C: Clock = SystemClock> {
+    kind: Kind,
+    label: String,
+    clock: C,
+}
+
+enum Kind {
+    File(FileState),
+    Reader {
+        rx: Receiver<Vec<u8>>,
+        /// Returns assembled chunks to the reader thread for reuse.
+        recycle: SyncSender<Vec<u8>>,
+        assembler: LineAssembler,
+    },
+}
+
+pub(crate) struct FileState {
+    path: PathBuf,
+    options: FollowOptions,
+    file: Option<File>,
+    #[cfg(unix)]
+    opened_id: Option<FileIdentity>,
+    assembler: LineAssembler,
+    chunk: Vec<u8>,
+    /// The last step stopped at the read-ahead limit rather than end of file.
+    backlog: bool,
+}
+
+impl Follower<SystemClock> {
+    /// Follow `path` from its current end, reopening it after rotation.
+    pub fn file(path: impl Into<PathBuf>, options: FollowOptions) -> Self {
+        let path = path.into();
+        Self {
+            label: path.display().to_string(),
+            kind: Kind::File(FileState::new(path, options)),
+            clock: SystemClock,
         }
+    }
+
+    pub fn stdin(options: FollowOptions) -> Self {
+        Self::reader(
+            io::BufReader::with_capacity(READ_CHUNK_BYTES, io::stdin()),
+            "stdin",
+            options,
+        )
+    }
+
+    /// Read lines from `reader` on a background thread until it reaches EOF.
+    pub fn reader<R: BufRead + Send + 'static>(
+        reader: R,
+        label: impl Into<String>,
+        options: FollowOptions,
+    ) -> Self {
+        let (tx, rx) = mpsc::sync_channel(READ_AHEAD_CHUNKS);
+        let (recycle, recycled) = mpsc::sync_channel(READ_AHEAD_CHUNKS + 1);
+        start_chunk_reader(reader, READ_CHUNK_BYTES, tx, recycled);
+        Self {
+            kind: Kind::Reader {
+                rx,
+                recycle,
+                assembler: options.assembler(),
+            },
+            label: label.into(),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<C: Clock> Follower<C> {
+    /// Use `clock` for poll waits between file reads.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> Follower<C2> {
+        Follower {
+            kind: self.kind,
+            label: self.label,
+            clock,
+        }
+    }
+}
+
+impl<C: Clock> LineSource for Follower<C> {
+    fn poll(&mut self, wait: Duration) -> io::Result<Option<Batch>> {
+        match &mut self.kind {
+            Kind::File(state) => {
+                let batch = state.step()?;
+                if !state.backlog {
+                    self.clock.sleep(wait);
+                }
+                Ok(Some(batch))
+            }
+            Kind::Reader {
+                rx,
+                recycle,
+                assembler,
+            } => poll_reader(rx, recycle, assembler, wait),
+        }
+    }
 
-        self.latest_line = Some(line);
+    fn label(&self) -> String {
+        self.label.clone()
     }
+}
 
-    fn maybe_emit(&mut self, args: &Args, regex: Option<&Regex>, colors_enabled: bool) {
-        let now = Instant::now();
-        let line_interval = Duration::from_secs(args.line_seconds);
-        if now >= self.next_line_emit {
-            if let Some(line) = self.latest_line.take() {
-                println!(
-                    "{}",
-                    decorate_line(&line, regex, &args.color, colors_enabled)
-                );
-                let _ = io::stdout().flush();
-                self.mark_output_emitted(now, args);
+fn poll_reader(
+    rx: &Receiver<Vec<u8>>,
+    recycle: &SyncSender<Vec<u8>>,
+    assembler: &mut LineAssembler,
+    wait: Duration,
+) -> io::Result<Option<Batch>> {
+    let first = match rx.recv_timeout(wait) {
+        Ok(chunk) => chunk,
+        Err(mpsc::RecvTimeoutError::Timeout) => return Ok(Some(Batch::default())),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            return Ok(assembler.finish().map(|line| Batch {
+                lines: Lines::from_iter([line]),
+                ..Batch::default()
+            }));
+        }
+    };
+
+    let mut batch = Batch::default();
+    for chunk in std::iter::once(first).chain(rx.try_iter().take(READ_AHEAD_CHUNKS)) {
+        batch.absorb(assembler, &chunk);
+        // A full pool just means this chunk is freed instead.
+        let _ = recycle.try_send(chunk);
+    }
+    Ok(Some(batch))
+}
+
+impl FileState {
+    pub(crate) fn new(path: PathBuf, options: FollowOptions) -> Self {
+        let assembler = options.assembler();
+        Self {
+            path,
+            options,
+            file: None,
+            #[cfg(unix)]
+            opened_id: None,
+            assembler,
+            chunk: vec![0; READ_CHUNK_BYTES],
+            backlog: false,
+        }
+    }
+
+    /// Read whatever is available and check for rotation, without waiting.
+    pub(crate) fn step(&mut self) -> io::Result<Batch> {
+        let mut batch = Batch::default();
+        self.backlog = false;
+        let allowed_root = self.options.allowed_root.as_deref();
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let opened = validate_follow_target(
+                    &self.path,
+                    self.options.no_follow_symlinks,
+                    allowed_root,
+                )
+                .and_then(|()| open_at_end(&self.path));
+                match opened {
+                    Ok(file) => {
+                        #[cfg(unix)]
+                        {
+                            self.opened_id =
+                                fs::metadata(&self.path).ok().map(|m| file_identity(&m));
+                        }
+                        self.file.insert(file)
+                    }
+                    Err(error) => {
+                        batch.notices.push(Notice::Waiting {
+                            path: self.path.clone(),
+                            error,
+                        });
+                        return Ok(batch);
+                    }
+                }
+            }
+        };
+
+        for reads in 1..=READ_AHEAD_CHUNKS {
+            match file.read(&mut self.chunk) {
+                Ok(0) => break,
+                Ok(n) => batch.absorb(&mut self.assembler, &self.chunk[..n]),
+                Err(err) => {
+                    batch.notices.push(Notice::ReadError(err));
+                    break;
+                }
             }
-            self.next_line_emit = now + line_interval;
+            self.backlog = reads == READ_AHEAD_CHUNKS;
         }
 
-        if let Some(idle_seconds) = args.idle_seconds {
-            let idle_interval = Duration::from_secs(idle_seconds);
-            if now.duration_since(self.last_output) >= idle_interval
-                && self.next_idle_emit.is_some_and(|next| now >= next)
-            {
-                println!("[no output for {} seconds]", idle_seconds);
-                let _ = io::stdout().flush();
-                self.next_idle_emit = Some(now + idle_interval);
+        let pos = file.stream_position()?;
+        let len = file.metadata()?.len();
+        if len < pos {
+            file.seek(SeekFrom::Start(0))?;
+            self.assembler.clear();
+            batch.notices.push(Notice::Truncated);
+        }
+
+        // A missing path usually means rotation is in progress; check again next time.
+        #[cfg(unix)]
+        if let Ok(meta) = fs::metadata(&self.path) {
+            let current_id = file_identity(&meta);
+            if self.opened_id != Some(current_id) {
+                if let Err(err) = validate_follow_target(
+                    &self.path,
+                    self.options.no_follow_symlinks,
+                    allowed_root,
+                ) {
+                    batch.notices.push(Notice::ReopenBlocked(err));
+                    return Ok(batch);
+                }
+                match open_from_start(&self.path) {
+                    Ok(new_file) => {
+                        self.file = Some(new_file);
+                        self.assembler.clear();
+                        self.opened_id = Some(current_id);
+                        batch.notices.push(Notice::Rotated {
+                            path: self.path.clone(),
+                        });
+                    }
+                    Err(err) => batch.notices.push(Notice::ReopenFailed(err)),
+                }
             }
         }
+
+        Ok(batch)
     }
 }
 
@@ -148,151 +482,10 @@ fn validate_follow_target(
     Ok(())
 }
 
-pub(crate) fn follow_file(
-    args: &Args,
-    path: &Path,
-    regex: Option<&Regex>,
-    colors_enabled: bool,
-    allowed_root: Option<&Path>,
-) -> io::Result<()> {
-    let poll = Duration::from_millis(args.poll_millis);
-    let mut emit = EmitState::new(args);
-
-    let mut file = loop {
-        if let Err(err) = validate_follow_target(path, args.no_follow_symlinks, allowed_root) {
-            eprintln!("[butt] waiting for file '{}' ({err})", path.display());
-            thread::sleep(poll);
-            continue;
-        }
-        match open_at_end(path) {
-            Ok(f) => break f,
-            Err(err) => {
-                eprintln!("[butt] waiting for file '{}' ({err})", path.display());
-                thread::sleep(poll);
-            }
-        }
-    };
-
-    #[cfg(unix)]
-    let mut opened_id = fs::metadata(path).ok().map(|m| file_identity(&m));
-
-    let mut pending = Vec::new();
-
-    loop {
-        emit.maybe_emit(args, regex, colors_enabled);
-
-        let mut chunk = [0_u8; 8192];
-        match file.read(&mut chunk) {
-            Ok(0) => {}
-            Ok(n) => {
-                if append_with_buffer_cap(&mut pending, &chunk[..n], args.max_buffer_bytes) {
-                    eprintln!(
-                        "[butt] buffer exceeded --max-buffer-bytes={}, dropping buffered data",
-                        args.max_buffer_bytes
-                    );
-                    let _ = io::stderr().flush();
-                }
-
-                let (lines, dropped_or_truncated) =
-                    collect_complete_lines(&mut pending, args.max_line_bytes);
-                if dropped_or_truncated > 0 {
-                    eprintln!(
-                        "[butt] truncated/dropped {} oversized line fragment(s) (max-line-bytes={})",
-                        dropped_or_truncated, args.max_line_bytes
-                    );
-                    let _ = io::stderr().flush();
-                }
-
-                for line in lines {
-                    emit.observe_input(line, args, regex, &args.color, colors_enabled);
-                }
-            }
-            Err(err) => {
-                eprintln!("[butt] read error: {err}");
-                let _ = io::stderr().flush();
-                thread::sleep(poll);
-            }
-        }
-
-        let pos = file.stream_position()?;
-        let len = file.metadata()?.len();
-        if len < pos {
-            file.seek(SeekFrom::Start(0))?;
-            pending.clear();
-        }
-
-        match fs::metadata(path) {
-            Ok(meta) => {
-                #[cfg(unix)]
-                {
-                    let current_id = file_identity(&meta);
-                    if opened_id != Some(current_id) {
-                        if let Err(err) =
-                            validate_follow_target(path, args.no_follow_symlinks, allowed_root)
-                        {
-                            eprintln!("[butt] reopen blocked: {err}");
-                            let _ = io::stderr().flush();
-                            thread::sleep(poll);
-                            continue;
-                        }
-                        match open_from_start(path) {
-                            Ok(new_file) => {
-                                file = new_file;
-                                pending.clear();
-                                opened_id = Some(current_id);
-                                eprintln!(
-                                    "[butt] reopened '{}' after rotation/replacement",
-                                    path.display()
-                                );
-                                let _ = io::stderr().flush();
-                            }
-                            Err(err) => {
-                                eprintln!("[butt] reopen failed: {err}");
-                                let _ = io::stderr().flush();
-                            }
-                        }
-                    }
-                }
-            }
-            Err(_) => {
-                thread::sleep(poll);
-                continue;
-            }
-        }
-
-        thread::sleep(poll);
-    }
-}
-
-pub(crate) fn follow_stdin(
-    args: &Args,
-    regex: Option<&Regex>,
-    colors_enabled: bool,
-) -> io::Result<()> {
-    let poll = Duration::from_millis(args.poll_millis);
-    let (tx, rx): (SyncSender<String>, Receiver<String>) = mpsc::sync_channel(1024);
-
-    let _reader_handle = start_stdin_reader(tx, args.max_line_bytes, args.max_buffer_bytes);
-
-    let mut emit = EmitState::new(args);
-
-    loop {
-        emit.maybe_emit(args, regex, colors_enabled);
-
-        match rx.recv_timeout(poll) {
-            Ok(line) => {
-                let line = line.trim_end_matches(['\n', '\r']).to_string();
-                emit.observe_input(line, args, regex, &args.color, colors_enabled);
-            }
-            Err(mpsc::RecvTimeoutError::Timeout) => {}
-            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
     use tempfile::tempdir;
 
     #[cfg(unix)]
@@ -321,4 +514,68 @@ mod tests {
         let result = validate_follow_target(&outside_file, false, Some(root.path()));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn file_follower_reads_appended_lines_and_notices_truncation() {
+        let tmp = tempdir().expect("tempdir");
+        let path = tmp.path().join("app.log");
+        fs::write(&path, "old line\n").expect("write log");
+
+        let mut follower = Follower::file(&path, FollowOptions::default());
+        let first = follower.poll(Duration::ZERO).expect("poll").expect("batch");
+        assert!(first.lines.is_empty(), "existing content is skipped");
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("open for append");
+        writeln!(file, "new line").expect("append");
+        let second = follower.poll(Duration::ZERO).expect("poll").expect("batch");
+        assert_eq!(second.lines.to_strings(), vec!["new line".to_string()]);
+        assert_eq!(second.bytes, 9);
+
+        fs::write(&path, "").expect("truncate");
+        let third = follower.poll(Duration::ZERO).expect("poll").expect("batch");
+        assert!(matches!(third.notices.as_slice(), [Notice::Truncated]));
+    }
+
+    #[test]
+    fn reader_follower_ends_at_eof() {
+        let input = io::Cursor::new(b"one\r\ntwo\n".to_vec());
+        let mut follower = Follower::reader(input, "test", FollowOptions::default());
+        let mut lines = Vec::new();
+        while let Some(batch) = follower.poll(Duration::from_secs(1)).expect("poll") {
+            lines.extend(batch.lines.to_strings());
+        }
+        assert_eq!(lines, vec!["one".to_string(), "two".to_string()]);
+        assert_eq!(follower.label(), "test");
+    }
+
+    #[test]
+    fn reader_follower_reports_truncated_lines_once_per_batch() {
+        let options = FollowOptions {
+            max_line_bytes: 4,
+            ..FollowOptions::default()
+        };
+        let input = io::Cursor::new(
+            b"abcdefgh
+ok
+ijklmnop
+"
+            .to_vec(),
+        );
+        let mut follower = Follower::reader(input, "test", options);
+        let batch = follower
+            .poll(Duration::from_secs(1))
+            .expect("poll")
+            .expect("batch");
+        assert_eq!(batch.lines.to_strings(), vec!["abcd", "ok", "ijkl"]);
+        assert!(matches!(
+            batch.notices.as_slice(),
+            [Notice::LinesTruncated {
+                count: 2,
+                max_line_bytes: 4
+            }]
+        ));
+    }
 }
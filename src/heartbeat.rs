@@ -0,0 +1,265 @@
+use crate::stats::StreamStats;
+use clap::ValueEnum;
+use serde_json::json;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Rewrites of the heartbeat file are at least this far apart.
+const MIN_BEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the heartbeat file contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HeartbeatFormat {
+    /// Leave the contents alone and update the modification time.
+    Touch,
+    /// Replace the file with a JSON status object.
+    Json,
+}
+
+/// A file whose modification time shows when input last arrived, for
+/// liveness probes that cannot read butt's output.
+#[derive(Debug)]
+pub struct HeartbeatFile {
+    path: PathBuf,
+    format: HeartbeatFormat,
+    last_beat: Option<Instant>,
+}
+
+impl HeartbeatFile {
+    pub fn new(path: impl Into<PathBuf>, format: HeartbeatFormat) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            last_beat: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record that input arrived. Beats within a second of the previous one
+    /// are skipped; returns whether the file was written.
+    pub fn beat(&mut self, stats: &StreamStats, source: &str, now: Instant) -> io::Result<bool> {
+        if self
+            .last_beat
+            .is_some_and(|last| now.saturating_duration_since(last) < MIN_BEAT_INTERVAL)
+        {
+            return Ok(false);
+        }
+        self.last_beat = Some(now);
+        match self.format {
+            HeartbeatFormat::Touch => touch(&self.path)?,
+            HeartbeatFormat::Json => self.write_status(stats, source)?,
+        }
+        Ok(true)
+    }
+
+    /// Write to a temporary file and rename it, so readers never see a partial status.
+    fn write_status(&self, stats: &StreamStats, source: &str) -> io::Result<()> {
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let status = json!({
+            "pid": std::process::id(),
+            "source": source,
+            "last_input_unix": unix_now,
+            "lines": stats.lines,
+            "bytes": stats.bytes,
+            "emitted": stats.emitted,
+            "urgent": stats.urgent,
+        });
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, format!("{status}\n"))?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Sends systemd `sd_notify` messages: `READY=1` on start, `STATUS=` on idle
+/// transitions, and `WATCHDOG=1` pings that pause while the stream is idle so
+/// systemd's watchdog restarts a service whose input has stalled.
+#[derive(Debug)]
+pub struct Watchdog {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    /// Half of `WATCHDOG_USEC`, or `None` when the watchdog is not enabled.
+    interval: Option<Duration>,
+    last_ping: Option<Instant>,
+    idle: bool,
+}
+
+impl Watchdog {
+    /// Connect to `$NOTIFY_SOCKET`, or `None` when butt is not run by systemd.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let for_us = std::env::var("WATCHDOG_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            .is_none_or(|pid| pid == std::process::id());
+        let interval = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| for_us && *usec > 0)
+            .map(|usec| Duration::from_micros(usec / 2));
+        Self::connect(Path::new(&socket), interval).map(Some)
+    }
+
+    /// Connect to a notify socket, pinging every `interval` if given. A path
+    /// starting with `@` names a Linux abstract socket.
+    #[cfg(unix)]
+    pub fn connect(path: &Path, interval: Option<Duration>) -> io::Result<Self> {
+        use std::os::unix::net::UnixDatagram;
+
+        let socket = UnixDatagram::unbound()?;
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&addr)?;
+            }
+            _ => socket.connect(path)?,
+        }
+        Ok(Self {
+            socket,
+            interval,
+            last_ping: None,
+            idle: false,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_path: &Path, _interval: Option<Duration>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sd_notify is not supported on this platform",
+        ))
+    }
+
+    /// Send one notification, e.g. `READY=1` or `STATUS=following app.log`.
+    pub fn notify(&self, message: &str) -> io::Result<()> {
+        #[cfg(unix)]
+        self.socket.send(message.as_bytes())?;
+        #[cfg(not(unix))]
+        let _ = message;
+        Ok(())
+    }
+
+    /// Ping the watchdog when due, unless the stream is `idle`.
+    pub fn tick(&mut self, idle: bool, now: Instant) -> io::Result<()> {
+        if idle != self.idle {
+            self.idle = idle;
+            self.notify(if idle {
+                "STATUS=input idle, watchdog pings paused"
+            } else {
+                "STATUS=receiving input"
+            })?;
+        }
+        let Some(interval) = self.interval else {
+            return Ok(());
+        };
+        if idle
+            || self
+                .last_ping
+                .is_some_and(|last| now.saturating_duration_since(last) < interval)
+        {
+            return Ok(());
+        }
+        self.last_ping = Some(now);
+        self.notify("WATCHDOG=1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_creates_file_and_rate_limits_updates() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("alive");
+        let stats = StreamStats::new(Instant::now(), None);
+        let mut heartbeat = HeartbeatFile::new(&path, HeartbeatFormat::Touch);
+
+        let start = Instant::now();
+        assert!(heartbeat.beat(&stats, "stdin", start).expect("beat"));
+        assert_eq!(fs::read_to_string(&path).expect("read"), "");
+        assert!(!heartbeat.beat(&stats, "stdin", start).expect("beat"));
+        assert!(
+            heartbeat
+                .beat(&stats, "stdin", start + Duration::from_secs(1))
+                .expect("beat")
+        );
+    }
+
+    #[test]
+    fn json_status_replaces_file_contents() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("status.json");
+        fs::write(&path, "old contents that are longer than the status").expect("seed");
+        let now = Instant::now();
+        let mut stats = StreamStats::new(now, None);
+        stats.record_line(now);
+
+        let mut heartbeat = HeartbeatFile::new(&path, HeartbeatFormat::Json);
+        heartbeat.beat(&stats, "app.log", now).expect("beat");
+        let status: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).expect("read")).expect("json");
+        assert_eq!(status["source"], "app.log");
+        assert_eq!(status["lines"], 1);
+        assert_eq!(status["pid"], std::process::id());
+        assert!(status["last_input_unix"].as_f64().expect("timestamp") > 0.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watchdog_pings_on_interval_and_pauses_while_idle() {
+        use std::os::unix::net::UnixDatagram;
+
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).expect("bind");
+        receiver.set_nonblocking(true).expect("nonblocking");
+        let received = || {
+            let mut messages = Vec::new();
+            let mut buf = [0_u8; 256];
+            while let Ok(n) = receiver.recv(&mut buf) {
+                messages.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+            }
+            messages
+        };
+
+        let mut watchdog = Watchdog::connect(&path, Some(Duration::from_secs(5))).expect("connect");
+        let start = Instant::now();
+        watchdog.tick(false, start).expect("tick");
+        watchdog
+            .tick(false, start + Duration::from_secs(1))
+            .expect("tick");
+        assert_eq!(received(), vec!["WATCHDOG=1"]);
+
+        watchdog
+            .tick(true, start + Duration::from_secs(10))
+            .expect("tick");
+        assert_eq!(received(), vec!["STATUS=input idle, watchdog pings paused"]);
+
+        watchdog
+            .tick(false, start + Duration::from_secs(11))
+            .expect("tick");
+        assert_eq!(received(), vec!["STATUS=receiving input", "WATCHDOG=1"]);
+    }
+}
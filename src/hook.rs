@@ -0,0 +1,430 @@
+use crate::pattern::Pattern;
+use std::fmt;
+use std::io;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+/// What made a hook run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// An urgent line (a `--regex` match or a severe syslog message).
+    Match,
+    /// An idle notice.
+    Idle,
+    /// A missed notice for an `--expect` pattern.
+    Missed,
+}
+
+impl HookKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Match => "match",
+            Self::Idle => "idle",
+            Self::Missed => "missed",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Self::Match => 0,
+            Self::Idle => 1,
+            Self::Missed => 2,
+        }
+    }
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Limits that keep a burst of events from turning into a burst of processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookOptions {
+    /// Minimum time between two runs of the same hook; triggers in between are skipped.
+    pub debounce: Duration,
+    /// Hooks allowed to run at once, across all kinds.
+    pub max_running: usize,
+    /// A hook still running after this long is killed.
+    pub timeout: Duration,
+}
+
+impl Default for HookOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_secs(10),
+            max_running: 4,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A hook that did not run or did not succeed.
+#[derive(Debug)]
+pub enum HookReport {
+    /// `max_running` hooks were already running.
+    Busy {
+        kind: HookKind,
+        max_running: usize,
+    },
+    SpawnFailed {
+        kind: HookKind,
+        error: io::Error,
+    },
+    Failed {
+        kind: HookKind,
+        status: ExitStatus,
+    },
+    TimedOut {
+        kind: HookKind,
+        timeout: Duration,
+    },
+}
+
+impl fmt::Display for HookReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Busy { kind, max_running } => write!(
+                f,
+                "skipped --on-{kind} hook: {max_running} hook(s) already running"
+            ),
+            Self::SpawnFailed { kind, error } => {
+                write!(f, "--on-{kind} hook could not start: {error}")
+            }
+            Self::Failed { kind, status } => write!(f, "--on-{kind} hook failed: {status}"),
+            Self::TimedOut { kind, timeout } => write!(
+                f,
+                "--on-{kind} hook killed after {} seconds",
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+struct Running {
+    kind: HookKind,
+    child: Child,
+    started: Instant,
+}
+
+/// Runs `--on-match` / `--on-idle` / `--on-missed` shell commands without
+/// waiting for them.
+///
+/// Event details are passed as `BUTT_*` environment variables (see
+/// [`match_vars`], [`idle_vars`] and [`missed_vars`]). Call
+/// [`poll`](Self::poll) regularly to reap finished hooks, kill ones that ran
+/// too long and collect failures.
+/// Hooks inherit stderr; their stdin and stdout are closed so they cannot
+/// interleave with butt's own output.
+pub struct Hooks {
+    options: HookOptions,
+    commands: [Option<String>; 3],
+    last_started: [Option<Instant>; 3],
+    debounced: u64,
+    running: Vec<Running>,
+}
+
+impl Hooks {
+    pub fn new(options: HookOptions) -> Self {
+        Self {
+            options,
+            commands: [None, None, None],
+            last_started: [None, None, None],
+            debounced: 0,
+            running: Vec::new(),
+        }
+    }
+
+    /// Set the shell command run for `kind`, or `None` to run nothing.
+    pub fn with_command(mut self, kind: HookKind, command: Option<String>) -> Self {
+        self.commands[kind.index()] = command;
+        self
+    }
+
+    pub fn has_command(&self, kind: HookKind) -> bool {
+        self.commands[kind.index()].is_some()
+    }
+
+    /// Triggers skipped because the same hook ran within the debounce window.
+    pub fn debounced(&self) -> u64 {
+        self.debounced
+    }
+
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Start the hook for `kind` with `vars` in its environment, unless it is
+    /// debounced, the concurrency limit is reached, or no command is set.
+    pub fn trigger(
+        &mut self,
+        kind: HookKind,
+        vars: &[(String, String)],
+        now: Instant,
+    ) -> Option<HookReport> {
+        let command = self.commands[kind.index()].as_ref()?;
+        if let Some(last) = self.last_started[kind.index()]
+            && now.saturating_duration_since(last) < self.options.debounce
+        {
+            self.debounced += 1;
+            return None;
+        }
+        if self.running.len() >= self.options.max_running {
+            return Some(HookReport::Busy {
+                kind,
+                max_running: self.options.max_running,
+            });
+        }
+
+        self.last_started[kind.index()] = Some(now);
+        let spawned = shell(command)
+            .envs(vars.iter().map(|(key, value)| (key, value)))
+            .env("BUTT_HOOK_EVENT", kind.name())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => {
+                self.running.push(Running {
+                    kind,
+                    child,
+                    started: now,
+                });
+                None
+            }
+            Err(error) => Some(HookReport::SpawnFailed { kind, error }),
+        }
+    }
+
+    /// Reap finished hooks and kill those past the timeout.
+    pub fn poll(&mut self, now: Instant) -> Vec<HookReport> {
+        let timeout = self.options.timeout;
+        let mut reports = Vec::new();
+        self.running.retain_mut(|hook| match hook.child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    reports.push(HookReport::Failed {
+                        kind: hook.kind,
+                        status,
+                    });
+                }
+                false
+            }
+            Ok(None) if now.saturating_duration_since(hook.started) >= timeout => {
+                let _ = hook.child.kill();
+                let _ = hook.child.wait();
+                reports.push(HookReport::TimedOut {
+                    kind: hook.kind,
+                    timeout,
+                });
+                false
+            }
+            Ok(None) => true,
+            Err(error) => {
+                reports.push(HookReport::SpawnFailed {
+                    kind: hook.kind,
+                    error,
+                });
+                false
+            }
+        });
+        reports
+    }
+
+    /// Wait for running hooks to finish, killing any that reach the timeout.
+    pub fn finish(&mut self) -> Vec<HookReport> {
+        let mut reports = Vec::new();
+        while !self.running.is_empty() {
+            reports.extend(self.poll(Instant::now()));
+            if !self.running.is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        reports
+    }
+}
+
+/// Environment for an urgent line: `BUTT_HOOK_SOURCE`, `BUTT_HOOK_LINE`, `BUTT_HOOK_PATTERN`
+/// (the reason such as `severity err` if there is one, else the regex), and for regex matches
+/// `BUTT_HOOK_MATCH`, `BUTT_HOOK_GROUP_<N>` and `BUTT_HOOK_GROUP_<NAME>` for each capture group,
+/// and `BUTT_HOOK_GROUPS`, a JSON object of the named groups.
+pub fn match_vars(
+    source: &str,
+    line: &str,
+    pattern: Option<&Pattern>,
+    reason: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("BUTT_HOOK_SOURCE".to_string(), source.to_string()),
+        ("BUTT_HOOK_LINE".to_string(), line.to_string()),
+    ];
+    let captures = pattern.and_then(|pattern| Some((pattern, pattern.captures(line.as_bytes())?)));
+    // The reason wins over the regex, as in webhook alerts, so both agree on
+    // why a line was urgent.
+    let label = match (reason, &captures) {
+        (Some(reason), _) => reason.to_string(),
+        (None, Some((regex, _))) => regex.as_str().to_string(),
+        (None, None) => String::new(),
+    };
+    vars.push(("BUTT_HOOK_PATTERN".to_string(), label));
+
+    if let Some((pattern, captures)) = captures {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        vars.push(("BUTT_HOOK_MATCH".to_string(), text(&captures[0])));
+        let mut named = serde_json::Map::new();
+        for (index, name) in pattern.regex().capture_names().enumerate().skip(1) {
+            let Some(group) = captures.get(index) else {
+                continue;
+            };
+            vars.push((format!("BUTT_HOOK_GROUP_{index}"), text(group.as_bytes())));
+            if let Some(name) = name {
+                vars.push((
+                    format!("BUTT_HOOK_GROUP_{}", name.to_ascii_uppercase()),
+                    text(group.as_bytes()),
+                ));
+                named.insert(name.to_string(), text(group.as_bytes()).into());
+            }
+        }
+        vars.push((
+            "BUTT_HOOK_GROUPS".to_string(),
+            serde_json::Value::Object(named).to_string(),
+        ));
+    }
+    vars
+}
+
+/// Environment for an idle notice: `BUTT_HOOK_SOURCE` and `BUTT_HOOK_IDLE_SECONDS`.
+pub fn idle_vars(source: &str, interval: Duration) -> Vec<(String, String)> {
+    vec![
+        ("BUTT_HOOK_SOURCE".to_string(), source.to_string()),
+        (
+            "BUTT_HOOK_IDLE_SECONDS".to_string(),
+            interval.as_secs().to_string(),
+        ),
+    ]
+}
+
+/// Environment for a missed notice: `BUTT_HOOK_SOURCE`, `BUTT_HOOK_PATTERN` (the
+/// `--expect` regex) and `BUTT_HOOK_WITHIN_SECONDS`.
+pub fn missed_vars(source: &str, pattern: &str, within: Duration) -> Vec<(String, String)> {
+    vec![
+        ("BUTT_HOOK_SOURCE".to_string(), source.to_string()),
+        ("BUTT_HOOK_PATTERN".to_string(), pattern.to_string()),
+        (
+            "BUTT_HOOK_WITHIN_SECONDS".to_string(),
+            within.as_secs().to_string(),
+        ),
+    ]
+}
+
+#[cfg(unix)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn options(debounce: Duration, max_running: usize, timeout: Duration) -> HookOptions {
+        HookOptions {
+            debounce,
+            max_running,
+            timeout,
+        }
+    }
+
+    #[test]
+    fn match_vars_expose_line_pattern_and_groups() {
+        let regex = Pattern::new(r"status=(?P<status>\d+) (\w+)", false).expect("pattern");
+        let vars = match_vars("app.log", "GET / status=503 slow", Some(&regex), None);
+        let get = |key: &str| {
+            vars.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(get("BUTT_HOOK_SOURCE"), Some("app.log"));
+        assert_eq!(get("BUTT_HOOK_LINE"), Some("GET / status=503 slow"));
+        assert_eq!(get("BUTT_HOOK_PATTERN"), Some(regex.as_str()));
+        assert_eq!(get("BUTT_HOOK_MATCH"), Some("status=503 slow"));
+        assert_eq!(get("BUTT_HOOK_GROUP_1"), Some("503"));
+        assert_eq!(get("BUTT_HOOK_GROUP_STATUS"), Some("503"));
+        assert_eq!(get("BUTT_HOOK_GROUP_2"), Some("slow"));
+        assert_eq!(get("BUTT_HOOK_GROUPS"), Some(r#"{"status":"503"}"#));
+
+        let vars = match_vars("-", "disk full", Some(&regex), Some("severity err"));
+        assert!(vars.contains(&("BUTT_HOOK_PATTERN".to_string(), "severity err".to_string())));
+        assert!(!vars.iter().any(|(name, _)| name == "BUTT_HOOK_MATCH"));
+
+        // A severe line that also matches keeps the severity as its reason.
+        let vars = match_vars("-", "status=500 boom", Some(&regex), Some("severity crit"));
+        assert!(vars.contains(&("BUTT_HOOK_PATTERN".to_string(), "severity crit".to_string())));
+        assert!(vars.contains(&("BUTT_HOOK_GROUP_STATUS".to_string(), "500".to_string())));
+    }
+
+    #[test]
+    fn runs_hook_with_environment_and_debounces() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let out = tmp.path().join("hook.out");
+        let command = format!(
+            "echo \"$BUTT_HOOK_EVENT $BUTT_HOOK_IDLE_SECONDS\" >> '{}'",
+            out.display()
+        );
+        let mut hooks = Hooks::new(options(Duration::from_secs(60), 4, Duration::from_secs(5)))
+            .with_command(HookKind::Idle, Some(command));
+        assert!(!hooks.has_command(HookKind::Match));
+
+        let now = Instant::now();
+        let vars = idle_vars("stdin", Duration::from_secs(7));
+        assert!(hooks.trigger(HookKind::Idle, &vars, now).is_none());
+        assert!(hooks.trigger(HookKind::Idle, &vars, now).is_none());
+        assert!(hooks.trigger(HookKind::Match, &vars, now).is_none());
+        assert_eq!(hooks.debounced(), 1);
+        assert!(hooks.finish().is_empty());
+
+        assert_eq!(
+            std::fs::read_to_string(&out).expect("hook output"),
+            "idle 7\n"
+        );
+    }
+
+    #[test]
+    fn limits_concurrency_and_reports_failures_and_timeouts() {
+        let mut hooks = Hooks::new(options(Duration::ZERO, 1, Duration::from_millis(200)))
+            .with_command(HookKind::Match, Some("sleep 5".to_string()))
+            .with_command(HookKind::Idle, Some("exit 3".to_string()));
+
+        let now = Instant::now();
+        assert!(hooks.trigger(HookKind::Match, &[], now).is_none());
+        assert!(matches!(
+            hooks.trigger(HookKind::Idle, &[], now),
+            Some(HookReport::Busy { max_running: 1, .. })
+        ));
+        let reports = hooks.finish();
+        assert!(matches!(
+            reports.as_slice(),
+            [HookReport::TimedOut {
+                kind: HookKind::Match,
+                ..
+            }]
+        ));
+
+        assert!(hooks.trigger(HookKind::Idle, &[], Instant::now()).is_none());
+        let reports = hooks.finish();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(
+            reports[0].to_string(),
+            "--on-idle hook failed: exit status: 3"
+        );
+    }
+}
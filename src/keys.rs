@@ -0,0 +1,274 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: u8 = 0x7f;
+const CTRL_H: u8 = 0x08;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum KeyAction {
+    IncreaseInterval,
+    DecreaseInterval,
+    TogglePause,
+    Flush,
+    /// Replace the highlight regex; an empty pattern clears it.
+    SetRegex(String),
+    Quit,
+}
+
+/// Interprets single keystrokes, including the `/` regex prompt.
+#[derive(Debug, Default)]
+struct KeyInterpreter {
+    editing: Option<Vec<u8>>,
+}
+
+impl KeyInterpreter {
+    fn feed(&mut self, byte: u8) -> Option<KeyAction> {
+        if let Some(buf) = &mut self.editing {
+            match byte {
+                b'\r' | b'\n' => {
+                    let pattern = String::from_utf8_lossy(buf).into_owned();
+                    self.editing = None;
+                    return Some(KeyAction::SetRegex(pattern));
+                }
+                ESCAPE => self.editing = None,
+                BACKSPACE | CTRL_H => {
+                    while let Some(last) = buf.pop() {
+                        if last & 0xC0 != 0x80 {
+                            break;
+                        }
+                    }
+                }
+                _ if byte >= 0x20 => buf.push(byte),
+                _ => {}
+            }
+            return None;
+        }
+
+        match byte {
+            b'+' | b'=' => Some(KeyAction::IncreaseInterval),
+            b'-' | b'_' => Some(KeyAction::DecreaseInterval),
+            b' ' => Some(KeyAction::TogglePause),
+            b'f' => Some(KeyAction::Flush),
+            b'q' => Some(KeyAction::Quit),
+            b'/' => {
+                self.editing = Some(Vec::new());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn prompt(&self) -> Option<String> {
+        self.editing
+            .as_ref()
+            .map(|buf| format!("/{}", String::from_utf8_lossy(buf)))
+    }
+}
+
+/// Keystrokes read from the controlling terminal while output is interactive.
+pub(crate) struct KeyControls {
+    rx: Receiver<u8>,
+    interpreter: KeyInterpreter,
+    _terminal: terminal::RawTerminal,
+}
+
+impl KeyControls {
+    /// Put the controlling terminal into cbreak mode and start reading keys.
+    ///
+    /// Returns `None` when there is no controlling terminal to read from.
+    pub(crate) fn start() -> Option<Self> {
+        let (terminal, reader) = terminal::RawTerminal::open()?;
+        let (tx, rx) = mpsc::sync_channel(64);
+        terminal::spawn_key_reader(reader, tx);
+        Some(Self {
+            rx,
+            interpreter: KeyInterpreter::default(),
+            _terminal: terminal,
+        })
+    }
+
+    pub(crate) fn next_action(&mut self) -> Option<KeyAction> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(byte) => {
+                    if let Some(action) = self.interpreter.feed(byte) {
+                        return Some(action);
+                    }
+                }
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+
+    pub(crate) fn prompt(&self) -> Option<String> {
+        self.interpreter.prompt()
+    }
+}
+
+/// Undo cbreak mode without waiting for `KeyControls` to be dropped.
+pub(crate) fn restore_terminal() {
+    terminal::restore();
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::fs::{File, OpenOptions};
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
+    use std::sync::OnceLock;
+    use std::sync::mpsc::SyncSender;
+    use std::thread;
+
+    /// The mode to restore, with a descriptor of its own that is never closed,
+    /// so `restore` still reaches the terminal after `RawTerminal` is dropped.
+    static ORIGINAL: OnceLock<(libc::c_int, libc::termios)> = OnceLock::new();
+
+    /// Restores the saved terminal mode when dropped.
+    pub(super) struct RawTerminal {
+        tty: File,
+        original: libc::termios,
+    }
+
+    impl RawTerminal {
+        pub(super) fn open() -> Option<(Self, File)> {
+            let tty = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .ok()?;
+            let reader = tty.try_clone().ok()?;
+            let fd = tty.as_raw_fd();
+
+            // SAFETY: tcgetattr/tcsetattr only read and write the termios struct.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+                return None;
+            }
+            let mut cbreak = original;
+            cbreak.c_lflag &= !(libc::ICANON | libc::ECHO);
+            cbreak.c_cc[libc::VMIN] = 1;
+            cbreak.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &cbreak) } != 0 {
+                return None;
+            }
+
+            if ORIGINAL.get().is_none()
+                && let Ok(saved) = tty.try_clone()
+                && let Err((fd, _)) = ORIGINAL.set((saved.into_raw_fd(), original))
+            {
+                // SAFETY: `fd` came from `into_raw_fd` above and is owned by nobody else.
+                drop(unsafe { File::from_raw_fd(fd) });
+            }
+            Some((Self { tty, original }, reader))
+        }
+    }
+
+    impl Drop for RawTerminal {
+        fn drop(&mut self) {
+            // SAFETY: restores the attributes captured in `open`.
+            unsafe {
+                libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.original);
+            }
+        }
+    }
+
+    /// Restore the saved terminal mode; safe to call from a signal handler.
+    pub(super) fn restore() {
+        if let Some((fd, original)) = ORIGINAL.get() {
+            // SAFETY: tcsetattr is async-signal-safe.
+            unsafe {
+                libc::tcsetattr(*fd, libc::TCSANOW, original);
+            }
+        }
+    }
+
+    pub(super) fn spawn_key_reader(mut reader: File, tx: SyncSender<u8>) {
+        thread::spawn(move || {
+            let mut buf = [0_u8; 64];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        for &byte in &buf[..n] {
+                            if tx.send(byte).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    use std::fs::File;
+    use std::sync::mpsc::SyncSender;
+
+    pub(super) struct RawTerminal;
+
+    impl RawTerminal {
+        pub(super) fn open() -> Option<(Self, File)> {
+            None
+        }
+    }
+
+    pub(super) fn restore() {}
+
+    pub(super) fn spawn_key_reader(_reader: File, _tx: SyncSender<u8>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(interpreter: &mut KeyInterpreter, input: &[u8]) -> Vec<KeyAction> {
+        input
+            .iter()
+            .filter_map(|&byte| interpreter.feed(byte))
+            .collect()
+    }
+
+    #[test]
+    fn maps_single_keys_to_actions() {
+        let mut interpreter = KeyInterpreter::default();
+        let actions = feed_all(&mut interpreter, b"+- fqx");
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::IncreaseInterval,
+                KeyAction::DecreaseInterval,
+                KeyAction::TogglePause,
+                KeyAction::Flush,
+                KeyAction::Quit,
+            ]
+        );
+    }
+
+    #[test]
+    fn slash_edits_regex_until_enter() {
+        let mut interpreter = KeyInterpreter::default();
+        assert!(feed_all(&mut interpreter, b"/ERRX").is_empty());
+        assert_eq!(interpreter.prompt().as_deref(), Some("/ERRX"));
+
+        let actions = feed_all(&mut interpreter, &[BACKSPACE, b'O', b'R', b'\r']);
+        assert_eq!(actions, vec![KeyAction::SetRegex("ERROR".to_string())]);
+        assert!(interpreter.prompt().is_none());
+    }
+
+    #[test]
+    fn escape_cancels_regex_edit() {
+        let mut interpreter = KeyInterpreter::default();
+        let actions = feed_all(&mut interpreter, &[b'/', b'q', ESCAPE, b'q']);
+        assert_eq!(actions, vec![KeyAction::Quit]);
+    }
+
+    #[test]
+    fn backspace_removes_whole_utf8_character() {
+        let mut interpreter = KeyInterpreter::default();
+        feed_all(&mut interpreter, "/aé".as_bytes());
+        feed_all(&mut interpreter, &[BACKSPACE]);
+        assert_eq!(interpreter.prompt().as_deref(), Some("/a"));
+    }
+}
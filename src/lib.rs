@@ -0,0 +1,79 @@
+//! Throttled log following: show the newest line every few seconds, urgent
+//! regex matches immediately, and a notice when the stream goes quiet.
+//!
+//! The `butt` binary is a thin terminal front end over these pieces:
+//!
+//! - [`Follower`] reads lines from a file (surviving rotation) or any reader.
+//! - [`Listener`] accepts lines over UDP, TCP or Unix sockets.
+//! - [`LineAssembler`] turns raw chunks into bounded [`Lines`], decoded only when read.
+//! - [`text`] decodes other [`Encoding`]s and escapes bytes that are unsafe to print.
+//! - [`Throttler`] decides what to show and when, using an injectable [`Clock`], and
+//!   notices when an [`Expectation`] stops matching.
+//! - [`Pattern`] matches raw line bytes, skipping lines that lack its required literals.
+//! - [`ansi`] finds escape sequences from colored tools so they can be stripped or kept.
+//! - [`Highlighter`] and [`Sink`] render and deliver the resulting [`Event`]s, painting
+//!   matches in a [`Style`].
+//! - [`TimestampParser`] reads when a line was logged, so [`LagMonitor`] can spot slow producers.
+//! - [`ProgressTracker`] turns progress lines into a rate and ETA, and notices stalls.
+//! - [`Hooks`] runs shell commands on urgent lines and idle notices, with limits.
+//! - [`HeartbeatFile`] and [`Watchdog`] signal liveness to probes and systemd.
+//! - [`MetricsServer`] exposes the stream counters to Prometheus.
+//! - [`Webhook`] POSTs batched alerts to an HTTP endpoint, retrying with backoff.
+//! - [`TeeSink`] copies lines to a file, UDP socket or command without blocking.
+//! - With the `async` feature, `stream::EventStream` delivers events as a tokio `Stream`.
+//! - [`sim`] replays scenarios on a [`VirtualClock`](sim::VirtualClock) for tests.
+//!
+//! ```
+//! use butt::{Event, Pattern, ThrottleConfig, Throttler};
+//! use std::time::Duration;
+//!
+//! let mut throttler = Throttler::new(ThrottleConfig::new(Duration::from_secs(5)))
+//!     .with_pattern(Some(Pattern::new("ERROR", false).unwrap()));
+//! let mut events: Vec<Event> = Vec::new();
+//!
+//! throttler.observe("all good", &mut events);
+//! throttler.observe("ERROR disk full", &mut events);
+//! assert_eq!(events.len(), 1);
+//! ```
+
+pub mod ansi;
+pub mod clock;
+pub mod follow;
+pub mod heartbeat;
+pub mod hook;
+pub mod limits;
+pub mod listen;
+pub mod metrics;
+pub mod output;
+pub mod pattern;
+pub mod progress;
+pub mod sim;
+pub mod stats;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod style;
+pub mod syslog;
+pub mod tee;
+pub mod text;
+pub mod throttle;
+pub mod timestamp;
+pub mod webhook;
+
+pub use ansi::AnsiMode;
+pub use clock::{Clock, SystemClock};
+pub use follow::{Batch, FollowOptions, Follower, LineSource, Notice};
+pub use heartbeat::{HeartbeatFile, HeartbeatFormat, Watchdog};
+pub use hook::{HookKind, HookOptions, HookReport, Hooks};
+pub use limits::{Assembled, LineAssembler, Lines};
+pub use listen::{ListenAddr, Listener};
+pub use metrics::MetricsServer;
+pub use output::{Highlighter, Sink, WriterSink, should_use_color};
+pub use pattern::Pattern;
+pub use progress::ProgressTracker;
+pub use stats::StreamStats;
+pub use style::{Color, ColorSupport, GroupStyles, HighlightScope, Style};
+pub use tee::{TeeSink, TeeTarget};
+pub use text::Encoding;
+pub use throttle::{Event, Expectation, Line, ThrottleConfig, Throttler};
+pub use timestamp::{LagMonitor, TimestampFormat, TimestampParser};
+pub use webhook::{Alert, Webhook, WebhookFormat, WebhookOptions};
@@ -1,103 +1,268 @@
-use std::io::{self, BufRead, Write};
-use std::sync::mpsc::SyncSender;
+use crate::text::{Decoder, Encoding};
+use memchr::{memchr, memchr_iter};
+use std::borrow::Cow;
+use std::io::{ErrorKind, Read};
+use std::sync::mpsc::{Receiver, SyncSender};
 use std::thread;
 
-pub(crate) fn collect_complete_lines(
-    pending: &mut Vec<u8>,
-    max_line_bytes: usize,
-) -> (Vec<String>, usize) {
-    let mut lines = Vec::new();
-    let mut dropped_or_truncated = 0;
-    while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
-        let mut line = pending.drain(..=pos).collect::<Vec<u8>>();
-        if line.ends_with(b"\n") {
-            line.pop();
-        }
-        if line.ends_with(b"\r") {
-            line.pop();
-        }
-        if line.len() > max_line_bytes {
-            line.truncate(max_line_bytes);
-            dropped_or_truncated += 1;
-        }
-        lines.push(String::from_utf8_lossy(&line).to_string());
+/// Complete lines from one batch of input, stored back to back in a single
+/// buffer. Nothing is decoded until a line is read with [`text`](Self::text)
+/// or [`texts`](Self::texts), and valid UTF-8 is borrowed rather than copied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lines {
+    bytes: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl Lines {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    if pending.len() > max_line_bytes {
-        pending.clear();
-        dropped_or_truncated += 1;
+    pub fn len(&self) -> usize {
+        self.ends.len()
     }
 
-    (lines, dropped_or_truncated)
-}
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
 
-pub(crate) fn append_with_buffer_cap(
-    pending: &mut Vec<u8>,
-    incoming: &[u8],
-    max_buffer_bytes: usize,
-) -> bool {
-    if incoming.len() >= max_buffer_bytes {
-        pending.clear();
-        pending.extend_from_slice(&incoming[incoming.len() - max_buffer_bytes..]);
-        return true;
+    pub fn push(&mut self, line: impl AsRef<[u8]>) {
+        self.bytes.extend_from_slice(line.as_ref());
+        self.ends.push(self.bytes.len());
+    }
+
+    /// The raw bytes of line `index`, without its line ending.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(index)?;
+        let start = index.checked_sub(1).map_or(0, |prev| self.ends[prev]);
+        Some(&self.bytes[start..end])
+    }
+
+    /// Line `index` as text; invalid UTF-8 is replaced with U+FFFD.
+    pub fn text(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.get(index).map(String::from_utf8_lossy)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        (0..self.len()).map(|index| self.get(index).expect("index in range"))
+    }
+
+    pub fn texts(&self) -> impl DoubleEndedIterator<Item = Cow<'_, str>> + ExactSizeIterator {
+        self.iter().map(String::from_utf8_lossy)
     }
 
-    let mut dropped = false;
-    if pending.len() + incoming.len() > max_buffer_bytes {
-        pending.clear();
-        dropped = true;
+    /// Decode every line into an owned `String`.
+    pub fn to_strings(&self) -> Vec<String> {
+        self.texts().map(Cow::into_owned).collect()
     }
 
-    pending.extend_from_slice(incoming);
-    dropped
+    /// Make room for `bytes` more bytes of line data.
+    pub fn reserve(&mut self, bytes: usize) {
+        self.bytes.reserve(bytes);
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.ends.clear();
+    }
 }
 
-fn truncate_utf8_to_bytes(s: &mut String, max_bytes: usize) {
-    if s.len() <= max_bytes {
-        return;
+impl<L: AsRef<[u8]>> Extend<L> for Lines {
+    fn extend<I: IntoIterator<Item = L>>(&mut self, lines: I) {
+        for line in lines {
+            self.push(line);
+        }
     }
+}
 
-    let mut idx = max_bytes;
-    while idx > 0 && !s.is_char_boundary(idx) {
-        idx -= 1;
+impl<L: AsRef<[u8]>> FromIterator<L> for Lines {
+    fn from_iter<I: IntoIterator<Item = L>>(lines: I) -> Self {
+        let mut collected = Self::new();
+        collected.extend(lines);
+        collected
     }
-    s.truncate(idx);
 }
 
-pub(crate) fn start_stdin_reader(
-    tx: SyncSender<String>,
+/// What [`LineAssembler::push`] produced from one chunk of input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembled {
+    pub lines: Lines,
+    /// Buffered bytes were discarded to stay under the buffer limit.
+    pub buffer_overflowed: bool,
+    /// Lines truncated, or unterminated fragments dropped, for exceeding the line limit.
+    pub dropped_or_truncated: usize,
+}
+
+/// Splits a byte stream into lines while bounding memory use.
+///
+/// Lines longer than `max_line_bytes` are truncated, and pending bytes never
+/// exceed `max_buffer_bytes`; older data is dropped instead.
+///
+/// Chunks are scanned with `memchr` and [`push_with`](Self::push_with) hands
+/// complete lines out as slices of the chunk itself; [`push`](Self::push)
+/// copies them once into a packed [`Lines`] buffer. The unterminated tail is
+/// carried over in an assembly buffer that keeps its allocation from chunk to
+/// chunk.
+///
+/// Input in another [`Encoding`] is converted to UTF-8 first, so the limits
+/// apply to the converted bytes.
+#[derive(Debug, Clone)]
+pub struct LineAssembler {
+    pending: Vec<u8>,
+    decoder: Decoder,
     max_line_bytes: usize,
     max_buffer_bytes: usize,
+}
+
+impl LineAssembler {
+    pub fn new(max_line_bytes: usize, max_buffer_bytes: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            decoder: Decoder::new(Encoding::Utf8),
+            max_line_bytes,
+            max_buffer_bytes,
+        }
+    }
+
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.decoder = Decoder::new(encoding);
+        self
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Assembled {
+        let mut lines = Lines::new();
+        lines.reserve(self.pending.len() + chunk.len());
+        let (buffer_overflowed, dropped_or_truncated) =
+            self.push_with(chunk, |line| lines.push(line));
+        Assembled {
+            lines,
+            buffer_overflowed,
+            dropped_or_truncated,
+        }
+    }
+
+    /// Like [`push`](Self::push), but pass each complete line to `on_line`
+    /// without collecting or copying it. Returns whether the buffer overflowed
+    /// and how many lines were truncated or dropped.
+    pub fn push_with(&mut self, chunk: &[u8], mut on_line: impl FnMut(&[u8])) -> (bool, usize) {
+        let decoded = self.decoder.decode(chunk);
+        let max_line_bytes = self.max_line_bytes;
+        let mut dropped_or_truncated = 0;
+        let mut emit = |line: &[u8]| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.len() > max_line_bytes {
+                dropped_or_truncated += 1;
+                on_line(&line[..max_line_bytes]);
+            } else {
+                on_line(line);
+            }
+        };
+
+        let mut rest: &[u8] = &decoded;
+        if !self.pending.is_empty()
+            && let Some(pos) = memchr(b'\n', rest)
+        {
+            self.pending.extend_from_slice(&rest[..pos]);
+            emit(&self.pending);
+            self.pending.clear();
+            rest = &rest[pos + 1..];
+        }
+        if self.pending.is_empty() {
+            let mut line_start = 0;
+            for pos in memchr_iter(b'\n', rest) {
+                emit(&rest[line_start..pos]);
+                line_start = pos + 1;
+            }
+            rest = &rest[line_start..];
+        }
+
+        // Only the unterminated tail is held between chunks, so only it counts
+        // against the buffer limit.
+        let buffer_overflowed = self.pending.len() + rest.len() > self.max_buffer_bytes;
+        if buffer_overflowed {
+            self.pending.clear();
+            rest = &rest[rest.len().saturating_sub(self.max_buffer_bytes)..];
+        }
+        self.pending.extend_from_slice(rest);
+        (
+            buffer_overflowed,
+            dropped_or_truncated + self.drop_oversized_pending(),
+        )
+    }
+
+    /// An unterminated fragment longer than a line may be is dropped.
+    fn drop_oversized_pending(&mut self) -> usize {
+        if self.pending.len() > self.max_line_bytes {
+            self.pending.clear();
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Take the unterminated last line at end of input, if any.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let mut line = std::mem::take(&mut self.pending);
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+        Some(line)
+    }
+
+    /// Forget any partial line and decoder state, e.g. after the source was
+    /// truncated or replaced.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.decoder = Decoder::new(self.decoder.encoding());
+    }
+
+    /// Bytes of the current unterminated line.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn max_line_bytes(&self) -> usize {
+        self.max_line_bytes
+    }
+
+    pub fn max_buffer_bytes(&self) -> usize {
+        self.max_buffer_bytes
+    }
+}
+
+/// Forward raw chunks of up to `chunk_bytes` from `reader` on a background
+/// thread until EOF, an error, or the receiver hangs up. Splitting into lines
+/// happens on the receiving side, so the channel carries a few large buffers,
+/// not one message per line.
+///
+/// The receiver hands finished chunks back on `recycled`, and they are read
+/// into again, so a steady stream allocates nothing per chunk.
+pub(crate) fn start_chunk_reader<R: Read + Send + 'static>(
+    mut reader: R,
+    chunk_bytes: usize,
+    tx: SyncSender<Vec<u8>>,
+    recycled: Receiver<Vec<u8>>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let stdin = io::stdin();
-        let mut locked = stdin.lock();
         loop {
-            let mut line = String::new();
-            match locked.read_line(&mut line) {
-                Ok(0) => break,
-                Ok(_) => {
-                    if line.len() > max_buffer_bytes {
-                        eprintln!(
-                            "[butt] stdin chunk exceeded --max-buffer-bytes={}, truncating",
-                            max_buffer_bytes
-                        );
-                        let _ = io::stderr().flush();
-                        truncate_utf8_to_bytes(&mut line, max_buffer_bytes);
-                    }
-                    if line.len() > max_line_bytes {
-                        eprintln!(
-                            "[butt] line exceeded --max-line-bytes={}, truncating",
-                            max_line_bytes
-                        );
-                        let _ = io::stderr().flush();
-                        truncate_utf8_to_bytes(&mut line, max_line_bytes);
-                    }
-                    if tx.send(line).is_err() {
-                        break;
-                    }
+            let mut chunk = recycled.try_recv().unwrap_or_default();
+            chunk.resize(chunk_bytes, 0);
+            let read = loop {
+                match reader.read(&mut chunk) {
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    result => break result,
                 }
-                Err(_) => break,
+            };
+            match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => chunk.truncate(n),
+            }
+            if tx.send(chunk).is_err() {
+                break;
             }
         }
     })
@@ -108,28 +273,86 @@ mod tests {
     use super::*;
 
     #[test]
-    fn collect_complete_lines_truncates_oversized_lines() {
-        let mut pending = b"abcdef\n".to_vec();
-        let (lines, dropped) = collect_complete_lines(&mut pending, 3);
-        assert_eq!(lines, vec!["abc".to_string()]);
-        assert_eq!(dropped, 1);
-        assert!(pending.is_empty());
+    fn truncates_oversized_lines() {
+        let mut assembler = LineAssembler::new(3, 1024);
+        let assembled = assembler.push(b"abcdef\n");
+        assert_eq!(assembled.lines.to_strings(), vec!["abc".to_string()]);
+        assert_eq!(assembled.dropped_or_truncated, 1);
+        assert_eq!(assembler.pending_len(), 0);
+    }
+
+    #[test]
+    fn drops_oversized_unterminated_fragment() {
+        let mut assembler = LineAssembler::new(3, 1024);
+        let assembled = assembler.push(b"abcdef");
+        assert!(assembled.lines.is_empty());
+        assert_eq!(assembled.dropped_or_truncated, 1);
+        assert_eq!(assembler.pending_len(), 0);
+    }
+
+    #[test]
+    fn line_assembler_joins_lines_across_chunks() {
+        let mut assembler = LineAssembler::new(64, 1024);
+        let first = assembler.push(b"hel");
+        assert!(first.lines.is_empty());
+        assert_eq!(assembler.pending_len(), 3);
+
+        let second = assembler.push(b"lo\r\nworld\npart");
+        assert_eq!(
+            second.lines.to_strings(),
+            vec!["hello".to_string(), "world".to_string()]
+        );
+        assert!(!second.buffer_overflowed);
+        assert_eq!(assembler.pending_len(), 4);
+        assert_eq!(assembler.finish(), Some(b"part".to_vec()));
+        assert_eq!(assembler.finish(), None);
+    }
+
+    #[test]
+    fn keeps_recent_bytes_when_incoming_chunk_is_huge() {
+        let mut assembler = LineAssembler::new(64, 4);
+        assembler.push(b"old");
+        let assembled = assembler.push(b"abcdef");
+        assert!(assembled.buffer_overflowed);
+        assert_eq!(assembler.finish(), Some(b"cdef".to_vec()));
+    }
+
+    #[test]
+    fn buffer_limit_applies_only_to_the_unterminated_tail() {
+        let mut assembler = LineAssembler::new(64, 16);
+        let chunk: Vec<u8> = (1..=200)
+            .flat_map(|n| format!("line {n}\n").into_bytes())
+            .chain(*b"tail")
+            .collect();
+        let assembled = assembler.push(&chunk);
+        assert!(!assembled.buffer_overflowed);
+        assert_eq!(assembled.lines.len(), 200);
+        assert_eq!(assembled.lines.get(199), Some(&b"line 200"[..]));
+        assert_eq!(assembler.finish(), Some(b"tail".to_vec()));
     }
 
     #[test]
-    fn collect_complete_lines_drops_oversized_unterminated_fragment() {
-        let mut pending = b"abcdef".to_vec();
-        let (lines, dropped) = collect_complete_lines(&mut pending, 3);
-        assert!(lines.is_empty());
-        assert_eq!(dropped, 1);
-        assert!(pending.is_empty());
+    fn lines_decode_lazily_and_borrow_valid_utf8() {
+        let lines: Lines = [&b"plain"[..], b"bad \xff byte", b""].into_iter().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines.get(1), Some(&b"bad \xff byte"[..]));
+        assert!(matches!(lines.text(0), Some(Cow::Borrowed("plain"))));
+        assert_eq!(lines.text(1).as_deref(), Some("bad \u{fffd} byte"));
+        assert_eq!(lines.text(2).as_deref(), Some(""));
+        assert_eq!(lines.text(3), None);
     }
 
     #[test]
-    fn append_with_buffer_cap_keeps_recent_bytes_when_incoming_is_huge() {
-        let mut pending = b"old".to_vec();
-        let dropped = append_with_buffer_cap(&mut pending, b"abcdef", 4);
-        assert!(dropped);
-        assert_eq!(pending, b"cdef".to_vec());
+    fn chunk_reader_forwards_input_until_eof() {
+        let (tx, rx) = std::sync::mpsc::sync_channel(4);
+        let (recycle, recycled) = std::sync::mpsc::sync_channel(4);
+        recycle.send(Vec::with_capacity(4)).expect("recycle");
+        let input = std::io::Cursor::new(b"abcdef\nok\n".to_vec());
+        start_chunk_reader(input, 4, tx, recycled)
+            .join()
+            .expect("reader thread");
+        let chunks: Vec<Vec<u8>> = rx.try_iter().collect();
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+        assert_eq!(chunks.concat(), b"abcdef\nok\n");
     }
 }
@@ -0,0 +1,398 @@
+use crate::follow::{Batch, FollowOptions, LineSource, Notice};
+use crate::limits::{LineAssembler, Lines};
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Stream connections served at once; each has its own thread, so more are
+/// refused until one closes.
+pub const MAX_CONNECTIONS: usize = 256;
+
+/// A socket to accept log lines on, written as `udp://HOST:PORT`,
+/// `tcp://HOST:PORT` or `unix:///PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Udp(String),
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = value
+            .split_once("://")
+            .ok_or_else(|| format!("expected udp://, tcp:// or unix:// in '{value}'"))?;
+        if rest.is_empty() {
+            return Err(format!("missing address in '{value}'"));
+        }
+        match scheme {
+            "udp" => Ok(Self::Udp(rest.to_string())),
+            "tcp" => Ok(Self::Tcp(rest.to_string())),
+            "unix" => Ok(Self::Unix(PathBuf::from(rest))),
+            other => Err(format!(
+                "unsupported scheme '{other}' (expected udp, tcp or unix)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Udp(addr) => write!(f, "udp://{addr}"),
+            Self::Tcp(addr) => write!(f, "tcp://{addr}"),
+            Self::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Input from one of the listener's threads.
+enum Input {
+    Connected(String),
+    Disconnected(String),
+    Refused(String),
+    Data { peer: String, batch: Batch },
+}
+
+/// Accepts lines from any number of socket connections (or UDP senders).
+///
+/// Each peer gets its own line assembly under the [`FollowOptions`] limits,
+/// and every line is tagged with its peer in [`Batch::peers`] so interleaved
+/// senders stay distinguishable without changing what patterns see.
+pub struct Listener {
+    rx: Receiver<Input>,
+    label: String,
+    #[cfg(unix)]
+    socket_path: Option<PathBuf>,
+}
+
+impl Listener {
+    pub fn bind(addr: &ListenAddr, options: &FollowOptions) -> io::Result<Self> {
+        let (tx, rx) = mpsc::sync_channel(1024);
+        let assembler = options.assembler();
+        let (label, socket_path) = match addr {
+            ListenAddr::Udp(host) => {
+                let socket = UdpSocket::bind(resolve(host)?)?;
+                let label = format!("udp://{}", socket.local_addr()?);
+                thread::spawn(move || receive_datagrams(socket, tx, assembler));
+                (label, None)
+            }
+            ListenAddr::Tcp(host) => {
+                let listener = TcpListener::bind(resolve(host)?)?;
+                let label = format!("tcp://{}", listener.local_addr()?);
+                thread::spawn(move || {
+                    let open = Arc::new(AtomicUsize::new(0));
+                    for stream in listener.incoming().flatten() {
+                        let peer = stream
+                            .peer_addr()
+                            .map_or_else(|_| "tcp".to_string(), |addr| addr.to_string());
+                        spawn_connection(stream, peer, &open, tx.clone(), assembler.clone());
+                    }
+                });
+                (label, None)
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                let listener = bind_unix(path)?;
+                thread::spawn(move || {
+                    let open = Arc::new(AtomicUsize::new(0));
+                    // Unix peers have no address, so number the connections instead.
+                    for (index, stream) in listener.incoming().flatten().enumerate() {
+                        let peer = format!("unix#{}", index + 1);
+                        spawn_connection(stream, peer, &open, tx.clone(), assembler.clone());
+                    }
+                });
+                (addr.to_string(), Some(path.clone()))
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "unix sockets are not supported on this platform",
+                ));
+            }
+        };
+
+        #[cfg(not(unix))]
+        let _: Option<PathBuf> = socket_path;
+        Ok(Self {
+            rx,
+            label,
+            #[cfg(unix)]
+            socket_path,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl LineSource for Listener {
+    fn poll(&mut self, wait: Duration) -> io::Result<Option<Batch>> {
+        let first = match self.rx.recv_timeout(wait) {
+            Ok(input) => input,
+            Err(mpsc::RecvTimeoutError::Timeout) => return Ok(Some(Batch::default())),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(None),
+        };
+
+        let mut batch = Batch::default();
+        for input in std::iter::once(first).chain(self.rx.try_iter()) {
+            match input {
+                Input::Connected(peer) => batch.notices.push(Notice::Connected { peer }),
+                Input::Disconnected(peer) => batch.notices.push(Notice::Disconnected { peer }),
+                Input::Refused(peer) => batch.notices.push(Notice::Refused {
+                    peer,
+                    max_connections: MAX_CONNECTIONS,
+                }),
+                Input::Data { peer, batch: data } => {
+                    batch.bytes += data.bytes;
+                    batch.notices.extend(data.notices);
+                    if data.lines.is_empty() {
+                        continue;
+                    }
+                    if batch.peers.last().is_none_or(|(_, last)| *last != peer) {
+                        batch.peers.push((batch.lines.len(), peer));
+                    }
+                    batch.lines.reserve(data.bytes);
+                    batch.lines.extend(data.lines.iter());
+                }
+            }
+        }
+        Ok(Some(batch))
+    }
+
+    /// The bound address, with the actual port when `:0` was requested.
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+}
+
+fn resolve(host: &str) -> io::Result<SocketAddr> {
+    host.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{host}' did not resolve to an address"),
+        )
+    })
+}
+
+/// Bind `path`, replacing a stale socket file that nothing is listening on.
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(err)
+            if err.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() =>
+        {
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+/// Read `stream` on its own thread, unless `open` already counts
+/// [`MAX_CONNECTIONS`], in which case the stream is dropped.
+fn spawn_connection<S: Read + Send + 'static>(
+    mut stream: S,
+    peer: String,
+    open: &Arc<AtomicUsize>,
+    tx: SyncSender<Input>,
+    mut assembler: LineAssembler,
+) {
+    if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+        open.fetch_sub(1, Ordering::SeqCst);
+        let _ = tx.send(Input::Refused(peer));
+        return;
+    }
+    let open = Arc::clone(open);
+    thread::spawn(move || {
+        read_connection(&mut stream, &peer, &tx, &mut assembler);
+        open.fetch_sub(1, Ordering::SeqCst);
+        let _ = tx.send(Input::Disconnected(peer));
+    });
+}
+
+/// Forward lines from `stream` until it closes or the listener is dropped.
+fn read_connection<S: Read>(
+    stream: &mut S,
+    peer: &str,
+    tx: &SyncSender<Input>,
+    assembler: &mut LineAssembler,
+) {
+    if tx.send(Input::Connected(peer.to_string())).is_err() {
+        return;
+    }
+    let mut chunk = [0_u8; 8192];
+    loop {
+        let batch = match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => Batch::assemble(assembler, &chunk[..n]),
+        };
+        let data = Input::Data {
+            peer: peer.to_string(),
+            batch,
+        };
+        if tx.send(data).is_err() {
+            return;
+        }
+    }
+    if let Some(line) = assembler.finish() {
+        let batch = Batch {
+            lines: Lines::from_iter([line]),
+            ..Batch::default()
+        };
+        let _ = tx.send(Input::Data {
+            peer: peer.to_string(),
+            batch,
+        });
+    }
+}
+
+/// Each datagram is one or more whole lines; a missing final newline is implied.
+/// Nothing carries over between datagrams, so one assembler serves every peer.
+fn receive_datagrams(socket: UdpSocket, tx: SyncSender<Input>, mut assembler: LineAssembler) {
+    let mut datagram = vec![0_u8; 65_535];
+    while let Ok((n, from)) = socket.recv_from(&mut datagram) {
+        let mut batch = Batch::assemble(&mut assembler, &datagram[..n]);
+        batch.lines.extend(assembler.finish());
+        assembler.clear();
+        let data = Input::Data {
+            peer: from.to_string(),
+            batch,
+        };
+        if tx.send(data).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    fn collect_lines(listener: &mut Listener, want: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            let batch = listener
+                .poll(Duration::from_millis(100))
+                .expect("poll")
+                .expect("listener stays open");
+            lines.extend(batch.lines.texts().enumerate().map(
+                |(index, line)| match batch.peer(index) {
+                    Some(peer) => format!("[{peer}] {line}"),
+                    None => line.into_owned(),
+                },
+            ));
+            if lines.len() >= want {
+                break;
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn parses_listen_addresses() {
+        assert_eq!(
+            "udp://127.0.0.1:5140".parse(),
+            Ok(ListenAddr::Udp("127.0.0.1:5140".to_string()))
+        );
+        assert_eq!(
+            "unix:///run/app.sock".parse(),
+            Ok(ListenAddr::Unix(PathBuf::from("/run/app.sock")))
+        );
+        assert!("http://example.com".parse::<ListenAddr>().is_err());
+        assert!("127.0.0.1:5140".parse::<ListenAddr>().is_err());
+        assert_eq!(
+            ListenAddr::Tcp("localhost:1".to_string()).to_string(),
+            "tcp://localhost:1"
+        );
+    }
+
+    #[test]
+    fn tcp_connections_are_labelled_by_peer_and_limited() {
+        let options = FollowOptions {
+            max_line_bytes: 8,
+            ..FollowOptions::default()
+        };
+        let addr = ListenAddr::Tcp("127.0.0.1:0".to_string());
+        let mut listener = Listener::bind(&addr, &options).expect("bind");
+        let local = listener.label().trim_start_matches("tcp://").to_string();
+
+        let mut first = TcpStream::connect(&local).expect("connect");
+        let first_peer = first.local_addr().expect("peer").to_string();
+        first
+            .write_all(b"hello\nthis line is long\n")
+            .expect("send");
+        drop(first);
+
+        let lines = collect_lines(&mut listener, 2);
+        assert_eq!(
+            lines,
+            vec![
+                format!("[{first_peer}] hello"),
+                format!("[{first_peer}] this lin"),
+            ]
+        );
+    }
+
+    #[test]
+    fn udp_datagrams_are_lines_without_trailing_newline() {
+        let addr = ListenAddr::Udp("127.0.0.1:0".to_string());
+        let mut listener = Listener::bind(&addr, &FollowOptions::default()).expect("bind");
+        let local = listener.label().trim_start_matches("udp://").to_string();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").expect("sender");
+        let peer = sender.local_addr().expect("peer").to_string();
+        sender.send_to(b"one", &local).expect("send");
+        sender.send_to(b"two\nthree\n", &local).expect("send");
+
+        assert_eq!(
+            collect_lines(&mut listener, 3),
+            vec![
+                format!("[{peer}] one"),
+                format!("[{peer}] two"),
+                format!("[{peer}] three"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_numbers_connections_and_removes_socket() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("app.sock");
+        let mut listener =
+            Listener::bind(&ListenAddr::Unix(path.clone()), &FollowOptions::default())
+                .expect("bind");
+
+        UnixStream::connect(&path)
+            .expect("connect")
+            .write_all(b"first\n")
+            .expect("send");
+        let lines = collect_lines(&mut listener, 1);
+        assert_eq!(lines, vec!["[unix#1] first".to_string()]);
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
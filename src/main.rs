@@ -1,19 +1,26 @@
+mod app;
 mod cli;
-mod follow;
-mod limits;
-mod output;
+mod config;
+mod keys;
+mod signals;
+mod status;
+mod summary;
 
-use clap::Parser;
-use regex::RegexBuilder;
 use std::fs;
+use std::io;
+use std::time::Duration;
 
-use crate::cli::Args;
-use crate::follow::{follow_file, follow_stdin};
-use crate::output::should_use_color;
+use crate::status::detect_terminal_features;
+use butt::webhook::{Body, Template};
+use butt::{
+    FollowOptions, Follower, LineSource, Listener, Pattern, SystemClock, WebhookOptions,
+    should_use_color,
+};
 
 fn main() {
-    let args = Args::parse();
+    let args = config::load_args();
     let colors_enabled = should_use_color();
+    let terminal = detect_terminal_features(&args);
     let allowed_root = match &args.allowed_root {
         Some(root) => match fs::canonicalize(root) {
             Ok(canonical) => Some(canonical),
@@ -26,10 +33,7 @@ fn main() {
     };
 
     let regex = match &args.regex {
-        Some(pattern) => match RegexBuilder::new(pattern)
-            .case_insensitive(args.regex_case_insensitive)
-            .build()
-        {
+        Some(pattern) => match Pattern::new(pattern, args.regex_case_insensitive) {
             Ok(re) => Some(re),
             Err(err) => {
                 eprintln!("[butt] invalid regex '{pattern}': {err}");
@@ -39,19 +43,102 @@ fn main() {
         None => None,
     };
 
-    let result = match &args.path {
-        Some(path) => follow_file(
+    if let (Some(groups), Some(pattern)) = (&args.group_color, &regex)
+        && let Some(name) = groups.names().find(|name| !pattern.has_group(name))
+    {
+        eprintln!("[butt] invalid --group-color: --regex has no group named '{name}'");
+        std::process::exit(2);
+    }
+
+    let webhook = match webhook_options(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("[butt] invalid --webhook-template: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    signals::install();
+
+    let options = FollowOptions {
+        max_line_bytes: args.max_line_bytes,
+        max_buffer_bytes: args.max_buffer_bytes,
+        encoding: args.encoding,
+        no_follow_symlinks: args.no_follow_symlinks,
+        allowed_root,
+    };
+    let result = if let Some(addr) = &args.listen {
+        match Listener::bind(addr, &options) {
+            Ok(listener) => {
+                eprintln!("[butt] listening on {}", listener.label());
+                app::run(
+                    &args,
+                    listener,
+                    regex,
+                    colors_enabled,
+                    terminal,
+                    webhook,
+                    SystemClock,
+                )
+            }
+            Err(err) => Err(io::Error::new(
+                err.kind(),
+                format!("cannot listen on {addr}: {err}"),
+            )),
+        }
+    } else if let Some(path) = &args.path {
+        app::run(
             &args,
-            path,
-            regex.as_ref(),
+            Follower::file(path, options),
+            regex,
             colors_enabled,
-            allowed_root.as_deref(),
-        ),
-        None => follow_stdin(&args, regex.as_ref(), colors_enabled),
+            terminal,
+            webhook,
+            SystemClock,
+        )
+    } else {
+        app::run(
+            &args,
+            Follower::stdin(options),
+            regex,
+            colors_enabled,
+            terminal,
+            webhook,
+            SystemClock,
+        )
     };
 
     if let Err(err) = result {
         eprintln!("[butt] error: {err}");
         std::process::exit(1);
     }
+    // An interrupted run still flushed cleanly, but is not a success.
+    if let Some(signal) = signals::shutdown_signal() {
+        std::process::exit(128 + signal);
+    }
+}
+
+/// Webhook settings from the command line, reading `--webhook-template @FILE`.
+fn webhook_options(args: &cli::Args) -> Result<Option<WebhookOptions>, String> {
+    if args.webhook.is_none() {
+        return Ok(None);
+    }
+    let body = match &args.webhook_template {
+        Some(template) => {
+            let text = match template.strip_prefix('@') {
+                Some(path) => fs::read_to_string(path)
+                    .map_err(|err| format!("cannot read '{path}': {err}"))?,
+                None => template.clone(),
+            };
+            Body::Template(Template::parse(&text)?)
+        }
+        None => Body::Format(args.webhook_format),
+    };
+    Ok(Some(WebhookOptions {
+        body,
+        batch_window: Duration::from_secs(args.webhook_batch_seconds),
+        max_queue: args.webhook_queue,
+        retries: args.webhook_retries,
+        ..WebhookOptions::default()
+    }))
 }
@@ -0,0 +1,259 @@
+use crate::stats::StreamStats;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Stream counters in the Prometheus text exposition format.
+pub fn render(stats: &StreamStats, now: Instant) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{name}{labels} {value}");
+        }
+    };
+    let plain = |value: u64| [(String::new(), value as f64)];
+
+    metric(
+        "butt_lines_read_total",
+        "counter",
+        "Complete input lines read.",
+        &plain(stats.lines),
+    );
+    metric(
+        "butt_bytes_read_total",
+        "counter",
+        "Bytes read from the input.",
+        &plain(stats.bytes),
+    );
+    metric(
+        "butt_lines_emitted_total",
+        "counter",
+        "Lines printed after throttling.",
+        &plain(stats.emitted),
+    );
+    let by_pattern: Vec<(String, f64)> = stats
+        .urgent_by_pattern
+        .iter()
+        .map(|(pattern, count)| {
+            (
+                format!("{{pattern=\"{}\"}}", escape_label(pattern)),
+                *count as f64,
+            )
+        })
+        .collect();
+    metric(
+        "butt_urgent_matches_total",
+        "counter",
+        "Urgent lines, by the pattern or reason that matched.",
+        &by_pattern,
+    );
+    metric(
+        "butt_truncated_fragments_total",
+        "counter",
+        "Oversized line fragments truncated or dropped (--max-line-bytes).",
+        &plain(stats.truncated),
+    );
+    metric(
+        "butt_buffer_overflows_total",
+        "counter",
+        "Times buffered data was dropped (--max-buffer-bytes).",
+        &plain(stats.buffer_drops),
+    );
+    metric(
+        "butt_rotations_total",
+        "counter",
+        "Times the followed file was reopened after rotation.",
+        &plain(stats.rotations),
+    );
+    metric(
+        "butt_file_truncations_total",
+        "counter",
+        "Times the followed file was truncated.",
+        &plain(stats.file_truncations),
+    );
+    metric(
+        "butt_expectations_missed_total",
+        "counter",
+        "Missed notices for --expect patterns that stopped matching.",
+        &plain(stats.missed_expectations),
+    );
+    metric(
+        "butt_seconds_since_last_input",
+        "gauge",
+        "Seconds since the last input arrived (or since start).",
+        &[(String::new(), stats.since_last_input(now).as_secs_f64())],
+    );
+    metric(
+        "butt_idle",
+        "gauge",
+        "1 while the input has been quiet for at least --idle-seconds.",
+        &plain(u64::from(stats.is_idle(now))),
+    );
+    if let Some(lag) = stats.lag {
+        metric(
+            "butt_lag_seconds",
+            "gauge",
+            "How far the newest timestamped line was behind the wall clock.",
+            &[(String::new(), lag.as_secs_f64())],
+        );
+        metric(
+            "butt_max_lag_seconds",
+            "gauge",
+            "Largest lag seen between a line's timestamp and its arrival.",
+            &[(String::new(), stats.max_lag.as_secs_f64())],
+        );
+    }
+    metric(
+        "butt_uptime_seconds",
+        "gauge",
+        "Seconds since butt started.",
+        &[(
+            String::new(),
+            now.saturating_duration_since(stats.started).as_secs_f64(),
+        )],
+    );
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves `GET /metrics` from a background thread.
+///
+/// The server reports the snapshot last passed to [`update`](Self::update);
+/// time-based gauges are computed when scraped.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stats: Arc<Mutex<Option<StreamStats>>>,
+}
+
+impl MetricsServer {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stats = Arc::new(Mutex::new(None));
+        let shared = Arc::clone(&stats);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // One scrape at a time; a stalled client only delays the next scrape.
+                let _ = respond(stream, &shared);
+            }
+        });
+        Ok(Self { addr, stats })
+    }
+
+    /// The bound address, with the actual port when `:0` was requested.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&self, stats: &StreamStats) {
+        *self.stats.lock().expect("metrics lock") = Some(stats.clone());
+    }
+}
+
+fn respond(stream: TcpStream, stats: &Mutex<Option<StreamStats>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    let (status, content_type, body) = match (method, path) {
+        (Some("GET"), Some("/metrics")) => {
+            let snapshot = stats.lock().expect("metrics lock").clone();
+            let body = snapshot
+                .map(|stats| render(&stats, Instant::now()))
+                .unwrap_or_default();
+            ("200 OK", "text/plain; version=0.0.4", body)
+        }
+        (Some("GET"), Some("/")) => (
+            "200 OK",
+            "text/plain",
+            "butt metrics are at /metrics\n".to_string(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).expect("connect");
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").expect("request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("response");
+        response
+    }
+
+    #[test]
+    fn renders_counters_gauges_and_escaped_pattern_labels() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(start, Some(Duration::from_secs(10)));
+        stats.record_bytes(42, start);
+        stats.record_line(start);
+        stats.record_urgent("say \"hi\"");
+        stats.rotations = 2;
+
+        let text = render(&stats, start + Duration::from_secs(15));
+        assert!(text.contains("# TYPE butt_lines_read_total counter\nbutt_lines_read_total 1\n"));
+        assert!(text.contains("butt_bytes_read_total 42\n"));
+        assert!(text.contains("butt_urgent_matches_total{pattern=\"say \\\"hi\\\"\"} 1\n"));
+        assert!(text.contains("butt_rotations_total 2\n"));
+        assert!(text.contains("butt_seconds_since_last_input 15\n"));
+        assert!(text.contains("butt_idle 1\n"));
+        assert!(!text.contains("butt_lag_seconds"));
+
+        stats.record_lag(Duration::from_secs(120));
+        let text = render(&stats, start + Duration::from_secs(15));
+        assert!(text.contains("butt_lag_seconds 120\n"));
+        assert!(text.contains("butt_max_lag_seconds 120\n"));
+    }
+
+    #[test]
+    fn serves_latest_snapshot_on_metrics_path() {
+        let server = MetricsServer::bind("127.0.0.1:0").expect("bind");
+        let mut stats = StreamStats::new(Instant::now(), None);
+        stats.record_line(Instant::now());
+        server.update(&stats);
+
+        let response = get(server.local_addr(), "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("butt_lines_read_total 1\n"));
+        assert!(response.contains("butt_idle 0\n"));
+
+        assert!(get(server.local_addr(), "/nope").starts_with("HTTP/1.1 404"));
+    }
+}
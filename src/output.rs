@@ -1,37 +1,348 @@
-use crate::cli::HighlightColor;
-use regex::Regex;
-use std::io::{self, IsTerminal};
-
-pub(crate) fn decorate_line(
-    line: &str,
-    regex: Option<&Regex>,
-    color: &HighlightColor,
-    colors_enabled: bool,
-) -> String {
-    if let Some(rgx) = regex {
-        if colors_enabled {
-            highlight_matches(line, rgx, color)
+use crate::ansi::{self, AnsiMode, Piece};
+use crate::pattern::Pattern;
+use crate::style::{ColorSupport, GroupStyles, HighlightScope, Style};
+use crate::text::escape_into;
+use crate::throttle::Event;
+use std::io::{self, IsTerminal, Write};
+use std::ops::Range;
+
+/// Renders events as text, painting regex matches (or whole matching lines)
+/// in a [`Style`] when enabled.
+///
+/// Control characters and bytes that are not valid UTF-8 are written as
+/// `\xNN` escapes (see [`escape_into`]), so a line
+/// cannot take over the terminal. Escape sequences already in the line are
+/// handled according to its [`AnsiMode`].
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    pattern: Option<Pattern>,
+    style: Style,
+    enabled: bool,
+    support: ColorSupport,
+    scope: HighlightScope,
+    /// Styles for named capture groups, painted over the match style.
+    groups: Vec<(String, Style)>,
+    ansi: AnsiMode,
+}
+
+impl Highlighter {
+    /// `enabled` controls whether ANSI colors are written at all; see [`should_use_color`].
+    pub fn new(pattern: Option<Pattern>, style: Style, enabled: bool) -> Self {
+        Self {
+            pattern,
+            style,
+            enabled,
+            support: ColorSupport::default(),
+            scope: HighlightScope::default(),
+            groups: Vec::new(),
+            ansi: AnsiMode::default(),
+        }
+    }
+
+    /// Reduce 24-bit and 256-color styles to what the terminal can show.
+    pub fn with_color_support(mut self, support: ColorSupport) -> Self {
+        self.support = support;
+        self
+    }
+
+    pub fn with_scope(mut self, scope: HighlightScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    pub fn with_group_styles(mut self, groups: GroupStyles) -> Self {
+        self.groups = groups.0;
+        self
+    }
+
+    pub fn with_ansi(mut self, ansi: AnsiMode) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        self.pattern = pattern;
+    }
+
+    /// The line with every match of the pattern painted, or unchanged.
+    pub fn highlight(&self, line: &str) -> String {
+        String::from_utf8_lossy(&self.highlight_bytes(line.as_bytes())).into_owned()
+    }
+
+    /// Like [`highlight`](Self::highlight), for a line that need not be UTF-8.
+    pub fn highlight_bytes(&self, line: &[u8]) -> Vec<u8> {
+        match &self.pattern {
+            Some(pattern) if self.enabled => self.paint(line, &pattern.find_ranges(line)),
+            _ => self.paint(line, &[]),
+        }
+    }
+
+    /// The text butt prints for an event.
+    pub fn render(&self, event: &Event) -> String {
+        String::from_utf8_lossy(&self.render_bytes(event)).into_owned()
+    }
+
+    /// The bytes butt prints for an event. Matches the throttler already
+    /// found are painted as they are; other lines are searched here. A line
+    /// from a named peer is prefixed with `[peer] `.
+    pub fn render_bytes(&self, event: &Event) -> Vec<u8> {
+        match event {
+            Event::Line(line) => {
+                let painted = if self.enabled && !line.matches.is_empty() {
+                    self.paint(&line.bytes, &line.matches)
+                } else {
+                    self.highlight_bytes(&line.bytes)
+                };
+                match &line.peer {
+                    Some(peer) => [format!("[{peer}] ").as_bytes(), &painted].concat(),
+                    None => painted,
+                }
+            }
+            Event::Idle { interval } => {
+                format!("[no output for {} seconds]", interval.as_secs()).into_bytes()
+            }
+            Event::Missed { pattern, within } => format!(
+                "[no line matching '{pattern}' for {} seconds]",
+                within.as_secs()
+            )
+            .into_bytes(),
+        }
+    }
+
+    /// `line`, escaped, with each of `matches` (or all of it, for
+    /// [`HighlightScope::Line`]) wrapped in the highlight style and named
+    /// groups in their own styles.
+    ///
+    /// Color sequences from the input that are kept are tracked, so they are
+    /// restored after each highlight and the highlight is re-applied when one
+    /// appears inside it. A line that leaves them active ends with a reset.
+    fn paint(&self, line: &[u8], matches: &[Range<usize>]) -> Vec<u8> {
+        let mut opens = vec![self.style.sgr(self.support)];
+        opens.extend(self.groups.iter().map(|(_, style)| style.sgr(self.support)));
+        let mut spans = self.spans(line, matches);
+        spans.retain(|(_, style)| !opens[*style].is_empty());
+        let mut painter = Painter {
+            out: Vec::with_capacity(line.len() + spans.len() * 9),
+            opens: &opens,
+            spans: &spans,
+            painting: false,
+            active: Vec::new(),
+        };
+        for piece in ansi::pieces(line) {
+            match piece {
+                Piece::Text(span) => painter.text(line, span),
+                Piece::Escape { span, sgr } => {
+                    painter.seek(span.start);
+                    let sequence = &line[span];
+                    match self.ansi {
+                        AnsiMode::Strip => {}
+                        _ if !sgr => escape_into(&mut painter.out, sequence),
+                        AnsiMode::Keep => painter.sequence(sequence),
+                        AnsiMode::Sanitize if self.enabled => painter.sequence(sequence),
+                        AnsiMode::Sanitize => {}
+                    }
+                }
+            }
+        }
+        painter.finish()
+    }
+
+    /// What to paint in `line`, in order: the matches (or the whole line) in
+    /// style `0`, split around named groups painted in style `i + 1` for
+    /// group style `i`.
+    fn spans(&self, line: &[u8], matches: &[Range<usize>]) -> Vec<Span> {
+        if matches.is_empty() {
+            return Vec::new();
+        }
+        let whole = 0..line.len();
+        let bases = match self.scope {
+            HighlightScope::Line => std::slice::from_ref(&whole),
+            HighlightScope::Match => matches,
+        };
+        let mut groups = self.group_spans(line).into_iter().peekable();
+        let mut spans = Vec::with_capacity(bases.len());
+        for base in bases {
+            let mut at = base.start;
+            while let Some((group, style)) = groups.next_if(|(group, _)| group.start < base.end) {
+                let (start, end) = (group.start.max(at), group.end.min(base.end));
+                if start >= end {
+                    continue;
+                }
+                if at < start {
+                    spans.push((at..start, 0));
+                }
+                spans.push((start..end, style));
+                at = end;
+            }
+            if at < base.end {
+                spans.push((at..base.end, 0));
+            }
+        }
+        spans
+    }
+
+    /// Named groups with a style, in every match, sorted by position.
+    fn group_spans(&self, line: &[u8]) -> Vec<Span> {
+        let Some(pattern) = self.pattern.as_ref().filter(|_| !self.groups.is_empty()) else {
+            return Vec::new();
+        };
+        let mut spans: Vec<Span> = pattern
+            .captures_all(line)
+            .iter()
+            .flat_map(|captures| {
+                self.groups
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, (name, _))| {
+                        Some((captures.name(name)?.range(), index + 1))
+                    })
+            })
+            .collect();
+        spans.sort_by_key(|(range, _)| range.start);
+        spans
+    }
+}
+
+/// A range of a line and the index of the style to paint it in.
+type Span = (Range<usize>, usize);
+
+/// Output state while painting one line.
+struct Painter<'a> {
+    out: Vec<u8>,
+    /// The sequences that start each highlight style.
+    opens: &'a [String],
+    /// Spans not yet passed.
+    spans: &'a [Span],
+    painting: bool,
+    /// Input color sequences in effect since the last reset.
+    active: Vec<u8>,
+}
+
+impl Painter<'_> {
+    /// Drop spans that end by `at`, closing the highlight if one ended.
+    fn seek(&mut self, at: usize) {
+        while let Some((range, _)) = self.spans.first()
+            && range.end <= at
+        {
+            self.spans = &self.spans[1..];
+            if self.painting {
+                self.painting = false;
+                self.out.extend_from_slice(b"\x1b[0m");
+                self.out.extend_from_slice(&self.active);
+            }
+        }
+    }
+
+    /// Start painting the current span, again if `force`.
+    fn open(&mut self, force: bool) {
+        if let Some((_, style)) = self.spans.first()
+            && (force || !self.painting)
+        {
+            self.painting = true;
+            self.out.extend_from_slice(self.opens[*style].as_bytes());
+        }
+    }
+
+    fn text(&mut self, line: &[u8], span: Range<usize>) {
+        let mut at = span.start;
+        while at < span.end {
+            self.seek(at);
+            let next = match self.spans.first() {
+                Some((range, _)) if range.start <= at => {
+                    let end = range.end;
+                    self.open(false);
+                    end
+                }
+                Some((range, _)) => range.start,
+                None => span.end,
+            };
+            let next = next.min(span.end);
+            escape_into(&mut self.out, &line[at..next]);
+            at = next;
+        }
+    }
+
+    /// Write an input color sequence as is.
+    fn sequence(&mut self, sequence: &[u8]) {
+        self.out.extend_from_slice(sequence);
+        if ansi::is_reset(sequence) {
+            self.active.clear();
         } else {
-            line.to_string()
+            self.active.extend_from_slice(sequence);
+        }
+        if self.painting {
+            self.open(true);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.seek(usize::MAX);
+        if !self.active.is_empty() {
+            self.out.extend_from_slice(b"\x1b[0m");
         }
-    } else {
-        line.to_string()
+        self.out
+    }
+}
+
+/// Destination for events produced by a [`Throttler`](crate::Throttler).
+pub trait Sink {
+    fn emit(&mut self, event: &Event);
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn emit(&mut self, event: &Event) {
+        (**self).emit(event);
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn emit(&mut self, event: &Event) {
+        (**self).emit(event);
+    }
+}
+
+/// Collects events, which is handy in tests.
+impl Sink for Vec<Event> {
+    fn emit(&mut self, event: &Event) {
+        self.push(event.clone());
     }
 }
 
-pub(crate) fn highlight_matches(line: &str, regex: &Regex, color: &HighlightColor) -> String {
-    let mut out = String::with_capacity(line.len());
-    let mut last = 0;
-    for mat in regex.find_iter(line) {
-        out.push_str(&line[last..mat.start()]);
-        out.push_str(&color.paint(mat.as_str()));
-        last = mat.end();
+/// Writes each event as one line of text, flushing after every event.
+pub struct WriterSink<W: Write> {
+    writer: W,
+    highlighter: Highlighter,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W, highlighter: Highlighter) -> Self {
+        Self {
+            writer,
+            highlighter,
+        }
+    }
+
+    pub fn highlighter_mut(&mut self) -> &mut Highlighter {
+        &mut self.highlighter
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
     }
-    out.push_str(&line[last..]);
-    out
 }
 
-pub(crate) fn should_use_color() -> bool {
+impl<W: Write> Sink for WriterSink<W> {
+    fn emit(&mut self, event: &Event) {
+        let mut text = self.highlighter.render_bytes(event);
+        text.push(b'\n');
+        let _ = self.writer.write_all(&text);
+        let _ = self.writer.flush();
+    }
+}
+
+/// Whether to write ANSI colors to stdout, honoring `NO_COLOR`, `CLICOLOR` and
+/// `CLICOLOR_FORCE`.
+pub fn should_use_color() -> bool {
     if std::env::var_os("NO_COLOR").is_some() {
         return false;
     }
@@ -48,6 +359,11 @@ pub(crate) fn should_use_color() -> bool {
         return false;
     }
 
+    stdout_is_interactive()
+}
+
+/// Whether stdout is a terminal that understands cursor control.
+pub fn stdout_is_interactive() -> bool {
     if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
         return false;
     }
@@ -58,19 +374,117 @@ pub(crate) fn should_use_color() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli::HighlightColor;
+    use crate::style::Color;
+    use crate::throttle::Line;
+    use std::time::Duration;
 
     #[test]
     fn highlights_all_matches() {
-        let re = Regex::new("ERR").expect("regex should compile");
-        let out = highlight_matches("x ERR y ERR z", &re, &HighlightColor::Red);
+        let re = Pattern::new("ERR", false).expect("pattern should compile");
+        let highlighter = Highlighter::new(Some(re), Style::fg(Color::Basic(1)), true);
+        let out = highlighter.highlight("x ERR y ERR z");
         assert!(out.contains("\x1b[31mERR\x1b[0m"));
         assert_eq!(out.matches("\x1b[31mERR\x1b[0m").count(), 2);
     }
 
+    #[test]
+    fn reuses_match_positions_and_escapes_the_rest() {
+        // The highlighter has no pattern, so the painted range can only come from the line.
+        let highlighter = Highlighter::new(None, Style::fg(Color::Basic(1)), true);
+        let mut line = Line::new(b"\xff ERR\x1b]0;pwned\x07".to_vec(), true);
+        line.matches.push(2..5);
+        assert_eq!(
+            highlighter.render(&Event::Line(line)),
+            "\\xff \x1b[31mERR\x1b[0m\\x1b]0;pwned\\x07"
+        );
+    }
+
+    #[test]
+    fn restores_input_colors_around_highlights() {
+        let re = Pattern::new("ERR", false).expect("pattern should compile");
+        let highlighter =
+            Highlighter::new(Some(re), Style::fg(Color::Basic(1)), true).with_ansi(AnsiMode::Keep);
+        assert_eq!(
+            highlighter.highlight("\x1b[1mbold ERR\x1b[0m ok \x1b[32mE\x1b[4mRR"),
+            "\x1b[1mbold \x1b[31mERR\x1b[0m\x1b[1m\x1b[0m ok \x1b[32mE\x1b[4mRR\x1b[0m"
+        );
+        let highlighter = Highlighter::new(
+            Some(Pattern::new("E.*RR", false).expect("pattern should compile")),
+            Style::fg(Color::Basic(1)),
+            true,
+        )
+        .with_ansi(AnsiMode::Keep);
+        assert_eq!(
+            highlighter.highlight("E\x1b[4mRR"),
+            "\x1b[31mE\x1b[4m\x1b[31mRR\x1b[0m\x1b[4m\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn keep_escapes_sequences_other_than_colors() {
+        let line = "\x1b]0;title\x07\x1b[2J\x1b[33mwarn\x1b[0m";
+        let expected = "\\x1b]0;title\\x07\\x1b[2J\x1b[33mwarn\x1b[0m";
+        for enabled in [true, false] {
+            let highlighter = Highlighter::new(None, Style::fg(Color::Basic(1)), enabled)
+                .with_ansi(AnsiMode::Keep);
+            assert_eq!(highlighter.highlight(line), expected);
+        }
+    }
+
+    #[test]
+    fn sanitize_keeps_colors_only_when_enabled() {
+        let line = "\x1b[33mwarn\x1b[0m \x1b[2Kdone";
+        let colored = Highlighter::new(None, Style::fg(Color::Basic(1)), true);
+        assert_eq!(colored.highlight(line), "\x1b[33mwarn\x1b[0m \\x1b[2Kdone");
+        let plain = Highlighter::new(None, Style::fg(Color::Basic(1)), false);
+        assert_eq!(plain.highlight(line), "warn \\x1b[2Kdone");
+        let stripped = plain.with_ansi(AnsiMode::Strip);
+        assert_eq!(stripped.highlight(line), "warn done");
+    }
+
+    #[test]
+    fn paints_named_groups_in_their_own_styles() {
+        let re = Pattern::new(r"(?P<status>5\d\d) took (?P<latency>\d+ms)", false)
+            .expect("pattern should compile");
+        let groups = "status=green,latency=cyan".parse().expect("groups");
+        let highlighter =
+            Highlighter::new(Some(re), Style::fg(Color::Basic(1)), true).with_group_styles(groups);
+        assert_eq!(
+            highlighter.highlight("GET 503 took 12ms done"),
+            "GET \x1b[32m503\x1b[0m\x1b[31m took \x1b[0m\x1b[36m12ms\x1b[0m done"
+        );
+        let highlighter = highlighter.with_scope(HighlightScope::Line);
+        assert_eq!(
+            highlighter.highlight("GET 503 took 12ms"),
+            "\x1b[31mGET \x1b[0m\x1b[32m503\x1b[0m\x1b[31m took \x1b[0m\x1b[36m12ms\x1b[0m"
+        );
+    }
+
     #[test]
     fn decorates_plain_when_no_regex() {
-        let out = decorate_line("plain text", None, &HighlightColor::Yellow, true);
-        assert_eq!(out, "plain text");
+        let highlighter = Highlighter::new(None, Style::fg(Color::Basic(3)), true);
+        assert_eq!(highlighter.highlight("plain text"), "plain text");
+    }
+
+    #[test]
+    fn writer_sink_renders_lines_and_notices() {
+        let re = Pattern::new("ERR", false).expect("pattern should compile");
+        let mut sink = WriterSink::new(
+            Vec::new(),
+            Highlighter::new(Some(re), Style::fg(Color::Basic(2)), false),
+        );
+        sink.emit(&Event::Line(Line::new("an ERR", true)));
+        sink.emit(&Event::Idle {
+            interval: Duration::from_secs(30),
+        });
+        sink.emit(&Event::Missed {
+            pattern: "health ok".to_string(),
+            within: Duration::from_secs(60),
+        });
+        let out = String::from_utf8(sink.into_inner()).expect("utf8");
+        assert_eq!(
+            out,
+            "an ERR\n[no output for 30 seconds]\n[no line matching 'health ok' for 60 seconds]\n"
+        );
     }
 }
@@ -0,0 +1,210 @@
+use aho_corasick::AhoCorasick;
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Prefix literal sets larger than this are not worth a separate scan.
+const MAX_PREFILTER_LITERALS: usize = 64;
+
+/// The urgent/highlight pattern, matched against raw line bytes.
+///
+/// Every match has to start with one of a small set of literals (`ERROR` or
+/// `FATAL` for `ERROR|FATAL`, `status=5` for `status=5\d\d`). When there is
+/// such a set, lines containing none of them are rejected with one
+/// Aho-Corasick scan before the regex runs.
+#[derive(Clone)]
+pub struct Pattern {
+    regex: Regex,
+    prefilter: Option<AhoCorasick>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(Self {
+            prefilter: prefilter(pattern, case_insensitive),
+            regex,
+        })
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    /// Whether a literal prefilter is used; see [`Pattern`].
+    pub fn has_prefilter(&self) -> bool {
+        self.prefilter.is_some()
+    }
+
+    /// Whether the line could match at all, without running the regex.
+    pub fn is_candidate(&self, line: &[u8]) -> bool {
+        self.prefilter
+            .as_ref()
+            .is_none_or(|prefilter| prefilter.is_match(line))
+    }
+
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.is_candidate(line) && self.regex.is_match(line)
+    }
+
+    /// Byte ranges of every match in `line`, empty when there is none.
+    pub fn find_ranges(&self, line: &[u8]) -> Vec<Range<usize>> {
+        if !self.is_candidate(line) {
+            return Vec::new();
+        }
+        self.regex
+            .find_iter(line)
+            .map(|found| found.range())
+            .collect()
+    }
+
+    pub fn captures<'h>(&self, line: &'h [u8]) -> Option<Captures<'h>> {
+        if !self.is_candidate(line) {
+            return None;
+        }
+        self.regex.captures(line)
+    }
+
+    /// Captures for every match in `line`.
+    pub fn captures_all<'h>(&self, line: &'h [u8]) -> Vec<Captures<'h>> {
+        if !self.is_candidate(line) {
+            return Vec::new();
+        }
+        self.regex.captures_iter(line).collect()
+    }
+
+    /// Name and text of each named group that took part in the first match.
+    pub fn named_groups(&self, line: &[u8]) -> Vec<(String, String)> {
+        let Some(captures) = self.captures(line) else {
+            return Vec::new();
+        };
+        self.regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| {
+                let group = captures.name(name)?;
+                Some((
+                    name.to_string(),
+                    String::from_utf8_lossy(group.as_bytes()).into_owned(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Whether the pattern has a capture group called `name`.
+    pub fn has_group(&self, name: &str) -> bool {
+        self.regex
+            .capture_names()
+            .flatten()
+            .any(|group| group == name)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pattern")
+            .field("pattern", &self.as_str())
+            .field("prefilter", &self.has_prefilter())
+            .finish()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses a case-sensitive pattern.
+impl FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::new(pattern, false)
+    }
+}
+
+/// A scanner for the literals every match must start with, if they form a
+/// small finite set that excludes the empty string.
+fn prefilter(pattern: &str, case_insensitive: bool) -> Option<AhoCorasick> {
+    let hir = regex_syntax::ParserBuilder::new()
+        .case_insensitive(case_insensitive)
+        .build()
+        .parse(pattern)
+        .ok()?;
+    let mut prefixes = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+    prefixes.optimize_for_prefix_by_preference();
+    let literals = prefixes.literals()?;
+    if literals.is_empty()
+        || literals.len() > MAX_PREFILTER_LITERALS
+        || literals.iter().any(|literal| literal.as_bytes().is_empty())
+    {
+        return None;
+    }
+    AhoCorasick::new(literals.iter().map(|literal| literal.as_bytes())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefilter_rejects_lines_without_a_required_prefix() {
+        let pattern = Pattern::new(r"ERROR|status=5\d\d", false).expect("pattern");
+        assert!(pattern.has_prefilter());
+        assert!(!pattern.is_candidate(b"INFO status=200"));
+        assert!(pattern.is_candidate(b"INFO status=503"));
+        assert!(pattern.is_match(b"x ERROR y"));
+        assert!(!pattern.is_match(b"status=5xx"));
+    }
+
+    #[test]
+    fn no_prefilter_when_a_match_can_start_anywhere() {
+        assert!(
+            !Pattern::new(r"\d+ ms", false)
+                .expect("pattern")
+                .has_prefilter()
+        );
+        assert!(!Pattern::new(r"a*", false).expect("pattern").has_prefilter());
+    }
+
+    #[test]
+    fn case_insensitive_prefilter_accepts_any_case() {
+        let pattern = Pattern::new("error", true).expect("pattern");
+        assert!(pattern.is_match(b"an ErRoR here"));
+        assert!(!pattern.is_match(b"all good"));
+    }
+
+    #[test]
+    fn matches_raw_bytes_that_are_not_utf8() {
+        let pattern: Pattern = "ERR".parse().expect("pattern");
+        let line = b"\xff\xfe ERR \xc3";
+        assert_eq!(pattern.find_ranges(line), vec![3..6]);
+        assert!(pattern.captures(line).is_some());
+    }
+
+    #[test]
+    fn reports_named_groups_of_the_first_match() {
+        let pattern =
+            Pattern::new(r"(?P<status>5\d\d) (?P<latency>\d+ms)?", false).expect("pattern");
+        assert!(pattern.has_group("latency"));
+        assert!(!pattern.has_group("host"));
+        assert_eq!(
+            pattern.named_groups(b"GET 503 12ms, 504 9ms"),
+            vec![
+                ("status".to_string(), "503".to_string()),
+                ("latency".to_string(), "12ms".to_string())
+            ]
+        );
+        assert_eq!(pattern.captures_all(b"503 1ms 504 x").len(), 2);
+    }
+}
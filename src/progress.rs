@@ -0,0 +1,345 @@
+use crate::pattern::Pattern;
+use regex::bytes::Captures;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Progress readings older than this no longer count towards the rate.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Where a progress line says a job is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub current: f64,
+    /// The amount of work in total, when the line says.
+    pub total: Option<f64>,
+}
+
+impl Reading {
+    /// How far along the job is, from 0 to 100.
+    pub fn percent(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0.0)
+            .map(|total| self.current / total * 100.0)
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.current)?;
+        if let Some(total) = self.total {
+            write!(f, "/{total}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A change in whether progress is moving, reported once per change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressChange {
+    /// The reading has not advanced for the given time.
+    Stalled(Reading, Duration),
+    /// The reading advanced again after a stall.
+    Resumed(Reading),
+}
+
+/// Reads progress lines such as `Processed 4500/10000 (45%)` and works out
+/// how fast the job is going, when it will finish and whether it has stalled.
+///
+/// The pattern's groups name the numbers: `current` and `total`, or
+/// `percent` (out of 100). Without named groups the first group is the
+/// current count and the second, if any, the total. Thousands separators
+/// (`4,500`, `4_500`) are ignored.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    pattern: Pattern,
+    stall_after: Option<Duration>,
+    samples: VecDeque<(Instant, f64)>,
+    latest: Option<Reading>,
+    last_advance: Option<Instant>,
+    stalled: bool,
+}
+
+impl ProgressTracker {
+    /// Track lines matching `pattern`, which needs at least one capture group.
+    pub fn new(pattern: Pattern) -> Result<Self, String> {
+        if pattern.regex().captures_len() < 2 {
+            return Err(format!(
+                "'{pattern}' has no capture group for the current count"
+            ));
+        }
+        Ok(Self {
+            pattern,
+            stall_after: None,
+            samples: VecDeque::new(),
+            latest: None,
+            last_advance: None,
+            stalled: false,
+        })
+    }
+
+    /// Report a stall when the reading has not advanced for `stall_after`.
+    pub fn with_stall_after(mut self, stall_after: Option<Duration>) -> Self {
+        self.stall_after = stall_after;
+        self
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// The newest reading seen.
+    pub fn latest(&self) -> Option<Reading> {
+        self.latest
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// The progress `line` reports, if it is a progress line.
+    pub fn reading(&self, line: &[u8]) -> Option<Reading> {
+        let captures = self.pattern.captures(line)?;
+        if self.pattern.has_group("percent") {
+            return Some(Reading {
+                current: number(&captures, "percent")?,
+                total: Some(100.0),
+            });
+        }
+        let (current, total) = if self.pattern.has_group("current") {
+            (number(&captures, "current")?, number(&captures, "total"))
+        } else {
+            (number_at(&captures, 1)?, number_at(&captures, 2))
+        };
+        Some(Reading { current, total })
+    }
+
+    /// Take a reading from `line`. Returns [`ProgressChange::Resumed`] when it
+    /// moves a stalled job forward. A reading below the last one starts a new
+    /// run, so the rate is measured afresh.
+    pub fn observe(&mut self, line: &[u8], now: Instant) -> Option<ProgressChange> {
+        let reading = self.reading(line)?;
+        let previous = self.latest.replace(reading);
+        match previous {
+            Some(previous) if reading.current == previous.current => return None,
+            Some(previous) if reading.current < previous.current => self.samples.clear(),
+            _ => {}
+        }
+        self.samples.push_back((now, reading.current));
+        while self.samples.len() > 2
+            && self
+                .samples
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        self.last_advance = Some(now);
+        if std::mem::take(&mut self.stalled) {
+            Some(ProgressChange::Resumed(reading))
+        } else {
+            None
+        }
+    }
+
+    /// Returns [`ProgressChange::Stalled`] once the reading has stood still
+    /// for the stall time; call it regularly.
+    pub fn check_stall(&mut self, now: Instant) -> Option<ProgressChange> {
+        let stall_after = self.stall_after?;
+        let (latest, last_advance) = self.latest.zip(self.last_advance)?;
+        let still = now.saturating_duration_since(last_advance);
+        if self.stalled || still < stall_after || latest.percent() >= Some(100.0) {
+            return None;
+        }
+        self.stalled = true;
+        Some(ProgressChange::Stalled(latest, still))
+    }
+
+    /// Units of work per second over the last minute, measured up to `now`
+    /// so the rate falls while nothing moves.
+    pub fn rate(&self, now: Instant) -> Option<f64> {
+        let (first_at, first) = *self.samples.front()?;
+        let (_, last) = *self.samples.back()?;
+        let elapsed = now.saturating_duration_since(first_at).as_secs_f64();
+        (last > first && elapsed > 0.0).then(|| (last - first) / elapsed)
+    }
+
+    /// Time left until `reading` reaches its total at the current rate.
+    pub fn eta(&self, reading: Reading, now: Instant) -> Option<Duration> {
+        let remaining = (reading.total? - reading.current).max(0.0);
+        let seconds = remaining / self.rate(now)?;
+        Duration::try_from_secs_f64(seconds).ok()
+    }
+
+    /// Percentage, rate and ETA for a progress line, such as
+    /// `[45%, 12.5/s, ETA 7m 20s]`, or `None` for other lines.
+    pub fn annotate(&self, line: &[u8], now: Instant) -> Option<String> {
+        let reading = self.reading(line)?;
+        let mut parts = Vec::new();
+        if let Some(percent) = reading.percent() {
+            parts.push(format!("{percent:.0}%"));
+        }
+        if let Some(rate) = self.rate(now) {
+            parts.push(format_rate(rate));
+        }
+        if let Some(eta) = self.eta(reading, now) {
+            parts.push(format!("ETA {}", format_duration(eta)));
+        }
+        (!parts.is_empty()).then(|| format!("[{}]", parts.join(", ")))
+    }
+}
+
+/// Parses a progress regex, such as `Processed (\d+)/(\d+)`.
+impl FromStr for ProgressTracker {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let pattern = Pattern::new(pattern, false).map_err(|err| err.to_string())?;
+        Self::new(pattern)
+    }
+}
+
+fn number(captures: &Captures<'_>, name: &str) -> Option<f64> {
+    parse_number(captures.name(name)?.as_bytes())
+}
+
+fn number_at(captures: &Captures<'_>, index: usize) -> Option<f64> {
+    parse_number(captures.get(index)?.as_bytes())
+}
+
+fn parse_number(bytes: &[u8]) -> Option<f64> {
+    let digits: String = std::str::from_utf8(bytes)
+        .ok()?
+        .chars()
+        .filter(|c| !matches!(c, ',' | '_'))
+        .collect();
+    digits
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+/// A rate per second, or per minute or hour when it is slow.
+pub fn format_rate(per_second: f64) -> String {
+    let (rate, unit) = if per_second >= 1.0 {
+        (per_second, "s")
+    } else if per_second * 60.0 >= 1.0 {
+        (per_second * 60.0, "min")
+    } else {
+        (per_second * 3600.0, "h")
+    };
+    if rate >= 100.0 {
+        format!("{rate:.0}/{unit}")
+    } else {
+        format!("{rate:.1}/{unit}")
+    }
+}
+
+/// A duration in its two largest units, e.g. `1h 05m` or `7m 20s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, _) => format!("{h}h {m:02}m"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(pattern: &str) -> ProgressTracker {
+        pattern.parse().expect("tracker")
+    }
+
+    #[test]
+    fn reads_numbered_and_named_groups() {
+        let positional = tracker(r"Processed ([\d,]+)/([\d,]+)");
+        assert_eq!(
+            positional.reading(b"Processed 4,500/10,000 (45%)"),
+            Some(Reading {
+                current: 4500.0,
+                total: Some(10000.0)
+            })
+        );
+        assert_eq!(positional.reading(b"starting up"), None);
+
+        let named = tracker(r"of (?P<total>\d+).*done (?P<current>\d+)");
+        let reading = named.reading(b"batch of 200, done 50").expect("reading");
+        assert_eq!(reading.total, Some(200.0));
+        assert_eq!(reading.percent(), Some(25.0));
+
+        let percent = tracker(r"(?P<percent>\d+(?:\.\d+)?)%");
+        assert_eq!(
+            percent.reading(b"upload 37.5% complete").map(|r| r.current),
+            Some(37.5)
+        );
+
+        assert!(r"Processed \d+".parse::<ProgressTracker>().is_err());
+        assert!("(".parse::<ProgressTracker>().is_err());
+    }
+
+    #[test]
+    fn computes_rate_and_eta() {
+        let start = Instant::now();
+        let mut progress = tracker(r"(\d+)/(\d+)");
+        progress.observe(b"0/1000", start);
+        progress.observe(b"100/1000", start + Duration::from_secs(10));
+        let now = start + Duration::from_secs(10);
+        assert_eq!(progress.rate(now), Some(10.0));
+        assert_eq!(
+            progress.annotate(b"100/1000", now).as_deref(),
+            Some("[10%, 10.0/s, ETA 1m 30s]")
+        );
+        assert_eq!(progress.annotate(b"unrelated", now), None);
+
+        // A smaller number is a new run, measured from scratch.
+        progress.observe(b"5/1000", now + Duration::from_secs(1));
+        assert_eq!(progress.rate(now + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn reports_a_stall_once_and_the_resume() {
+        let start = Instant::now();
+        let mut progress = tracker(r"(\d+)/(\d+)").with_stall_after(Some(Duration::from_secs(30)));
+        assert_eq!(progress.check_stall(start), None);
+        progress.observe(b"10/100", start);
+        // Chatty output that repeats the same number does not count as progress.
+        progress.observe(b"10/100", start + Duration::from_secs(20));
+        assert_eq!(progress.check_stall(start + Duration::from_secs(29)), None);
+        let reading = Reading {
+            current: 10.0,
+            total: Some(100.0),
+        };
+        assert_eq!(
+            progress.check_stall(start + Duration::from_secs(31)),
+            Some(ProgressChange::Stalled(reading, Duration::from_secs(31)))
+        );
+        assert_eq!(progress.check_stall(start + Duration::from_secs(60)), None);
+        assert!(progress.is_stalled());
+        assert_eq!(
+            progress.observe(b"11/100", start + Duration::from_secs(61)),
+            Some(ProgressChange::Resumed(Reading {
+                current: 11.0,
+                total: Some(100.0)
+            }))
+        );
+
+        // A finished job is not stalled.
+        progress.observe(b"100/100", start + Duration::from_secs(62));
+        assert_eq!(progress.check_stall(start + Duration::from_secs(200)), None);
+    }
+
+    #[test]
+    fn formats_rates_and_durations() {
+        assert_eq!(format_rate(250.4), "250/s");
+        assert_eq!(format_rate(0.5), "30.0/min");
+        assert_eq!(format_rate(0.001), "3.6/h");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(440)), "7m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 05m");
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// The signal that asked for shutdown, or `0`.
+static SHUTDOWN_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static SUMMARY_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Route SIGINT/SIGTERM to a clean shutdown and SIGUSR1 to a summary request.
+///
+/// A second SIGINT/SIGTERM while shutdown is pending exits immediately, with
+/// the usual `128 + signal` status.
+#[cfg(unix)]
+pub(crate) fn install() {
+    extern "C" fn on_shutdown(signal: libc::c_int) {
+        if SHUTDOWN_SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+            crate::keys::restore_terminal();
+            // SAFETY: _exit is async-signal-safe.
+            unsafe { libc::_exit(128 + signal) };
+        }
+    }
+
+    extern "C" fn on_summary(_signal: libc::c_int) {
+        SUMMARY_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    let shutdown = on_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    let summary = on_summary as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handlers only touch atomics and async-signal-safe calls.
+    unsafe {
+        libc::signal(libc::SIGINT, shutdown);
+        libc::signal(libc::SIGTERM, shutdown);
+        libc::signal(libc::SIGUSR1, summary);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn install() {}
+
+pub(crate) fn shutdown_requested() -> bool {
+    shutdown_signal().is_some()
+}
+
+/// The SIGINT or SIGTERM that asked for shutdown, if one did.
+pub(crate) fn shutdown_signal() -> Option<i32> {
+    Some(SHUTDOWN_SIGNAL.load(Ordering::SeqCst)).filter(|signal| *signal != 0)
+}
+
+pub(crate) fn take_summary_request() -> bool {
+    SUMMARY_REQUESTED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,265 @@
+use crate::clock::Clock;
+use crate::follow::LineSource;
+use crate::output::Sink;
+use crate::pattern::Pattern;
+use crate::throttle::{Event, Expectation, ThrottleConfig, Throttler};
+use std::borrow::Cow;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so one handle can drive a [`Throttler`] while
+/// another is passed to a [`Follower`](crate::Follower), whose poll waits
+/// then advance the clock instead of sleeping.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            offset: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.offset.lock().expect("virtual clock lock") += by;
+    }
+
+    /// Simulated time since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().expect("virtual clock lock")
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Records events with the simulated time they were emitted at.
+struct Recorder {
+    clock: VirtualClock,
+    events: Vec<(Duration, Event)>,
+}
+
+impl Sink for Recorder {
+    fn emit(&mut self, event: &Event) {
+        self.events.push((self.clock.elapsed(), event.clone()));
+    }
+}
+
+/// Runs a [`Throttler`] through a scripted scenario in simulated time.
+///
+/// Time moves in steps of the tick interval (200ms by default, like
+/// `--poll-millis`), and the throttler is ticked at every step just as the
+/// follow loop would.
+///
+/// ```
+/// use butt::sim::Simulation;
+/// use butt::ThrottleConfig;
+/// use std::time::Duration;
+///
+/// let config = ThrottleConfig::new(Duration::from_secs(6)).idle_interval(Duration::from_secs(7));
+/// let mut sim = Simulation::new(config);
+/// sim.feed_spread((0..100).map(|i| format!("line {i}")), Duration::from_secs(12));
+/// sim.advance(Duration::from_secs(7));
+/// assert_eq!(sim.emitted_lines().len(), 2);
+/// assert_eq!(sim.idle_notices(), 1);
+/// ```
+pub struct Simulation {
+    clock: VirtualClock,
+    throttler: Throttler<VirtualClock>,
+    recorder: Recorder,
+    tick: Duration,
+    next_tick: Duration,
+}
+
+impl Simulation {
+    pub fn new(config: ThrottleConfig) -> Self {
+        let clock = VirtualClock::new();
+        let tick = Duration::from_millis(200);
+        Self {
+            throttler: Throttler::with_clock(config, clock.clone()),
+            recorder: Recorder {
+                clock: clock.clone(),
+                events: Vec::new(),
+            },
+            clock,
+            tick,
+            next_tick: tick,
+        }
+    }
+
+    pub fn with_pattern(mut self, pattern: Option<Pattern>) -> Self {
+        self.throttler.set_pattern(pattern);
+        self
+    }
+
+    /// Watch for `--expect` patterns, with their windows starting now.
+    pub fn with_expectations(mut self, expectations: Vec<Expectation>) -> Self {
+        self.throttler = self.throttler.with_expectations(expectations);
+        self
+    }
+
+    /// How often the throttler is ticked while time advances.
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        assert!(!tick.is_zero(), "tick interval must be positive");
+        self.tick = tick;
+        self.next_tick = self.clock.elapsed() + tick;
+        self
+    }
+
+    /// A handle to the simulated clock, e.g. for a [`Follower`](crate::Follower).
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    pub fn throttler(&self) -> &Throttler<VirtualClock> {
+        &self.throttler
+    }
+
+    pub fn throttler_mut(&mut self) -> &mut Throttler<VirtualClock> {
+        &mut self.throttler
+    }
+
+    /// Move time forward, ticking the throttler at every step on the way.
+    pub fn advance(&mut self, by: Duration) {
+        let end = self.clock.elapsed() + by;
+        while self.next_tick <= end {
+            self.clock.advance(self.next_tick - self.clock.elapsed());
+            self.throttler.tick(&mut self.recorder);
+            self.next_tick += self.tick;
+        }
+        self.clock.advance(end - self.clock.elapsed());
+    }
+
+    /// Feed one line at the current simulated time.
+    pub fn feed(&mut self, line: impl Into<String>) {
+        self.throttler.observe(line.into(), &mut self.recorder);
+    }
+
+    /// Feed lines evenly spaced over `over`, starting now; returns once `over` has passed.
+    pub fn feed_spread<I>(&mut self, lines: I, over: Duration)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let lines: Vec<String> = lines.into_iter().map(Into::into).collect();
+        let start = self.clock.elapsed();
+        let count = lines.len().max(1) as u32;
+        for (i, line) in lines.into_iter().enumerate() {
+            let at = start + over * i as u32 / count;
+            self.advance(at.saturating_sub(self.clock.elapsed()));
+            self.feed(line);
+        }
+        self.advance((start + over).saturating_sub(self.clock.elapsed()));
+    }
+
+    /// One round of the follow loop: tick, then poll `source` for up to `wait`.
+    ///
+    /// Returns `false` once the source has ended. A [`Follower`](crate::Follower)
+    /// built with [`clock`](Self::clock) advances simulated time while it waits;
+    /// reader-backed sources wait in real time instead.
+    pub fn poll_source<S: LineSource + ?Sized>(
+        &mut self,
+        source: &mut S,
+        wait: Duration,
+    ) -> io::Result<bool> {
+        self.throttler.tick(&mut self.recorder);
+        let Some(batch) = source.poll(wait)? else {
+            return Ok(false);
+        };
+        let now = self.clock.now();
+        let stats = self.throttler.stats_mut();
+        if batch.bytes > 0 {
+            stats.record_bytes(batch.bytes, now);
+        }
+        for notice in &batch.notices {
+            stats.record_notice(notice);
+        }
+        for line in batch.lines.iter() {
+            self.throttler.observe(line, &mut self.recorder);
+        }
+        self.next_tick = self.clock.elapsed() + self.tick;
+        Ok(true)
+    }
+
+    /// Emit the pending line now, as butt does on exit.
+    pub fn flush(&mut self) {
+        self.throttler.flush(&mut self.recorder);
+    }
+
+    /// Every event so far, with the simulated time it was emitted at.
+    pub fn events(&self) -> &[(Duration, Event)] {
+        &self.recorder.events
+    }
+
+    /// Text of every emitted line, in order.
+    pub fn emitted_lines(&self) -> Vec<Cow<'_, str>> {
+        self.recorder
+            .events
+            .iter()
+            .filter_map(|(_, event)| match event {
+                Event::Line(line) => Some(line.text()),
+                Event::Idle { .. } | Event::Missed { .. } => None,
+            })
+            .collect()
+    }
+
+    pub fn idle_notices(&self) -> usize {
+        self.recorder
+            .events
+            .iter()
+            .filter(|(_, event)| matches!(event, Event::Idle { .. }))
+            .count()
+    }
+
+    pub fn missed_notices(&self) -> usize {
+        self.recorder
+            .events
+            .iter()
+            .filter(|(_, event)| matches!(event, Event::Missed { .. }))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_clock_clones_share_time() {
+        let clock = VirtualClock::new();
+        let other = clock.clone();
+        let before = clock.now();
+        other.sleep(Duration::from_secs(3));
+        assert_eq!(clock.now() - before, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn ticks_on_tick_boundaries() {
+        let mut sim = Simulation::new(ThrottleConfig::new(Duration::from_millis(500)))
+            .with_tick(Duration::from_millis(300));
+        sim.feed("only");
+        sim.advance(Duration::from_secs(1));
+        assert_eq!(sim.events().len(), 1);
+        assert_eq!(sim.events()[0].0, Duration::from_millis(600));
+    }
+}
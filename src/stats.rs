@@ -0,0 +1,196 @@
+use crate::follow::Notice;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Counters describing everything a stream has produced so far.
+#[derive(Debug, Clone)]
+pub struct StreamStats {
+    pub started: Instant,
+    pub lines: u64,
+    pub bytes: u64,
+    pub urgent: u64,
+    pub urgent_by_pattern: BTreeMap<String, u64>,
+    pub emitted: u64,
+    pub truncated: u64,
+    pub buffer_drops: u64,
+    pub rotations: u64,
+    pub file_truncations: u64,
+    /// Missed notices for expected lines that stopped appearing.
+    pub missed_expectations: u64,
+    pub last_input: Option<Instant>,
+    /// How far the newest timestamped line was behind the wall clock.
+    pub lag: Option<Duration>,
+    pub max_lag: Duration,
+    /// Input gaps at least this long are counted as idle periods.
+    idle_threshold: Option<Duration>,
+    idle_periods: u64,
+    longest_idle: Duration,
+}
+
+impl StreamStats {
+    pub fn new(now: Instant, idle_threshold: Option<Duration>) -> Self {
+        Self {
+            started: now,
+            lines: 0,
+            bytes: 0,
+            urgent: 0,
+            urgent_by_pattern: BTreeMap::new(),
+            emitted: 0,
+            truncated: 0,
+            buffer_drops: 0,
+            rotations: 0,
+            file_truncations: 0,
+            missed_expectations: 0,
+            last_input: None,
+            lag: None,
+            max_lag: Duration::ZERO,
+            idle_threshold,
+            idle_periods: 0,
+            longest_idle: Duration::ZERO,
+        }
+    }
+
+    /// Count bytes read from the source.
+    pub fn record_bytes(&mut self, bytes: usize, now: Instant) {
+        self.bytes += bytes as u64;
+        self.touch(now);
+    }
+
+    /// Count one complete input line.
+    pub fn record_line(&mut self, now: Instant) {
+        self.lines += 1;
+        self.touch(now);
+    }
+
+    pub fn record_urgent(&mut self, pattern: &str) {
+        self.urgent += 1;
+        *self
+            .urgent_by_pattern
+            .entry(pattern.to_string())
+            .or_default() += 1;
+    }
+
+    /// Note how long ago a line was logged when it arrived.
+    pub fn record_lag(&mut self, lag: Duration) {
+        self.lag = Some(lag);
+        self.max_lag = self.max_lag.max(lag);
+    }
+
+    /// Count what a follower notice says happened to the source.
+    pub fn record_notice(&mut self, notice: &Notice) {
+        match notice {
+            Notice::BufferOverflow { .. } => self.buffer_drops += 1,
+            Notice::LinesTruncated { count, .. } => self.truncated += *count as u64,
+            Notice::Truncated => self.file_truncations += 1,
+            Notice::Rotated { .. } => self.rotations += 1,
+            _ => {}
+        }
+    }
+
+    pub fn dropped_or_truncated(&self) -> u64 {
+        self.truncated + self.buffer_drops
+    }
+
+    /// Lines that were read but never shown.
+    pub fn suppressed(&self) -> u64 {
+        self.lines.saturating_sub(self.emitted)
+    }
+
+    /// Idle period count and longest idle period, including one still in progress.
+    pub fn idle_periods(&self, now: Instant) -> (u64, Duration) {
+        let gap = self.since_last_input(now);
+        match self.idle_threshold {
+            Some(threshold) if gap >= threshold => {
+                (self.idle_periods + 1, self.longest_idle.max(gap))
+            }
+            _ => (self.idle_periods, self.longest_idle),
+        }
+    }
+
+    /// Time since the last input, or since the start if nothing arrived yet.
+    pub fn since_last_input(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_input.unwrap_or(self.started))
+    }
+
+    /// Whether the input has been quiet for at least the idle threshold.
+    pub fn is_idle(&self, now: Instant) -> bool {
+        self.idle_threshold
+            .is_some_and(|threshold| self.since_last_input(now) >= threshold)
+    }
+
+    fn touch(&mut self, now: Instant) {
+        let gap = self.since_last_input(now);
+        if self
+            .idle_threshold
+            .is_some_and(|threshold| gap >= threshold)
+        {
+            self.idle_periods += 1;
+            self.longest_idle = self.longest_idle.max(gap);
+        }
+        self.last_input = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_truncations_and_buffer_drops() {
+        let stats = StreamStats {
+            truncated: 2,
+            buffer_drops: 3,
+            ..StreamStats::new(Instant::now(), None)
+        };
+        assert_eq!(stats.dropped_or_truncated(), 5);
+    }
+
+    #[test]
+    fn records_last_input_time() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(start, None);
+        assert!(stats.last_input.is_none());
+
+        stats.record_bytes(10, start);
+        stats.record_line(start);
+        assert_eq!(stats.bytes, 10);
+        assert_eq!(stats.lines, 1);
+        assert_eq!(stats.last_input, Some(start));
+    }
+
+    #[test]
+    fn counts_urgent_matches_per_pattern() {
+        let mut stats = StreamStats::new(Instant::now(), None);
+        stats.record_urgent("ERR");
+        stats.record_urgent("ERR");
+        stats.record_urgent("WARN");
+        assert_eq!(stats.urgent, 3);
+        assert_eq!(stats.urgent_by_pattern.get("ERR"), Some(&2));
+        assert_eq!(stats.urgent_by_pattern.get("WARN"), Some(&1));
+    }
+
+    #[test]
+    fn tracks_idle_periods_longer_than_threshold() {
+        let start = Instant::now();
+        let mut stats = StreamStats::new(start, Some(Duration::from_secs(5)));
+
+        stats.record_line(start + Duration::from_secs(1));
+        stats.record_line(start + Duration::from_secs(8));
+        stats.record_line(start + Duration::from_secs(20));
+        assert_eq!(
+            stats.idle_periods(start + Duration::from_secs(21)),
+            (2, Duration::from_secs(12))
+        );
+
+        assert_eq!(
+            stats.idle_periods(start + Duration::from_secs(40)),
+            (3, Duration::from_secs(20))
+        );
+        assert!(stats.is_idle(start + Duration::from_secs(40)));
+        assert!(!stats.is_idle(start + Duration::from_secs(21)));
+        assert_eq!(
+            stats.since_last_input(start + Duration::from_secs(21)),
+            Duration::from_secs(1)
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use crate::cli::Args;
+use butt::StreamStats;
+use butt::output::stdout_is_interactive;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, Copy)]
+struct RateSample {
+    at: Instant,
+    lines: u64,
+    bytes: u64,
+}
+
+/// Interactive extras enabled only when a person is watching the terminal.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TerminalFeatures {
+    pub(crate) status_bar: bool,
+    pub(crate) keyboard: bool,
+}
+
+pub(crate) fn detect_terminal_features(args: &Args) -> TerminalFeatures {
+    let interactive = stdout_is_interactive();
+    TerminalFeatures {
+        status_bar: interactive && !args.no_status_bar,
+        keyboard: interactive && !args.no_keyboard,
+    }
+}
+
+/// Values shown on the status line besides the stream counters.
+pub(crate) struct StatusView<'a> {
+    pub(crate) stats: &'a StreamStats,
+    pub(crate) next_emit: Duration,
+    pub(crate) line_seconds: u64,
+    pub(crate) paused: bool,
+    /// Replaces the status text while the user is typing, e.g. a `/` regex prompt.
+    pub(crate) prompt: Option<String>,
+}
+
+/// Bottom-of-terminal status line, redrawn in place below scrolling output.
+pub(crate) struct StatusBar {
+    source: String,
+    colors_enabled: bool,
+    samples: VecDeque<RateSample>,
+    last_draw: Option<Instant>,
+    visible: bool,
+}
+
+impl StatusBar {
+    pub(crate) fn new(source: String, colors_enabled: bool) -> Self {
+        Self {
+            source,
+            colors_enabled,
+            samples: VecDeque::new(),
+            last_draw: None,
+            visible: false,
+        }
+    }
+
+    /// Erase the status line so regular output can be written in its place.
+    pub(crate) fn clear(&mut self, out: &mut impl Write) {
+        if self.visible {
+            let _ = write!(out, "\r\x1b[2K");
+            self.visible = false;
+        }
+    }
+
+    pub(crate) fn is_due(&self, now: Instant) -> bool {
+        !self.visible
+            || self
+                .last_draw
+                .is_none_or(|last| now.duration_since(last) >= REDRAW_INTERVAL)
+    }
+
+    pub(crate) fn draw(&mut self, out: &mut impl Write, view: &StatusView<'_>, now: Instant) {
+        self.sample(view.stats, now);
+        let (lines_per_sec, bytes_per_sec) = self.rates();
+        let text = match &view.prompt {
+            Some(prompt) => format!(" regex {prompt}"),
+            None => render_status(&self.source, view, lines_per_sec, bytes_per_sec, now),
+        };
+        let text = truncate_to_width(&text, terminal_width());
+
+        let _ = write!(out, "\r\x1b[2K");
+        if self.colors_enabled {
+            let _ = write!(out, "\x1b[7m{text}\x1b[0m");
+        } else {
+            let _ = write!(out, "{text}");
+        }
+        let _ = out.flush();
+
+        self.visible = true;
+        self.last_draw = Some(now);
+    }
+
+    fn sample(&mut self, stats: &StreamStats, now: Instant) {
+        self.samples.push_back(RateSample {
+            at: now,
+            lines: stats.lines,
+            bytes: stats.bytes,
+        });
+        while self.samples.len() > 2
+            && self
+                .samples
+                .front()
+                .is_some_and(|oldest| now.duration_since(oldest.at) > RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn rates(&self) -> (f64, f64) {
+        let (Some(oldest), Some(newest)) = (self.samples.front(), self.samples.back()) else {
+            return (0.0, 0.0);
+        };
+        let elapsed = newest.at.duration_since(oldest.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return (0.0, 0.0);
+        }
+        (
+            (newest.lines - oldest.lines) as f64 / elapsed,
+            (newest.bytes - oldest.bytes) as f64 / elapsed,
+        )
+    }
+}
+
+fn render_status(
+    source: &str,
+    view: &StatusView<'_>,
+    lines_per_sec: f64,
+    bytes_per_sec: f64,
+    now: Instant,
+) -> String {
+    let stats = view.stats;
+    let paused = if view.paused { "PAUSED | " } else { "" };
+    let last_input = match stats.last_input {
+        Some(at) => format!("{}s ago", now.duration_since(at).as_secs()),
+        None => "never".to_string(),
+    };
+    let lag = stats
+        .lag
+        .map(|lag| format!(" | lag {}s", lag.as_secs()))
+        .unwrap_or_default();
+    format!(
+        " {paused}{source} | {lines_per_sec:.1} lines/s {}/s | lines {} | urgent {} | dropped {} | input {last_input}{lag} | next {}s/{}s ",
+        format_bytes(bytes_per_sec),
+        stats.lines,
+        stats.urgent,
+        stats.dropped_or_truncated(),
+        view.next_emit.as_secs_f64().ceil() as u64,
+        view.line_seconds,
+    )
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
+#[cfg(unix)]
+fn terminal_width() -> usize {
+    // SAFETY: TIOCGWINSZ only writes into the provided winsize struct.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        return usize::from(size.ws_col);
+    }
+    columns_from_env()
+}
+
+#[cfg(not(unix))]
+fn terminal_width() -> usize {
+    columns_from_env()
+}
+
+fn columns_from_env() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.parse().ok())
+        .filter(|&cols| cols > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_countdown() {
+        let start = Instant::now();
+        let now = start + Duration::from_secs(10);
+        let mut stats = StreamStats::new(start, None);
+        stats.lines = 42;
+        stats.urgent = 3;
+        stats.truncated = 1;
+        stats.buffer_drops = 1;
+        stats.last_input = Some(start + Duration::from_secs(8));
+
+        let view = StatusView {
+            stats: &stats,
+            next_emit: Duration::from_millis(1500),
+            line_seconds: 5,
+            paused: false,
+            prompt: None,
+        };
+
+        let text = render_status("app.log", &view, 2.5, 2048.0, now);
+        assert!(text.contains("app.log"));
+        assert!(text.contains("2.5 lines/s 2.0 KiB/s"));
+        assert!(text.contains("lines 42"));
+        assert!(text.contains("urgent 3"));
+        assert!(text.contains("dropped 2"));
+        assert!(text.contains("input 2s ago"));
+        assert!(text.contains("next 2s/5s"));
+        assert!(!text.contains("PAUSED"));
+        assert!(!text.contains("lag"));
+
+        stats.record_lag(Duration::from_secs(90));
+        let view = StatusView {
+            stats: &stats,
+            next_emit: Duration::from_millis(1500),
+            line_seconds: 5,
+            paused: false,
+            prompt: None,
+        };
+        assert!(
+            render_status("app.log", &view, 2.5, 2048.0, now).contains("input 2s ago | lag 90s |")
+        );
+    }
+
+    #[test]
+    fn computes_rates_over_sample_window() {
+        let start = Instant::now();
+        let mut bar = StatusBar::new("stdin".to_string(), false);
+        let mut stats = StreamStats::new(start, None);
+        bar.sample(&stats, start);
+
+        stats.lines = 20;
+        stats.bytes = 4096;
+        bar.sample(&stats, start + Duration::from_secs(2));
+
+        let (lines_per_sec, bytes_per_sec) = bar.rates();
+        assert_eq!(lines_per_sec, 10.0);
+        assert_eq!(bytes_per_sec, 2048.0);
+    }
+
+    #[test]
+    fn clear_only_erases_when_visible() {
+        let mut bar = StatusBar::new("stdin".to_string(), false);
+        let mut out = Vec::new();
+        bar.clear(&mut out);
+        assert!(out.is_empty());
+
+        let now = Instant::now();
+        let stats = StreamStats::new(now, None);
+        let view = StatusView {
+            stats: &stats,
+            next_emit: Duration::ZERO,
+            line_seconds: 5,
+            paused: true,
+            prompt: None,
+        };
+        bar.draw(&mut out, &view, now);
+        assert!(String::from_utf8_lossy(&out).contains("PAUSED"));
+        assert!(!bar.is_due(now));
+        out.clear();
+        bar.clear(&mut out);
+        assert_eq!(out, b"\r\x1b[2K");
+        assert!(bar.is_due(now));
+    }
+
+    #[test]
+    fn truncates_to_terminal_width() {
+        assert_eq!(truncate_to_width("abcdef", 3), "abc");
+        assert_eq!(truncate_to_width("abc", 10), "abc");
+    }
+}
@@ -0,0 +1,387 @@
+use crate::clock::Clock;
+use crate::follow::{Batch, FileState, FollowOptions};
+use crate::limits::{LineAssembler, Lines};
+use crate::pattern::Pattern;
+use crate::throttle::{Event, ThrottleConfig, Throttler};
+use futures_core::Stream;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Tokio's clock, so throttling follows paused or auto-advanced test time.
+#[derive(Debug, Clone, Copy)]
+struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        // The async drivers never block; they await `tokio::time::sleep` instead.
+        std::thread::sleep(duration);
+    }
+}
+
+/// Throttled [`Event`]s from a file, stdin or child process, for tokio services.
+///
+/// Input is read by a task spawned on the current tokio runtime. Dropping the
+/// stream aborts that task, closing the input and killing any child process.
+/// The stream ends after the input does, once the pending line is flushed.
+///
+/// ```no_run
+/// # async fn demo() -> std::io::Result<()> {
+/// use butt::stream::EventStream;
+/// use butt::ThrottleConfig;
+/// use std::time::Duration;
+///
+/// let mut events = EventStream::builder(ThrottleConfig::new(Duration::from_secs(5)))
+///     .with_pattern(Some(butt::Pattern::new("ERROR", false).unwrap()))
+///     .file("/var/log/app.log");
+/// while let Some(event) = events.next_event().await {
+///     println!("{event:?}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct EventStream {
+    rx: mpsc::Receiver<Event>,
+    task: JoinHandle<()>,
+}
+
+impl EventStream {
+    pub fn builder(config: ThrottleConfig) -> EventStreamBuilder {
+        EventStreamBuilder {
+            config,
+            pattern: None,
+            options: FollowOptions::default(),
+            poll_interval: Duration::from_millis(200),
+        }
+    }
+
+    /// The next event, or `None` once the input has ended.
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Settings for an [`EventStream`]; finish with the method naming the input.
+#[derive(Debug, Clone)]
+pub struct EventStreamBuilder {
+    config: ThrottleConfig,
+    pattern: Option<Pattern>,
+    options: FollowOptions,
+    poll_interval: Duration,
+}
+
+impl EventStreamBuilder {
+    /// Lines matching `pattern` are urgent and emitted immediately.
+    pub fn with_pattern(mut self, pattern: Option<Pattern>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn with_follow_options(mut self, options: FollowOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// How long a file follower waits before checking an idle file again.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Follow `path` from its current end, reopening it after rotation.
+    pub fn file(self, path: impl Into<PathBuf>) -> EventStream {
+        let state = FileState::new(path.into(), self.options.clone());
+        let poll_interval = self.poll_interval;
+        self.spawn(|driver| follow_file(state, poll_interval, driver))
+    }
+
+    pub fn stdin(self) -> EventStream {
+        self.reader(tokio::io::stdin())
+    }
+
+    /// Read lines from `reader` until it reaches EOF.
+    pub fn reader<R: AsyncRead + Unpin + Send + 'static>(self, reader: R) -> EventStream {
+        let assembler = self.assembler();
+        self.spawn(|driver| read_lines(reader, assembler, driver))
+    }
+
+    /// Spawn `command` and read lines from its stdout. The child is killed when
+    /// the stream is dropped, and reaped after its stdout closes.
+    pub fn child(self, command: &mut Command) -> io::Result<EventStream> {
+        let mut child = command.stdout(Stdio::piped()).kill_on_drop(true).spawn()?;
+        let stdout = child.stdout.take().expect("child stdout is piped");
+        let assembler = self.assembler();
+        Ok(self.spawn(|driver| async move {
+            read_lines(stdout, assembler, driver).await;
+            let _ = child.wait().await;
+        }))
+    }
+
+    fn assembler(&self) -> LineAssembler {
+        self.options.assembler()
+    }
+
+    fn spawn<F, Fut>(self, run: F) -> EventStream
+    where
+        F: FnOnce(Driver) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(64);
+        let driver = Driver {
+            throttler: Throttler::with_clock(self.config, TokioClock).with_pattern(self.pattern),
+            tx,
+            pending: Vec::new(),
+        };
+        EventStream {
+            rx,
+            task: tokio::spawn(run(driver)),
+        }
+    }
+}
+
+/// Runs the throttler inside the spawned task and forwards its events.
+///
+/// Every method returns `false` once the stream has been dropped.
+struct Driver {
+    throttler: Throttler<TokioClock>,
+    tx: mpsc::Sender<Event>,
+    pending: Vec<Event>,
+}
+
+impl Driver {
+    async fn deliver(&mut self) -> bool {
+        for event in self.pending.drain(..) {
+            if self.tx.send(event).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn observe(&mut self, batch: Batch) -> bool {
+        let now = self.throttler.clock().now();
+        let stats = self.throttler.stats_mut();
+        if batch.bytes > 0 {
+            stats.record_bytes(batch.bytes, now);
+        }
+        for notice in &batch.notices {
+            stats.record_notice(notice);
+        }
+        for line in batch.lines.iter() {
+            self.throttler.observe(line, &mut self.pending);
+        }
+        self.deliver().await
+    }
+
+    async fn tick(&mut self) -> bool {
+        self.throttler.tick(&mut self.pending);
+        self.deliver().await
+    }
+
+    /// Sleep until the throttler has something due, or at most `limit`.
+    async fn wait(&mut self, limit: Duration) -> bool {
+        let wait = self
+            .throttler
+            .next_wakeup()
+            .map_or(limit, |due| due.min(limit));
+        tokio::time::sleep(wait).await;
+        self.tick().await
+    }
+
+    async fn finish(mut self) {
+        self.throttler.flush(&mut self.pending);
+        self.deliver().await;
+    }
+}
+
+async fn follow_file(mut state: FileState, poll_interval: Duration, mut driver: Driver) {
+    loop {
+        // File reads and metadata checks block, so run them off the async workers.
+        let Ok((returned, result)) = tokio::task::spawn_blocking(move || {
+            let result = state.step();
+            (state, result)
+        })
+        .await
+        else {
+            return;
+        };
+        state = returned;
+
+        let Ok(batch) = result else {
+            break;
+        };
+        let idle = batch.bytes == 0;
+        if !driver.observe(batch).await {
+            return;
+        }
+        if idle && !driver.wait(poll_interval).await {
+            return;
+        }
+    }
+    driver.finish().await;
+}
+
+async fn read_lines<R: AsyncRead + Unpin>(
+    mut reader: R,
+    mut assembler: LineAssembler,
+    mut driver: Driver,
+) {
+    let mut chunk = [0_u8; 8192];
+    loop {
+        let wakeup = driver.throttler.next_wakeup();
+        tokio::select! {
+            read = reader.read(&mut chunk) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let batch = Batch::assemble(&mut assembler, &chunk[..n]);
+                    if !driver.observe(batch).await {
+                        return;
+                    }
+                }
+            },
+            () = tokio::time::sleep(wakeup.unwrap_or_default()), if wakeup.is_some() => {
+                if !driver.tick().await {
+                    return;
+                }
+            }
+        }
+    }
+
+    if let Some(line) = assembler.finish() {
+        let batch = Batch {
+            lines: Lines::from_iter([line]),
+            ..Batch::default()
+        };
+        if !driver.observe(batch).await {
+            return;
+        }
+    }
+    driver.finish().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::Line;
+    use tokio::io::AsyncWriteExt;
+
+    fn line(text: &str, urgent: bool) -> Event {
+        let mut line = Line::new(text, urgent);
+        if urgent {
+            line.matches.extend(text.find("ERR").map(|at| at..at + 3));
+        }
+        Event::Line(line)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reader_stream_throttles_and_flushes_at_eof() {
+        let config =
+            ThrottleConfig::new(Duration::from_secs(5)).idle_interval(Duration::from_secs(3));
+        let (mut writer, reader) = tokio::io::duplex(1024);
+        let mut events = EventStream::builder(config)
+            .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")))
+            .reader(reader);
+
+        writer.write_all(b"one\ntwo\n").await.expect("write");
+        let start = tokio::time::Instant::now();
+        assert_eq!(
+            events.next_event().await,
+            Some(Event::Idle {
+                interval: Duration::from_secs(3)
+            })
+        );
+        assert_eq!(events.next_event().await, Some(line("two", false)));
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+
+        writer.write_all(b"ERR now\nlast").await.expect("write");
+        assert_eq!(events.next_event().await, Some(line("ERR now", true)));
+        drop(writer);
+        assert_eq!(events.next_event().await, Some(line("last", false)));
+        assert_eq!(events.next_event().await, None);
+    }
+
+    #[tokio::test]
+    async fn dropping_stream_closes_the_input() {
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let events =
+            EventStream::builder(ThrottleConfig::new(Duration::from_secs(60))).reader(reader);
+        drop(events);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while writer.write_all(b"line\n").await.is_ok() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("reader dropped after the stream");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn child_stream_reads_stdout_until_exit() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo one; echo ERR two"]);
+        let mut events = EventStream::builder(ThrottleConfig::new(Duration::from_secs(60)))
+            .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")))
+            .child(&mut command)
+            .expect("spawn child");
+
+        assert_eq!(events.next_event().await, Some(line("ERR two", true)));
+        assert_eq!(events.next_event().await, None);
+    }
+
+    #[tokio::test]
+    async fn file_stream_follows_appended_lines() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("app.log");
+        std::fs::write(&path, "old\n").expect("write log");
+
+        let mut events = EventStream::builder(ThrottleConfig::new(Duration::from_secs(60)))
+            .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")))
+            .with_poll_interval(Duration::from_millis(10))
+            .file(&path);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let mut file = std::fs::OpenOptions::new()
+                    .append(true)
+                    .open(&path)
+                    .expect("open for append");
+                std::io::Write::write_all(&mut file, b"ERR appended\n").expect("append");
+                if let Ok(event) =
+                    tokio::time::timeout(Duration::from_millis(50), events.next_event()).await
+                {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("appended line within timeout");
+        assert_eq!(event, Some(line("ERR appended", true)));
+    }
+}
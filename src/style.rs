@@ -0,0 +1,425 @@
+use clap::ValueEnum;
+use std::fmt;
+use std::str::FromStr;
+
+/// Names of the eight basic colors, in SGR order.
+const BASIC_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// RGB values of the basic colors, used to pick the nearest one.
+const BASIC_RGB: [(u8, u8, u8); 8] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+];
+
+/// A terminal color as written in a style: `red`, `color256:208` or `#ff8800`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the eight basic colors, `0` (black) to `7` (white).
+    Basic(u8),
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// SGR parameters for this color as a foreground (`38`) or background
+    /// (`48`), reduced to what the terminal supports.
+    fn sgr(self, background: bool, support: ColorSupport) -> String {
+        let base = if background { 40 } else { 30 };
+        match self.reduce(support) {
+            Self::Basic(index) => (base + u32::from(index)).to_string(),
+            Self::Ansi256(index) => format!("{};5;{index}", base + 8),
+            Self::Rgb(r, g, b) => format!("{};2;{r};{g};{b}", base + 8),
+        }
+    }
+
+    fn reduce(self, support: ColorSupport) -> Self {
+        match (self, support) {
+            (Self::Rgb(r, g, b), ColorSupport::Ansi256) => Self::Ansi256(rgb_to_256(r, g, b)),
+            (Self::Rgb(r, g, b), ColorSupport::Basic) => Self::Basic(nearest_basic(r, g, b)),
+            (Self::Ansi256(index), ColorSupport::Basic) => {
+                let (r, g, b) = ansi256_to_rgb(index);
+                Self::Basic(nearest_basic(r, g, b))
+            }
+            (color, _) => color,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = value.strip_prefix('#') {
+            let channel = |at: usize| {
+                hex.get(at..at + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            };
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(Self::Rgb(r, g, b)),
+                _ => Err(format!("invalid hex color '{value}' (expected #rrggbb)")),
+            };
+        }
+        if let Some(index) = value.strip_prefix("color256:") {
+            return index
+                .parse()
+                .map(Self::Ansi256)
+                .map_err(|_| format!("invalid 256-color index '{index}' (expected 0-255)"));
+        }
+        BASIC_NAMES
+            .iter()
+            .position(|name| *name == value)
+            .map(|index| Self::Basic(index as u8))
+            .ok_or_else(|| format!("unknown color '{value}'"))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic(index) => f.write_str(BASIC_NAMES[usize::from(*index)]),
+            Self::Ansi256(index) => write!(f, "color256:{index}"),
+            Self::Rgb(r, g, b) => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
+
+/// How a highlight looks, parsed from a comma-separated spec such as
+/// `bold,red`, `underline`, `bg:yellow`, `#ff8800` or `color256:208`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..Self::default()
+        }
+    }
+
+    /// The escape sequence that turns this style on, reducing colors to what
+    /// the terminal supports. Empty for the default style.
+    pub fn sgr(&self, support: ColorSupport) -> String {
+        let mut params = Vec::new();
+        for (on, code) in [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reverse, "7"),
+        ] {
+            if on {
+                params.push(code.to_string());
+            }
+        }
+        params.extend(self.fg.map(|color| color.sgr(false, support)));
+        params.extend(self.bg.map(|color| color.sgr(true, support)));
+        if params.is_empty() {
+            return String::new();
+        }
+        format!("\x1b[{}m", params.join(";"))
+    }
+
+    /// `input` wrapped in this style and a reset.
+    pub fn paint(&self, input: &str, support: ColorSupport) -> String {
+        format!("{}{input}\x1b[0m", self.sgr(support))
+    }
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut style = Self::default();
+        for part in spec.split(',').map(str::trim) {
+            match part {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "" => return Err(format!("empty entry in style '{spec}'")),
+                _ => match part.strip_prefix("bg:") {
+                    Some(color) => style.bg = Some(color.parse()?),
+                    None => style.fg = Some(part.parse()?),
+                },
+            }
+        }
+        Ok(style)
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        for (on, name) in [
+            (self.bold, "bold"),
+            (self.dim, "dim"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.reverse, "reverse"),
+        ] {
+            if on {
+                parts.push(name.to_string());
+            }
+        }
+        parts.extend(self.fg.map(|color| color.to_string()));
+        parts.extend(self.bg.map(|color| format!("bg:{color}")));
+        f.write_str(&parts.join(","))
+    }
+}
+
+/// Styles for named capture groups, parsed from `status=red,latency=cyan`.
+///
+/// Entries without `=` continue the previous group's style, so
+/// `status=bold,red,latency=cyan` makes `status` bold and red.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupStyles(pub Vec<(String, Style)>);
+
+impl GroupStyles {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+impl FromStr for GroupStyles {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for part in spec.split(',').map(str::trim) {
+            match (part.split_once('='), entries.last_mut()) {
+                (Some((name, style)), _) => {
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err(format!("missing group name in '{part}'"));
+                    }
+                    entries.push((name.to_string(), style.trim().to_string()));
+                }
+                (None, Some((_, style))) => {
+                    style.push(',');
+                    style.push_str(part);
+                }
+                (None, None) => return Err(format!("expected NAME=STYLE, got '{part}'")),
+            }
+        }
+        entries
+            .into_iter()
+            .map(|(name, style)| {
+                let style = style
+                    .parse()
+                    .map_err(|err| format!("group '{name}': {err}"))?;
+                Ok((name, style))
+            })
+            .collect::<Result<_, String>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for GroupStyles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .0
+            .iter()
+            .map(|(name, style)| format!("{name}={style}"))
+            .collect();
+        f.write_str(&entries.join(","))
+    }
+}
+
+/// Which colors the terminal can show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    Basic,
+    Ansi256,
+    #[default]
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Detect support from `COLORTERM` (`truecolor` or `24bit`) and `TERM`
+    /// (`*-256color`, `*-direct`), falling back to the basic colors.
+    pub fn detect() -> Self {
+        Self::from_env(
+            std::env::var("COLORTERM").ok().as_deref(),
+            std::env::var("TERM").ok().as_deref(),
+        )
+    }
+
+    pub fn from_env(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit"))
+            || term.is_some_and(|term| term.ends_with("-direct"))
+        {
+            Self::TrueColor
+        } else if term.is_some_and(|term| term.contains("256color")) {
+            Self::Ansi256
+        } else {
+            Self::Basic
+        }
+    }
+}
+
+/// Which part of an urgent line gets the highlight style.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HighlightScope {
+    /// Only the text the regex matched.
+    #[default]
+    Match,
+    /// The whole line.
+    Line,
+}
+
+/// The nearest entry in the 6x6x6 cube or grayscale ramp of the 256-color palette.
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let cube = |channel: u8| -> u8 {
+        match channel {
+            0..=47 => 0,
+            48..=114 => 1,
+            _ => (channel - 35) / 40,
+        }
+    };
+    let (cr, cg, cb) = (cube(r), cube(g), cube(b));
+    let cube_index = 16 + 36 * cr + 6 * cg + cb;
+    let gray_level = ((u16::from(r) + u16::from(g) + u16::from(b)) / 3) as u8;
+    let gray_index = if gray_level > 238 {
+        23
+    } else {
+        gray_level.saturating_sub(3) / 10
+    };
+    let cube_rgb = ansi256_to_rgb(cube_index);
+    let gray_rgb = ansi256_to_rgb(232 + gray_index);
+    if distance((r, g, b), gray_rgb) < distance((r, g, b), cube_rgb) {
+        232 + gray_index
+    } else {
+        cube_index
+    }
+}
+
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=7 => BASIC_RGB[usize::from(index)],
+        8..=15 => {
+            let (r, g, b) = BASIC_RGB[usize::from(index - 8)];
+            (
+                r.saturating_add(50),
+                g.saturating_add(50),
+                b.saturating_add(50),
+            )
+        }
+        16..=231 => {
+            let index = index - 16;
+            (
+                LEVELS[usize::from(index / 36)],
+                LEVELS[usize::from(index / 6 % 6)],
+                LEVELS[usize::from(index % 6)],
+            )
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn nearest_basic(r: u8, g: u8, b: u8) -> u8 {
+    (0..8u8)
+        .min_by_key(|&index| distance((r, g, b), BASIC_RGB[usize::from(index)]))
+        .unwrap_or(7)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |x: u8, y: u8| (i32::from(x) - i32::from(y)).unsigned_abs().pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_style_specs() {
+        let style: Style = "bold,red,bg:yellow".parse().expect("style");
+        assert!(style.bold);
+        assert_eq!(style.fg, Some(Color::Basic(1)));
+        assert_eq!(style.bg, Some(Color::Basic(3)));
+        assert_eq!(style.to_string(), "bold,red,bg:yellow");
+
+        let style: Style = "underline, #ff8800".parse().expect("style");
+        assert!(style.underline);
+        assert_eq!(style.fg, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(
+            "color256:208".parse::<Style>(),
+            Ok(Style::fg(Color::Ansi256(208)))
+        );
+
+        assert!("purple".parse::<Style>().is_err());
+        assert!("#ff88".parse::<Style>().is_err());
+        assert!("color256:300".parse::<Style>().is_err());
+        assert!("bold,,red".parse::<Style>().is_err());
+    }
+
+    #[test]
+    fn parses_group_styles() {
+        let groups: GroupStyles = "status=bold,red,latency=cyan".parse().expect("groups");
+        assert_eq!(
+            groups,
+            GroupStyles(vec![
+                ("status".to_string(), "bold,red".parse().expect("style")),
+                ("latency".to_string(), Style::fg(Color::Basic(6))),
+            ])
+        );
+        assert_eq!(groups.to_string(), "status=bold,red,latency=cyan");
+        assert!("red".parse::<GroupStyles>().is_err());
+        assert!("=red".parse::<GroupStyles>().is_err());
+        assert!("status=purple".parse::<GroupStyles>().is_err());
+    }
+
+    #[test]
+    fn renders_and_reduces_colors() {
+        let style: Style = "bold,#ff8800,bg:color256:17".parse().expect("style");
+        assert_eq!(
+            style.sgr(ColorSupport::TrueColor),
+            "\x1b[1;38;2;255;136;0;48;5;17m"
+        );
+        assert_eq!(style.sgr(ColorSupport::Ansi256), "\x1b[1;38;5;208;48;5;17m");
+        assert_eq!(style.sgr(ColorSupport::Basic), "\x1b[1;33;40m");
+        assert_eq!(Style::default().sgr(ColorSupport::Basic), "");
+    }
+
+    #[test]
+    fn detects_color_support() {
+        assert_eq!(
+            ColorSupport::from_env(Some("truecolor"), Some("xterm")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env(None, Some("xterm-256color")),
+            ColorSupport::Ansi256
+        );
+        assert_eq!(
+            ColorSupport::from_env(None, Some("xterm-direct")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env(None, Some("vt100")),
+            ColorSupport::Basic
+        );
+        assert_eq!(ColorSupport::from_env(None, None), ColorSupport::Basic);
+    }
+}
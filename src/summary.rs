@@ -0,0 +1,139 @@
+use butt::StreamStats;
+use serde_json::json;
+use std::time::Instant;
+
+pub(crate) fn render_text(stats: &StreamStats, now: Instant) -> String {
+    let duration = now.saturating_duration_since(stats.started);
+    let (idle_periods, longest_idle) = stats.idle_periods(now);
+    let by_pattern = if stats.urgent_by_pattern.is_empty() {
+        String::new()
+    } else {
+        let parts: Vec<String> = stats
+            .urgent_by_pattern
+            .iter()
+            .map(|(pattern, count)| format!("'{pattern}': {count}"))
+            .collect();
+        format!(" ({})", parts.join(", "))
+    };
+
+    [
+        format!("[butt] summary after {:.1}s", duration.as_secs_f64()),
+        format!("[butt]   lines: {} ({} bytes)", stats.lines, stats.bytes),
+        format!(
+            "[butt]   emitted: {}, suppressed: {}",
+            stats.emitted,
+            stats.suppressed()
+        ),
+        format!("[butt]   urgent matches: {}{by_pattern}", stats.urgent),
+        format!(
+            "[butt]   idle periods: {idle_periods} (longest {:.1}s)",
+            longest_idle.as_secs_f64()
+        ),
+        format!(
+            "[butt]   rotations: {}, file truncations: {}",
+            stats.rotations, stats.file_truncations
+        ),
+        format!(
+            "[butt]   truncated/dropped lines: {}, buffer drops: {}",
+            stats.truncated, stats.buffer_drops
+        ),
+    ]
+    .into_iter()
+    .chain((stats.missed_expectations > 0).then(|| {
+        format!(
+            "[butt]   missed expectations: {}",
+            stats.missed_expectations
+        )
+    }))
+    .chain(stats.lag.map(|lag| {
+        format!(
+            "[butt]   lag: last {:.1}s, max {:.1}s",
+            lag.as_secs_f64(),
+            stats.max_lag.as_secs_f64()
+        )
+    }))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+pub(crate) fn render_json(stats: &StreamStats, now: Instant) -> String {
+    let duration = now.saturating_duration_since(stats.started);
+    let (idle_periods, longest_idle) = stats.idle_periods(now);
+    json!({
+        "duration_secs": duration.as_secs_f64(),
+        "lines": stats.lines,
+        "bytes": stats.bytes,
+        "emitted": stats.emitted,
+        "suppressed": stats.suppressed(),
+        "urgent": {
+            "total": stats.urgent,
+            "by_pattern": stats.urgent_by_pattern,
+        },
+        "idle_periods": {
+            "count": idle_periods,
+            "longest_secs": longest_idle.as_secs_f64(),
+        },
+        "rotations": stats.rotations,
+        "file_truncations": stats.file_truncations,
+        "truncated_lines": stats.truncated,
+        "buffer_drops": stats.buffer_drops,
+        "missed_expectations": stats.missed_expectations,
+        "lag": stats.lag.map(|lag| json!({
+            "last_secs": lag.as_secs_f64(),
+            "max_secs": stats.max_lag.as_secs_f64(),
+        })),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample_stats(start: Instant) -> StreamStats {
+        let mut stats = StreamStats::new(start, Some(Duration::from_secs(10)));
+        stats.record_bytes(120, start + Duration::from_secs(15));
+        for _ in 0..4 {
+            stats.record_line(start + Duration::from_secs(15));
+        }
+        stats.record_urgent("ERR");
+        stats.emitted = 2;
+        stats.rotations = 1;
+        stats.buffer_drops = 3;
+        stats
+    }
+
+    #[test]
+    fn text_summary_lists_counters() {
+        let start = Instant::now();
+        let text = render_text(&sample_stats(start), start + Duration::from_secs(16));
+        assert!(text.starts_with("[butt] summary after 16.0s"));
+        assert!(text.contains("lines: 4 (120 bytes)"));
+        assert!(text.contains("emitted: 2, suppressed: 2"));
+        assert!(text.contains("urgent matches: 1 ('ERR': 1)"));
+        assert!(text.contains("idle periods: 1 (longest 15.0s)"));
+        assert!(text.contains("rotations: 1, file truncations: 0"));
+        assert!(text.contains("buffer drops: 3"));
+        assert!(!text.contains("lag"));
+
+        let mut stats = sample_stats(start);
+        stats.record_lag(Duration::from_secs(300));
+        stats.record_lag(Duration::from_secs(2));
+        let text = render_text(&stats, start + Duration::from_secs(16));
+        assert!(text.ends_with("lag: last 2.0s, max 300.0s"));
+    }
+
+    #[test]
+    fn json_summary_is_machine_readable() {
+        let start = Instant::now();
+        let text = render_json(&sample_stats(start), start + Duration::from_secs(16));
+        let value: serde_json::Value = serde_json::from_str(&text).expect("valid json");
+        assert_eq!(value["lines"], 4);
+        assert_eq!(value["suppressed"], 2);
+        assert_eq!(value["urgent"]["by_pattern"]["ERR"], 1);
+        assert_eq!(value["idle_periods"]["count"], 1);
+        assert_eq!(value["buffer_drops"], 3);
+        assert!(value["lag"].is_null());
+    }
+}
@@ -0,0 +1,316 @@
+use clap::ValueEnum;
+use std::fmt;
+
+/// Syslog severity, most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum Severity {
+    #[value(name = "emerg")]
+    Emergency,
+    Alert,
+    #[value(name = "crit")]
+    Critical,
+    #[value(name = "err", alias = "error")]
+    Error,
+    #[value(alias = "warn")]
+    Warning,
+    Notice,
+    #[value(name = "info")]
+    Informational,
+    Debug,
+}
+
+impl Severity {
+    const ALL: [Self; 8] = [
+        Self::Emergency,
+        Self::Alert,
+        Self::Critical,
+        Self::Error,
+        Self::Warning,
+        Self::Notice,
+        Self::Informational,
+        Self::Debug,
+    ];
+
+    fn from_code(code: u8) -> Self {
+        Self::ALL[usize::from(code & 7)]
+    }
+
+    /// The keyword used by syslog tools, e.g. `err`.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Emergency => "emerg",
+            Self::Alert => "alert",
+            Self::Critical => "crit",
+            Self::Error => "err",
+            Self::Warning => "warning",
+            Self::Notice => "notice",
+            Self::Informational => "info",
+            Self::Debug => "debug",
+        }
+    }
+
+    /// Whether this is as severe as `threshold` or more.
+    pub fn at_least(&self, threshold: Severity) -> bool {
+        *self <= threshold
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.keyword())
+    }
+}
+
+/// The parts of an RFC 5424 or RFC 3164 syslog message that butt shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogMessage {
+    pub facility: u8,
+    pub severity: Severity,
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub proc_id: Option<String>,
+    pub message: String,
+}
+
+/// Shown as `host app: msg`, leaving out whichever parts the sender omitted.
+impl fmt::Display for SyslogMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origin: Vec<&str> = [&self.hostname, &self.app_name]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if origin.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", origin.join(" "), self.message)
+        }
+    }
+}
+
+/// Parse one syslog frame, trying RFC 5424 first and then RFC 3164.
+///
+/// Returns `None` when the line does not start with a valid `<PRI>`.
+pub fn parse(frame: &str) -> Option<SyslogMessage> {
+    let rest = frame.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let digits = &rest[..end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let pri: u8 = digits.parse().ok().filter(|pri| *pri <= 191)?;
+    let body = &rest[end + 1..];
+    let mut message = parse_5424(body).unwrap_or_else(|| parse_3164(body));
+    message.facility = pri / 8;
+    message.severity = Severity::from_code(pri);
+    Some(message)
+}
+
+/// A decoded frame as butt prints it (`host app: msg`), with its severity.
+///
+/// Returns `None` for lines that are not syslog, which should be shown unchanged.
+pub fn reformat(line: &str) -> Option<(String, Severity)> {
+    let message = parse(line)?;
+    Some((message.to_string(), message.severity))
+}
+
+fn nil(field: &str) -> Option<String> {
+    (field != "-").then(|| field.to_string())
+}
+
+fn empty_message() -> SyslogMessage {
+    SyslogMessage {
+        facility: 0,
+        severity: Severity::Notice,
+        timestamp: None,
+        hostname: None,
+        app_name: None,
+        proc_id: None,
+        message: String::new(),
+    }
+}
+
+/// `VERSION SP TIMESTAMP SP HOSTNAME SP APP-NAME SP PROCID SP MSGID SP SD [SP MSG]`
+fn parse_5424(body: &str) -> Option<SyslogMessage> {
+    let rest = body.strip_prefix("1 ")?;
+    let mut fields = rest.splitn(6, ' ');
+    let timestamp = fields.next()?;
+    let hostname = fields.next()?;
+    let app_name = fields.next()?;
+    let proc_id = fields.next()?;
+    let _msg_id = fields.next()?;
+    let rest = fields.next()?;
+    let message = skip_structured_data(rest)?;
+    Some(SyslogMessage {
+        timestamp: nil(timestamp),
+        hostname: nil(hostname),
+        app_name: nil(app_name),
+        proc_id: nil(proc_id),
+        message: message
+            .strip_prefix('\u{feff}')
+            .unwrap_or(message)
+            .to_string(),
+        ..empty_message()
+    })
+}
+
+/// Skip `-` or the structured data elements, returning the message.
+fn skip_structured_data(rest: &str) -> Option<&str> {
+    let after = match rest.strip_prefix('-') {
+        Some(after) => after,
+        None => &rest[structured_data_len(rest)?..],
+    };
+    if after.is_empty() {
+        Some("")
+    } else {
+        after.strip_prefix(' ')
+    }
+}
+
+/// Length of the `[id key="value"]...` elements at the start of `text`.
+fn structured_data_len(text: &str) -> Option<usize> {
+    let mut end = None;
+    let mut in_element = false;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (idx, ch) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' if in_quotes => escaped = true,
+            '"' if in_element => in_quotes = !in_quotes,
+            '[' if !in_element => in_element = true,
+            ']' if in_element && !in_quotes => {
+                in_element = false;
+                end = Some(idx + 1);
+            }
+            ' ' if !in_element => break,
+            _ if !in_element => return None,
+            _ => {}
+        }
+    }
+    if in_element { None } else { end }
+}
+
+/// `Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG`, tolerating a missing timestamp or hostname.
+fn parse_3164(body: &str) -> SyslogMessage {
+    let mut message = empty_message();
+    let mut rest = body;
+    if is_3164_timestamp(rest) {
+        message.timestamp = Some(rest[..15].to_string());
+        rest = rest[15..].trim_start_matches(' ');
+    } else {
+        message.message = body.to_string();
+        return message;
+    }
+
+    let (first, after_first) = rest.split_once(' ').unwrap_or((rest, ""));
+    let tag_part = if looks_like_tag(first) {
+        rest
+    } else {
+        message.hostname = Some(first.to_string());
+        after_first
+    };
+
+    match tag_part.split_once(':') {
+        Some((tag, msg)) if looks_like_tag(&format!("{tag}:")) => {
+            let (app, pid) = match tag.split_once('[') {
+                Some((app, pid)) => (app, Some(pid.trim_end_matches(']').to_string())),
+                None => (tag, None),
+            };
+            message.app_name = Some(app.to_string());
+            message.proc_id = pid;
+            message.message = msg.strip_prefix(' ').unwrap_or(msg).to_string();
+        }
+        _ => message.message = tag_part.to_string(),
+    }
+    message
+}
+
+fn is_3164_timestamp(text: &str) -> bool {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let bytes = text.as_bytes();
+    bytes.len() >= 15
+        && MONTHS.iter().any(|month| text.starts_with(month))
+        && bytes[3] == b' '
+        && bytes[6] == b' '
+        && bytes[9] == b':'
+        && bytes[12] == b':'
+}
+
+/// A token such as `sshd:` or `cron[123]:` rather than a hostname.
+fn looks_like_tag(token: &str) -> bool {
+    (token.ends_with(':') && token.len() > 1 && !token[..token.len() - 1].contains(' '))
+        || token.contains('[')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rfc5424_with_structured_data() {
+        let frame = r#"<34>1 2026-10-11T22:14:15.003Z mymachine.example.com su 77 ID47 [exampleSDID@32473 iut="3" eventSource="App\]"] 'su root' failed"#;
+        let message = parse(frame).expect("valid frame");
+        assert_eq!(message.facility, 4);
+        assert_eq!(message.severity, Severity::Critical);
+        assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert_eq!(message.proc_id.as_deref(), Some("77"));
+        assert_eq!(
+            message.to_string(),
+            "mymachine.example.com su: 'su root' failed"
+        );
+    }
+
+    #[test]
+    fn parses_rfc5424_with_nil_fields_and_bom() {
+        let message = parse("<165>1 - - - - - - \u{feff}hello").expect("valid frame");
+        assert_eq!(message.severity, Severity::Notice);
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.to_string(), "hello");
+    }
+
+    #[test]
+    fn parses_rfc3164_with_and_without_hostname() {
+        let message =
+            parse("<13>Oct 11 22:14:15 web1 nginx[812]: upstream timed out").expect("valid frame");
+        assert_eq!(message.facility, 1);
+        assert_eq!(message.severity, Severity::Notice);
+        assert_eq!(message.proc_id.as_deref(), Some("812"));
+        assert_eq!(message.to_string(), "web1 nginx: upstream timed out");
+
+        let message = parse("<11>Oct  1 02:00:00 cron: job failed").expect("valid frame");
+        assert_eq!(message.severity, Severity::Error);
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.to_string(), "cron: job failed");
+
+        let message = parse("<14>just text").expect("valid frame");
+        assert_eq!(message.to_string(), "just text");
+    }
+
+    #[test]
+    fn rejects_lines_without_priority() {
+        assert_eq!(parse("plain line"), None);
+        assert_eq!(parse("<abc>1 - - -"), None);
+        assert_eq!(parse("<192>too big"), None);
+    }
+
+    #[test]
+    fn reformats_frames_with_their_severity() {
+        let message = parse("<11>1 - host app - - - boom").expect("frame");
+        assert_eq!(message.to_string(), "host app: boom");
+        assert!(message.severity.at_least(Severity::Error));
+        assert!(!Severity::Warning.at_least(Severity::Error));
+        assert_eq!(reformat("[peer] plain"), None);
+        assert_eq!(
+            reformat("<12>Oct 11 22:14:15 db1 postgres: slow query"),
+            Some(("db1 postgres: slow query".to_string(), Severity::Warning))
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use crate::hook::shell;
+use crate::output::{Highlighter, Sink};
+use crate::style::Style;
+use crate::throttle::Event;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Lines queued for a tee before new ones are dropped.
+const QUEUE_LINES: usize = 1024;
+
+/// Where a tee copies lines: a file, `udp://HOST:PORT`, or `exec:COMMAND`
+/// (run by the shell, with lines written to its stdin).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeeTarget {
+    File(PathBuf),
+    Udp(String),
+    Exec(String),
+}
+
+impl FromStr for TeeTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = value.strip_prefix("udp://") {
+            if addr.is_empty() {
+                return Err("missing address after udp://".to_string());
+            }
+            Ok(Self::Udp(addr.to_string()))
+        } else if let Some(command) = value.strip_prefix("exec:") {
+            if command.trim().is_empty() {
+                return Err("missing command after exec:".to_string());
+            }
+            Ok(Self::Exec(command.to_string()))
+        } else if value.is_empty() {
+            Err("missing file path".to_string())
+        } else {
+            Ok(Self::File(PathBuf::from(value)))
+        }
+    }
+}
+
+impl fmt::Display for TeeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Udp(addr) => write!(f, "udp://{addr}"),
+            Self::Exec(command) => write!(f, "exec:{command}"),
+        }
+    }
+}
+
+enum Writer {
+    File(BufWriter<File>),
+    Udp(UdpSocket),
+    Exec { child: Child, stdin: ChildStdin },
+}
+
+impl Writer {
+    fn open(target: &TeeTarget) -> io::Result<Self> {
+        match target {
+            TeeTarget::File(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(Self::File(BufWriter::new(file)))
+            }
+            TeeTarget::Udp(addr) => {
+                let remote = addr.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("'{addr}' did not resolve to an address"),
+                    )
+                })?;
+                let local = if remote.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(remote)?;
+                Ok(Self::Udp(socket))
+            }
+            TeeTarget::Exec(command) => {
+                let mut child = shell(command)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .spawn()?;
+                let stdin = child.stdin.take().expect("child stdin is piped");
+                Ok(Self::Exec { child, stdin })
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.write_all(line).and_then(|()| file.write_all(b"\n")),
+            Self::Udp(socket) => socket.send(line).map(|_| ()),
+            Self::Exec { stdin, .. } => stdin.write_all(line).and_then(|()| stdin.write_all(b"\n")),
+        }
+    }
+
+    /// Flush once the queue is drained, so bursts are written in one go.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Udp(_) => Ok(()),
+            Self::Exec { stdin, .. } => stdin.flush(),
+        }
+    }
+
+    fn close(self) {
+        match self {
+            Self::File(mut file) => {
+                let _ = file.flush();
+            }
+            Self::Udp(_) => {}
+            Self::Exec { mut child, stdin } => {
+                drop(stdin);
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+/// State shared with the writer thread.
+#[derive(Default)]
+struct Shared {
+    dropped: AtomicU64,
+    error: Mutex<Option<io::Error>>,
+}
+
+/// Copies lines to a [`TeeTarget`] from a background thread.
+///
+/// Lines are queued and written in order; when the writer falls behind and the
+/// queue is full, new lines are dropped and counted instead of blocking the
+/// caller. A write error stops the tee and is reported by
+/// [`take_error`](Self::take_error).
+pub struct TeeSink {
+    target: TeeTarget,
+    tx: Option<SyncSender<Vec<u8>>>,
+    shared: Arc<Shared>,
+    handle: Option<thread::JoinHandle<()>>,
+    plain: Highlighter,
+}
+
+impl TeeSink {
+    pub fn open(target: TeeTarget) -> io::Result<Self> {
+        Self::with_capacity(target, QUEUE_LINES)
+    }
+
+    /// Like [`open`](Self::open), queueing at most `capacity` lines.
+    pub fn with_capacity(target: TeeTarget, capacity: usize) -> io::Result<Self> {
+        let mut writer = Writer::open(&target)?;
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(capacity);
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+        let handle = thread::spawn(move || {
+            let result = (|| {
+                while let Ok(line) = rx.recv() {
+                    writer.write_line(&line)?;
+                    for line in rx.try_iter() {
+                        writer.write_line(&line)?;
+                    }
+                    writer.flush()?;
+                }
+                Ok(())
+            })();
+            if let Err(err) = result {
+                *thread_shared.error.lock().expect("tee error lock") = Some(err);
+            }
+            writer.close();
+        });
+        Ok(Self {
+            target,
+            tx: Some(tx),
+            shared,
+            handle: Some(handle),
+            plain: Highlighter::new(None, Style::default(), false),
+        })
+    }
+
+    pub fn target(&self) -> &TeeTarget {
+        &self.target
+    }
+
+    /// Queue one line without waiting for it to be written.
+    pub fn write_line(&mut self, line: impl AsRef<[u8]>) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        match tx.try_send(line.as_ref().to_vec()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // The writer stopped after an error; keep counting what it misses.
+            Err(TrySendError::Disconnected(_)) => {
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Lines dropped because the queue was full or the writer had stopped.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The error that stopped the writer, reported once.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.shared.error.lock().expect("tee error lock").take()
+    }
+
+    /// Write out queued lines, waiting at most `timeout` for a slow target.
+    ///
+    /// Returns `false` if the writer was still busy when the time ran out.
+    pub fn close(&mut self, timeout: Duration) -> bool {
+        self.tx = None;
+        let Some(handle) = self.handle.take() else {
+            return true;
+        };
+        let deadline = Instant::now() + timeout;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = handle.join();
+        true
+    }
+}
+
+/// Writes each event as plain text, the way butt prints it without colors.
+impl Sink for TeeSink {
+    fn emit(&mut self, event: &Event) {
+        let text = self.plain.render_bytes(event);
+        self.write_line(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::Line;
+
+    #[test]
+    fn parses_tee_targets() {
+        assert_eq!(
+            "out.log".parse(),
+            Ok(TeeTarget::File(PathBuf::from("out.log")))
+        );
+        assert_eq!(
+            "udp://127.0.0.1:5140".parse(),
+            Ok(TeeTarget::Udp("127.0.0.1:5140".to_string()))
+        );
+        assert_eq!(
+            "exec:logger -t butt".parse(),
+            Ok(TeeTarget::Exec("logger -t butt".to_string()))
+        );
+        assert!("exec: ".parse::<TeeTarget>().is_err());
+    }
+
+    #[test]
+    fn file_tee_appends_events_as_plain_text() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("tee.log");
+        std::fs::write(&path, "existing\n").expect("seed file");
+
+        let mut tee = TeeSink::open(TeeTarget::File(path.clone())).expect("open tee");
+        tee.emit(&Event::Line(Line::new("ERR boom", true)));
+        tee.emit(&Event::Idle {
+            interval: Duration::from_secs(5),
+        });
+        assert!(tee.close(Duration::from_secs(5)));
+
+        let text = std::fs::read_to_string(&path).expect("read tee");
+        assert_eq!(text, "existing\nERR boom\n[no output for 5 seconds]\n");
+        assert_eq!(tee.dropped(), 0);
+    }
+
+    #[test]
+    fn udp_tee_sends_one_datagram_per_line() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").expect("bind receiver");
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("timeout");
+        let addr = receiver.local_addr().expect("addr").to_string();
+
+        let mut tee = TeeSink::open(TeeTarget::Udp(addr)).expect("open tee");
+        tee.write_line("hello");
+        let mut buf = [0_u8; 64];
+        let n = receiver.recv(&mut buf).expect("datagram");
+        assert_eq!(&buf[..n], b"hello");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_exec_tee_drops_lines_instead_of_blocking() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let out = tmp.path().join("exec.log");
+        let command = format!("sleep 1; cat > '{}'", out.display());
+        let mut tee = TeeSink::with_capacity(TeeTarget::Exec(command), 2).expect("open tee");
+
+        let started = Instant::now();
+        for i in 0..10_000 {
+            tee.write_line(format!("line {i}"));
+        }
+        assert!(
+            started.elapsed() < Duration::from_millis(500),
+            "writes never block"
+        );
+        assert!(tee.dropped() > 0);
+
+        assert!(tee.close(Duration::from_secs(10)));
+        let written = std::fs::read_to_string(&out).expect("read exec output");
+        assert!(written.starts_with("line 0\n"));
+    }
+}
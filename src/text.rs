@@ -0,0 +1,212 @@
+use clap::ValueEnum;
+use std::borrow::Cow;
+
+/// Character encoding of a source. Lines are converted to UTF-8 before they
+/// are split, matched or printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// UTF-8; invalid bytes are kept as they are and escaped when printed.
+    #[default]
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    /// ISO-8859-1: every byte is the code point of the same value.
+    #[value(name = "latin-1", alias = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+}
+
+/// Converts a stream in some [`Encoding`] to UTF-8, chunk by chunk.
+///
+/// Code units and surrogate pairs split across chunks are carried over, and
+/// a byte order mark at the start of a UTF-16 stream is dropped.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    encoding: Encoding,
+    /// An odd trailing byte of a UTF-16 code unit.
+    carry: Option<u8>,
+    /// A high surrogate waiting for its pair.
+    high_surrogate: Option<u16>,
+    started: bool,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            carry: None,
+            high_surrogate: None,
+            started: false,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The UTF-8 for `chunk`. UTF-8 input and ASCII Latin-1 are borrowed as is.
+    pub fn decode<'a>(&mut self, chunk: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.encoding {
+            Encoding::Utf8 => Cow::Borrowed(chunk),
+            Encoding::Latin1 if chunk.is_ascii() => Cow::Borrowed(chunk),
+            Encoding::Latin1 => Cow::Owned(
+                chunk
+                    .iter()
+                    .map(|&byte| char::from(byte))
+                    .collect::<String>()
+                    .into_bytes(),
+            ),
+            Encoding::Utf16Le => Cow::Owned(self.decode_utf16(chunk, u16::from_le_bytes)),
+            Encoding::Utf16Be => Cow::Owned(self.decode_utf16(chunk, u16::from_be_bytes)),
+        }
+    }
+
+    fn decode_utf16(&mut self, chunk: &[u8], unit: fn([u8; 2]) -> u16) -> Vec<u8> {
+        let mut out = String::with_capacity(chunk.len());
+        let mut bytes = self.carry.take().into_iter().chain(chunk.iter().copied());
+        while let Some(first) = bytes.next() {
+            let Some(second) = bytes.next() else {
+                self.carry = Some(first);
+                break;
+            };
+            let unit = unit([first, second]);
+            if !self.started {
+                self.started = true;
+                if unit == 0xfeff {
+                    continue;
+                }
+            }
+            if let Some(high) = self.high_surrogate.take() {
+                if (0xdc00..=0xdfff).contains(&unit) {
+                    let code =
+                        0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(unit) - 0xdc00);
+                    out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    continue;
+                }
+                out.push(char::REPLACEMENT_CHARACTER);
+            }
+            if (0xd800..=0xdbff).contains(&unit) {
+                self.high_surrogate = Some(unit);
+            } else {
+                out.push(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+        }
+        out.into_bytes()
+    }
+}
+
+/// Lines with more than this share of control bytes are treated as binary.
+const BINARY_CONTROL_RATIO: f64 = 0.1;
+
+/// Whether `line` looks like binary data rather than text: it contains a NUL
+/// byte, or more than a tenth of its bytes are control characters other than
+/// tab and escape.
+pub fn is_binary(line: &[u8]) -> bool {
+    if line.contains(&0) {
+        return true;
+    }
+    let controls = line
+        .iter()
+        .filter(|&&byte| (byte < 0x20 && byte != b'\t' && byte != 0x1b) || byte == 0x7f)
+        .count();
+    controls as f64 > line.len() as f64 * BINARY_CONTROL_RATIO
+}
+
+/// What butt prints in place of a binary line.
+pub fn binary_summary(len: usize) -> String {
+    format!("[binary data: {len} bytes]")
+}
+
+/// Append `bytes` to `out` so it is safe to print to a terminal: control
+/// characters (C0, DEL and C1) and bytes that are not valid UTF-8 become
+/// visible `\xNN` escapes, so input cannot move the cursor, change colors or
+/// retitle the window. Tabs are kept.
+pub fn escape_into(out: &mut Vec<u8>, bytes: &[u8]) {
+    for chunk in bytes.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            if needs_escape(ch) {
+                // Control characters are all below U+0100, so they fit a byte.
+                push_escape(out, ch as u8);
+            } else {
+                let mut buf = [0; 4];
+                out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        for &byte in chunk.invalid() {
+            push_escape(out, byte);
+        }
+    }
+}
+
+/// `bytes` made safe to print; see [`escape_into`]. Borrowed when nothing changes.
+pub fn escape(bytes: &[u8]) -> Cow<'_, [u8]> {
+    let clean = bytes
+        .utf8_chunks()
+        .all(|chunk| chunk.invalid().is_empty() && !chunk.valid().chars().any(needs_escape));
+    if clean {
+        return Cow::Borrowed(bytes);
+    }
+    let mut out = Vec::with_capacity(bytes.len() + 8);
+    escape_into(&mut out, bytes);
+    Cow::Owned(out)
+}
+
+fn needs_escape(ch: char) -> bool {
+    ch != '\t' && ch.is_control()
+}
+
+fn push_escape(out: &mut Vec<u8>, byte: u8) {
+    out.extend_from_slice(format!("\\x{byte:02x}").as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_control_characters_and_invalid_bytes() {
+        assert_eq!(
+            escape(b"red \x1b[31mtext\x07\tok \xff").as_ref(),
+            b"red \\x1b[31mtext\\x07\tok \\xff"
+        );
+        assert_eq!(
+            escape("caf\u{e9} \u{9b}".as_bytes()).as_ref(),
+            "caf\u{e9} \\x9b".as_bytes()
+        );
+        assert!(matches!(escape(b"plain\ttext"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn detects_binary_lines() {
+        assert!(is_binary(b"ELF\x02\x01\x01\0\0\0"));
+        assert!(is_binary(b"\x01\x02\x03 junk"));
+        assert!(!is_binary(b"plain text\twith a tab"));
+        assert!(!is_binary(b"\x1b[31mcolored\x1b[0m log line"));
+        assert!(!is_binary("caf\u{e9} na\u{ef}ve".as_bytes()));
+    }
+
+    #[test]
+    fn decodes_latin1() {
+        let mut decoder = Decoder::new(Encoding::Latin1);
+        assert_eq!(decoder.decode(b"caf\xe9").as_ref(), "caf\u{e9}".as_bytes());
+        assert!(matches!(decoder.decode(b"ascii"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn decodes_utf16_across_chunk_boundaries() {
+        let text = "\u{feff}h\u{e9} \u{1f600}\n";
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut decoder = Decoder::new(Encoding::Utf16Le);
+        let mut out = Vec::new();
+        for chunk in bytes.chunks(3) {
+            out.extend_from_slice(&decoder.decode(chunk));
+        }
+        assert_eq!(String::from_utf8(out).expect("utf8"), "h\u{e9} \u{1f600}\n");
+
+        let bytes: Vec<u8> = "ok\n".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut decoder = Decoder::new(Encoding::Utf16Be);
+        assert_eq!(decoder.decode(&bytes).as_ref(), b"ok\n");
+    }
+}
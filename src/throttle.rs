@@ -0,0 +1,554 @@
+use crate::clock::{Clock, SystemClock};
+use crate::output::Sink;
+use crate::pattern::Pattern;
+use crate::stats::StreamStats;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Timing settings for a [`Throttler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleConfig {
+    /// Emit at most one regular line per interval.
+    pub line_interval: Duration,
+    /// Emit an idle notice after this long without output.
+    pub idle_interval: Option<Duration>,
+}
+
+impl ThrottleConfig {
+    pub fn new(line_interval: Duration) -> Self {
+        Self {
+            line_interval,
+            idle_interval: None,
+        }
+    }
+
+    pub fn idle_interval(mut self, idle_interval: Duration) -> Self {
+        self.idle_interval = Some(idle_interval);
+        self
+    }
+}
+
+/// A pattern that should keep showing up, such as a periodic `health ok`
+/// line: an [`Event::Missed`] notice is emitted whenever no line has matched
+/// it for `within`.
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    pub pattern: Pattern,
+    pub within: Duration,
+}
+
+impl Expectation {
+    pub fn new(pattern: Pattern, within: Duration) -> Self {
+        Self { pattern, within }
+    }
+}
+
+/// An input line chosen for output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// The line as read, without its line ending. It need not be valid UTF-8.
+    pub bytes: Vec<u8>,
+    /// The line matched the urgent pattern and bypassed throttling.
+    pub urgent: bool,
+    /// Byte ranges where the pattern matched, found while classifying the
+    /// line, so highlighting need not search again. Empty when nothing
+    /// matched or the line was urgent for another reason.
+    pub matches: Vec<Range<usize>>,
+    /// The sender the line came from, such as a [`Listener`](crate::Listener)
+    /// connection; printed as a `[peer] ` prefix.
+    pub peer: Option<String>,
+}
+
+impl Line {
+    pub fn new(bytes: impl Into<Vec<u8>>, urgent: bool) -> Self {
+        Self {
+            bytes: bytes.into(),
+            urgent,
+            matches: Vec::new(),
+            peer: None,
+        }
+    }
+
+    pub fn with_peer(mut self, peer: Option<String>) -> Self {
+        self.peer = peer;
+        self
+    }
+
+    /// The line as text; invalid UTF-8 is replaced with U+FFFD.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.bytes)
+    }
+}
+
+/// Something a [`Throttler`] wants shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Line(Line),
+    /// Nothing has been emitted for `interval`.
+    Idle {
+        interval: Duration,
+    },
+    /// No line has matched the expected `pattern` for `within`.
+    Missed {
+        pattern: String,
+        within: Duration,
+    },
+}
+
+/// Decides which input lines to show: urgent matches immediately, everything
+/// else at most once per interval, plus idle notices when the stream goes quiet
+/// and missed notices when an [`Expectation`] stops matching.
+///
+/// The throttler never blocks; call [`observe`](Self::observe) for each input
+/// line and [`tick`](Self::tick) regularly so timed output happens.
+pub struct Throttler<C: Clock = SystemClock> {
+    clock: C,
+    config: ThrottleConfig,
+    pattern: Option<Pattern>,
+    next_line_emit: Instant,
+    next_idle_emit: Option<Instant>,
+    last_output: Instant,
+    latest_line: Option<Vec<u8>>,
+    latest_peer: Option<String>,
+    /// The sender of the lines being observed, set with [`set_peer`](Self::set_peer).
+    peer: Option<String>,
+    /// Each expectation with the time its next missed notice is due.
+    expectations: Vec<(Expectation, Instant)>,
+    paused: bool,
+    stats: StreamStats,
+}
+
+impl Throttler<SystemClock> {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+}
+
+impl<C: Clock> Throttler<C> {
+    pub fn with_clock(config: ThrottleConfig, clock: C) -> Self {
+        let now = clock.now();
+        Self {
+            next_line_emit: now + config.line_interval,
+            next_idle_emit: config.idle_interval.map(|idle| now + idle),
+            last_output: now,
+            latest_line: None,
+            latest_peer: None,
+            peer: None,
+            expectations: Vec::new(),
+            paused: false,
+            stats: StreamStats::new(now, config.idle_interval),
+            pattern: None,
+            config,
+            clock,
+        }
+    }
+
+    /// Lines matching `pattern` are urgent and emitted immediately.
+    pub fn with_pattern(mut self, pattern: Option<Pattern>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Watch for lines matching each expectation, starting now.
+    pub fn with_expectations(mut self, expectations: Vec<Expectation>) -> Self {
+        let now = self.clock.now();
+        self.expectations = expectations
+            .into_iter()
+            .map(|expectation| {
+                let due = now + expectation.within;
+                (expectation, due)
+            })
+            .collect();
+        self
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        self.pattern = pattern;
+    }
+
+    /// Label the lines observed from now on as coming from `peer`. Matching
+    /// sees only the line; the label travels with it in [`Line::peer`].
+    pub fn set_peer(&mut self, peer: Option<&str>) {
+        match (&mut self.peer, peer) {
+            (Some(current), Some(peer)) => {
+                current.clear();
+                current.push_str(peer);
+            }
+            (current, peer) => *current = peer.map(str::to_string),
+        }
+    }
+
+    pub fn pattern(&self) -> Option<&Pattern> {
+        self.pattern.as_ref()
+    }
+
+    pub fn config(&self) -> ThrottleConfig {
+        self.config
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn stats(&self) -> &StreamStats {
+        &self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut StreamStats {
+        &mut self.stats
+    }
+
+    /// Change the line interval, keeping the time already waited this round.
+    pub fn set_line_interval(&mut self, line_interval: Duration) {
+        let now = self.clock.now();
+        let elapsed = self
+            .config
+            .line_interval
+            .saturating_sub(self.next_line_emit.saturating_duration_since(now));
+        self.config.line_interval = line_interval;
+        self.next_line_emit = now + line_interval.saturating_sub(elapsed);
+    }
+
+    /// While paused nothing is emitted; the newest line is kept for later.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Time until the next throttled line may be emitted.
+    pub fn next_emit_in(&self) -> Duration {
+        self.next_line_emit
+            .saturating_duration_since(self.clock.now())
+    }
+
+    /// Time until [`tick`](Self::tick) would emit something, or `None` when
+    /// nothing is scheduled (paused, or no pending line, idle interval or
+    /// expectation).
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        if self.paused {
+            return None;
+        }
+        let now = self.clock.now();
+        let line = self.latest_line.as_ref().map(|_| self.next_line_emit);
+        let idle = self
+            .config
+            .idle_interval
+            .zip(self.next_idle_emit)
+            .map(|(idle, next)| next.max(self.last_output + idle));
+        let missed = self.expectations.iter().map(|(_, due)| *due).min();
+        [line, idle, missed]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    /// Whether a throttled line is waiting for its turn.
+    pub fn has_pending(&self) -> bool {
+        self.latest_line.is_some()
+    }
+
+    /// Observe one input line and return whether it was urgent. Only lines
+    /// that are emitted are copied into an [`Event`]; the pending line reuses
+    /// its buffer between emits.
+    pub fn observe<S: Sink + ?Sized>(&mut self, line: impl AsRef<[u8]>, sink: &mut S) -> bool {
+        self.observe_line(line.as_ref(), None, sink)
+    }
+
+    /// Observe a line that is urgent for a reason other than the pattern, such
+    /// as its syslog severity. `reason` is counted like a pattern in the stats.
+    pub fn observe_urgent<S: Sink + ?Sized>(
+        &mut self,
+        line: impl AsRef<[u8]>,
+        reason: &str,
+        sink: &mut S,
+    ) {
+        self.observe_line(line.as_ref(), Some(reason), sink);
+    }
+
+    fn observe_line<S: Sink + ?Sized>(
+        &mut self,
+        line: &[u8],
+        reason: Option<&str>,
+        sink: &mut S,
+    ) -> bool {
+        let now = self.clock.now();
+        self.stats.record_line(now);
+        for (expectation, due) in &mut self.expectations {
+            if expectation.pattern.is_match(line) {
+                *due = now + expectation.within;
+            }
+        }
+
+        let mut matches = Vec::new();
+        let urgent = match (reason, &self.pattern) {
+            (Some(reason), _) => {
+                self.stats.record_urgent(reason);
+                true
+            }
+            (None, Some(pattern)) => {
+                matches = pattern.find_ranges(line);
+                if !matches.is_empty() {
+                    self.stats.record_urgent(pattern.as_str());
+                }
+                !matches.is_empty()
+            }
+            (None, None) => false,
+        };
+
+        if urgent && !self.paused {
+            let line = Line {
+                bytes: line.to_vec(),
+                urgent: true,
+                matches,
+                peer: self.peer.clone(),
+            };
+            self.emit_line(line, now, sink);
+            self.latest_line = None;
+            self.next_line_emit = now + self.config.line_interval;
+            return true;
+        }
+
+        match &mut self.latest_line {
+            Some(latest) => {
+                latest.clear();
+                latest.extend_from_slice(line);
+            }
+            None => self.latest_line = Some(line.to_vec()),
+        }
+        self.latest_peer.clone_from(&self.peer);
+        urgent
+    }
+
+    /// Emit whatever is due at the current time.
+    pub fn tick<S: Sink + ?Sized>(&mut self, sink: &mut S) {
+        if self.paused {
+            return;
+        }
+
+        let now = self.clock.now();
+        if now >= self.next_line_emit {
+            self.emit_latest(now, sink);
+            self.next_line_emit = now + self.config.line_interval;
+        }
+
+        if let Some(idle_interval) = self.config.idle_interval
+            && now.duration_since(self.last_output) >= idle_interval
+            && self.next_idle_emit.is_some_and(|next| now >= next)
+        {
+            sink.emit(&Event::Idle {
+                interval: idle_interval,
+            });
+            self.next_idle_emit = Some(now + idle_interval);
+        }
+
+        for (expectation, due) in &mut self.expectations {
+            if now >= *due {
+                sink.emit(&Event::Missed {
+                    pattern: expectation.pattern.as_str().to_string(),
+                    within: expectation.within,
+                });
+                self.stats.missed_expectations += 1;
+                *due = now + expectation.within;
+            }
+        }
+    }
+
+    /// Emit the pending line now, even when paused, and restart the interval.
+    pub fn flush<S: Sink + ?Sized>(&mut self, sink: &mut S) {
+        let now = self.clock.now();
+        self.emit_latest(now, sink);
+        self.next_line_emit = now + self.config.line_interval;
+    }
+
+    fn emit_latest<S: Sink + ?Sized>(&mut self, now: Instant, sink: &mut S) {
+        if let Some(line) = self.latest_line.take() {
+            let line = Line::new(line, false).with_peer(self.latest_peer.take());
+            self.emit_line(line, now, sink);
+        }
+    }
+
+    fn emit_line<S: Sink + ?Sized>(&mut self, line: Line, now: Instant, sink: &mut S) {
+        sink.emit(&Event::Line(line));
+        self.stats.emitted += 1;
+        self.last_output = now;
+        self.next_idle_emit = self.config.idle_interval.map(|idle| now + idle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::VirtualClock;
+
+    fn lines(events: &[Event]) -> Vec<Cow<'_, str>> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Line(line) => Some(line.text()),
+                Event::Idle { .. } | Event::Missed { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emits_latest_line_once_per_interval() {
+        let clock = VirtualClock::new();
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(5)), &clock);
+        let mut events = Vec::new();
+
+        throttler.observe("one", &mut events);
+        throttler.observe("two", &mut events);
+        throttler.tick(&mut events);
+        assert!(events.is_empty());
+
+        clock.advance(Duration::from_secs(5));
+        throttler.tick(&mut events);
+        throttler.tick(&mut events);
+        assert_eq!(lines(&events), vec!["two"]);
+        assert_eq!(throttler.stats().suppressed(), 1);
+    }
+
+    #[test]
+    fn urgent_lines_bypass_throttle_and_reset_window() {
+        let clock = VirtualClock::new();
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(5)), &clock)
+                .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")));
+        let mut events = Vec::new();
+
+        throttler.observe("regular", &mut events);
+        clock.advance(Duration::from_secs(3));
+        assert!(throttler.observe("ERR now", &mut events));
+        let mut expected = Line::new("ERR now", true);
+        expected.matches.push(0..3);
+        assert_eq!(events, vec![Event::Line(expected)]);
+
+        clock.advance(Duration::from_secs(3));
+        throttler.tick(&mut events);
+        assert_eq!(events.len(), 1, "regular line dropped by urgent reset");
+    }
+
+    #[test]
+    fn emits_idle_notice_after_quiet_interval() {
+        let clock = VirtualClock::new();
+        let config =
+            ThrottleConfig::new(Duration::from_secs(60)).idle_interval(Duration::from_secs(2));
+        let mut throttler = Throttler::with_clock(config, &clock);
+        let mut events = Vec::new();
+
+        clock.advance(Duration::from_secs(2));
+        throttler.tick(&mut events);
+        clock.advance(Duration::from_secs(1));
+        throttler.tick(&mut events);
+        clock.advance(Duration::from_secs(1));
+        throttler.tick(&mut events);
+        assert_eq!(
+            events,
+            vec![
+                Event::Idle {
+                    interval: Duration::from_secs(2)
+                };
+                2
+            ]
+        );
+    }
+
+    #[test]
+    fn emits_missed_notice_until_expected_line_returns() {
+        let clock = VirtualClock::new();
+        let expectation = Expectation::new(
+            Pattern::new("health ok", false).expect("pattern"),
+            Duration::from_secs(30),
+        );
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(300)), &clock)
+                .with_expectations(vec![expectation]);
+        let mut events = Vec::new();
+        let missed = Event::Missed {
+            pattern: "health ok".to_string(),
+            within: Duration::from_secs(30),
+        };
+
+        clock.advance(Duration::from_secs(20));
+        throttler.observe("tick: health ok", &mut events);
+        throttler.observe("busy", &mut events);
+        assert_eq!(throttler.next_wakeup(), Some(Duration::from_secs(30)));
+        clock.advance(Duration::from_secs(29));
+        throttler.tick(&mut events);
+        assert!(events.is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        throttler.tick(&mut events);
+        throttler.tick(&mut events);
+        assert_eq!(events, vec![missed.clone()]);
+
+        // The notice repeats each window while the line stays away.
+        clock.advance(Duration::from_secs(30));
+        throttler.tick(&mut events);
+        assert_eq!(events, vec![missed.clone(), missed]);
+        assert_eq!(throttler.stats().missed_expectations, 2);
+    }
+
+    #[test]
+    fn observe_urgent_bypasses_throttle_without_pattern() {
+        let clock = VirtualClock::new();
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(5)), &clock);
+        let mut events = Vec::new();
+
+        throttler.observe_urgent("disk failing", "severity crit", &mut events);
+        assert_eq!(lines(&events), vec!["disk failing"]);
+        assert_eq!(throttler.stats().urgent_by_pattern["severity crit"], 1);
+    }
+
+    #[test]
+    fn pause_holds_output_until_flush() {
+        let clock = VirtualClock::new();
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(1)), &clock)
+                .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")));
+        let mut events = Vec::new();
+
+        throttler.set_paused(true);
+        throttler.observe("ERR while paused", &mut events);
+        clock.advance(Duration::from_secs(2));
+        throttler.tick(&mut events);
+        assert!(events.is_empty());
+
+        throttler.flush(&mut events);
+        assert_eq!(lines(&events), vec!["ERR while paused"]);
+    }
+
+    #[test]
+    fn next_wakeup_tracks_pending_line_and_idle_notice() {
+        let clock = VirtualClock::new();
+        let config =
+            ThrottleConfig::new(Duration::from_secs(5)).idle_interval(Duration::from_secs(8));
+        let mut throttler = Throttler::with_clock(config, &clock);
+        let mut events = Vec::new();
+        assert_eq!(throttler.next_wakeup(), Some(Duration::from_secs(8)));
+
+        clock.advance(Duration::from_secs(1));
+        throttler.observe("line", &mut events);
+        assert_eq!(throttler.next_wakeup(), Some(Duration::from_secs(4)));
+
+        throttler.set_paused(true);
+        assert_eq!(throttler.next_wakeup(), None);
+    }
+
+    #[test]
+    fn changing_interval_keeps_elapsed_time() {
+        let clock = VirtualClock::new();
+        let mut throttler =
+            Throttler::with_clock(ThrottleConfig::new(Duration::from_secs(5)), &clock);
+        clock.advance(Duration::from_secs(2));
+        throttler.set_line_interval(Duration::from_secs(3));
+        assert_eq!(throttler.next_emit_in(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,417 @@
+use regex::bytes::{Captures, Regex};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: &str = "Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec";
+
+const RFC3339: &str = r"(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})[T ](?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})(?:[.,](?P<frac>\d{1,9}))?(?P<tz>Z|[+-]\d{2}:?\d{2})?";
+const APACHE: &str = r"\[(?P<day>\d{2})/(?P<mon>[A-Z][a-z]{2})/(?P<year>\d{4}):(?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2}) (?P<tz>[+-]\d{4})\]";
+const SYSLOG: &str = r"\b(?P<mon>[A-Z][a-z]{2}) +(?P<day>\d{1,2}) (?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})\b";
+const EPOCH_MILLIS: &str = r"\b(?P<epoch_ms>1\d{12})\b";
+
+/// A way of writing the time a line was logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `2026-10-16T10:00:00.123Z`, also with a space instead of `T` or no zone.
+    Rfc3339,
+    /// `Oct 16 10:00:00`, in local time and the current year.
+    Syslog,
+    /// `[16/Oct/2026:10:00:00 +0000]`, as in Apache and nginx access logs.
+    Apache,
+    /// Milliseconds since the Unix epoch, such as `1792144800000`.
+    EpochMillis,
+    /// A strftime-style pattern such as `%d.%m.%Y %H:%M:%S`.
+    Custom(String),
+}
+
+impl TimestampFormat {
+    const BUILT_IN: [Self; 4] = [Self::Rfc3339, Self::Apache, Self::Syslog, Self::EpochMillis];
+
+    fn regex(&self) -> Result<Regex, String> {
+        let pattern = match self {
+            Self::Rfc3339 => RFC3339.to_string(),
+            Self::Syslog => SYSLOG.to_string(),
+            Self::Apache => APACHE.to_string(),
+            Self::EpochMillis => EPOCH_MILLIS.to_string(),
+            Self::Custom(format) => strftime_regex(format)?,
+        };
+        Regex::new(&pattern).map_err(|err| err.to_string())
+    }
+}
+
+impl fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rfc3339 => f.write_str("rfc3339"),
+            Self::Syslog => f.write_str("syslog"),
+            Self::Apache => f.write_str("apache"),
+            Self::EpochMillis => f.write_str("epoch-millis"),
+            Self::Custom(format) => f.write_str(format),
+        }
+    }
+}
+
+/// Finds and reads the timestamp in a line, trying each of its formats in turn.
+///
+/// Times without a zone are taken as local time; syslog times, which have no
+/// year, are placed in the year that puts them closest before now.
+#[derive(Debug, Clone)]
+pub struct TimestampParser {
+    formats: Vec<(TimestampFormat, Regex)>,
+    /// Seconds east of UTC for times without a zone.
+    local_offset: i64,
+}
+
+impl TimestampParser {
+    pub fn new(format: TimestampFormat) -> Result<Self, String> {
+        Self::with_formats(vec![format])
+    }
+
+    /// Every built-in format: RFC 3339, Apache, syslog and epoch milliseconds.
+    pub fn auto() -> Self {
+        Self::with_formats(TimestampFormat::BUILT_IN.to_vec())
+            .expect("built-in timestamp patterns compile")
+    }
+
+    fn with_formats(formats: Vec<TimestampFormat>) -> Result<Self, String> {
+        let formats = formats
+            .into_iter()
+            .map(|format| format.regex().map(|regex| (format, regex)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            formats,
+            local_offset: local_offset(),
+        })
+    }
+
+    /// Read zoneless times as `seconds` east of UTC instead of local time.
+    pub fn with_local_offset(mut self, seconds: i64) -> Self {
+        self.local_offset = seconds;
+        self
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &TimestampFormat> {
+        self.formats.iter().map(|(format, _)| format)
+    }
+
+    /// The time written in `line`, if any; `now` settles the year of syslog times.
+    pub fn parse(&self, line: &[u8], now: SystemTime) -> Option<SystemTime> {
+        self.formats.iter().find_map(|(_, regex)| {
+            let captures = regex.captures(line)?;
+            to_time(&captures, now, self.local_offset)
+        })
+    }
+}
+
+/// `auto`, a built-in format name, or a strftime pattern containing `%`.
+impl FromStr for TimestampParser {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let format = match value {
+            "auto" => return Ok(Self::auto()),
+            "rfc3339" | "iso8601" => TimestampFormat::Rfc3339,
+            "syslog" => TimestampFormat::Syslog,
+            "apache" | "clf" => TimestampFormat::Apache,
+            "epoch-millis" => TimestampFormat::EpochMillis,
+            custom if custom.contains('%') => TimestampFormat::Custom(custom.to_string()),
+            other => {
+                return Err(format!(
+                    "unknown timestamp format '{other}' (expected auto, rfc3339, syslog, apache, epoch-millis or a strftime pattern)"
+                ));
+            }
+        };
+        Self::new(format)
+    }
+}
+
+/// A change in whether lines arrive later than the `--max-lag` threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagChange {
+    Exceeded(Duration),
+    Recovered(Duration),
+}
+
+/// Tracks how far behind the wall clock line timestamps are, reporting once
+/// when the lag goes over the threshold and once when it comes back under.
+#[derive(Debug, Clone)]
+pub struct LagMonitor {
+    threshold: Option<Duration>,
+    lagging: bool,
+}
+
+impl LagMonitor {
+    pub fn new(threshold: Option<Duration>) -> Self {
+        Self {
+            threshold,
+            lagging: false,
+        }
+    }
+
+    /// How long ago `line_time` was at `now`; zero for times in the future.
+    pub fn lag(line_time: SystemTime, now: SystemTime) -> Duration {
+        now.duration_since(line_time).unwrap_or(Duration::ZERO)
+    }
+
+    pub fn observe(&mut self, lag: Duration) -> Option<LagChange> {
+        let threshold = self.threshold?;
+        match (self.lagging, lag > threshold) {
+            (false, true) => {
+                self.lagging = true;
+                Some(LagChange::Exceeded(lag))
+            }
+            (true, false) => {
+                self.lagging = false;
+                Some(LagChange::Recovered(lag))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_lagging(&self) -> bool {
+        self.lagging
+    }
+}
+
+/// The regex for a strftime pattern. Supports `%Y %y %m %d %e %H %M %S %T %b
+/// %f %z %s %%`; everything else matches literally.
+fn strftime_regex(format: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push_str(&regex::escape(&ch.to_string()));
+            continue;
+        }
+        let directive = chars
+            .next()
+            .ok_or_else(|| format!("'{format}' ends with a lone %"))?;
+        out.push_str(&match directive {
+            'Y' => r"(?P<year>\d{4})".to_string(),
+            'y' => r"(?P<year2>\d{2})".to_string(),
+            'm' => r"(?P<month>\d{1,2})".to_string(),
+            'd' => r"(?P<day>\d{1,2})".to_string(),
+            'e' => r" ?(?P<day>\d{1,2})".to_string(),
+            'H' => r"(?P<hour>\d{1,2})".to_string(),
+            'M' => r"(?P<minute>\d{2})".to_string(),
+            'S' => r"(?P<second>\d{2})".to_string(),
+            'T' => r"(?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})".to_string(),
+            'b' => format!("(?P<mon>{MONTHS})"),
+            'f' => r"(?P<frac>\d{1,9})".to_string(),
+            'z' => r"(?P<tz>Z|[+-]\d{2}:?\d{2})".to_string(),
+            's' => r"(?P<epoch_s>\d{1,12})".to_string(),
+            '%' => "%".to_string(),
+            other => return Err(format!("unsupported directive %{other} in '{format}'")),
+        });
+    }
+    Ok(out)
+}
+
+fn to_time(captures: &Captures<'_>, now: SystemTime, local_offset: i64) -> Option<SystemTime> {
+    let number = |name: &str| -> Option<i64> {
+        std::str::from_utf8(captures.name(name)?.as_bytes())
+            .ok()?
+            .parse()
+            .ok()
+    };
+    if let Some(millis) = number("epoch_ms") {
+        return UNIX_EPOCH.checked_add(Duration::from_millis(u64::try_from(millis).ok()?));
+    }
+    if let Some(seconds) = number("epoch_s") {
+        return UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(seconds).ok()?));
+    }
+    let month = match captures.name("mon") {
+        Some(name) => {
+            MONTHS
+                .split('|')
+                .position(|month| month.as_bytes() == name.as_bytes())? as i64
+                + 1
+        }
+        None => number("month")?,
+    };
+    let (day, hour) = (number("day")?, number("hour").unwrap_or(0));
+    let (minute, second) = (number("minute").unwrap_or(0), number("second").unwrap_or(0));
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let offset = match captures.name("tz").map(|tz| tz.as_bytes()) {
+        Some(b"Z") => 0,
+        Some(tz) => zone_offset(tz)?,
+        None => local_offset,
+    };
+    let at = |year: i64| {
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset
+    };
+    let seconds = match (number("year"), number("year2")) {
+        (Some(year), _) => at(year),
+        (None, Some(year)) => at(2000 + year),
+        (None, None) => {
+            // No year: use this year, or last year if that would be more than a day ahead.
+            let now_secs = now.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+            let year = civil_year(now_secs.div_euclid(86_400));
+            let this_year = at(year);
+            if this_year > now_secs + 86_400 {
+                at(year - 1)
+            } else {
+                this_year
+            }
+        }
+    };
+    let nanos = captures.name("frac").map_or(0, |frac| {
+        let digits = frac.as_bytes();
+        digits
+            .iter()
+            .chain(std::iter::repeat(&b'0'))
+            .take(9)
+            .fold(0u32, |total, digit| total * 10 + u32::from(digit - b'0'))
+    });
+    UNIX_EPOCH.checked_add(Duration::new(u64::try_from(seconds).ok()?, nanos))
+}
+
+/// Seconds east of UTC for `+hh:mm`, `+hhmm` or `-hh:mm`.
+fn zone_offset(tz: &[u8]) -> Option<i64> {
+    let sign = match tz.first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: Vec<i64> = tz[1..]
+        .iter()
+        .filter(|byte| byte.is_ascii_digit())
+        .map(|digit| i64::from(digit - b'0'))
+        .collect();
+    let [h1, h2, m1, m2] = digits[..] else {
+        return None;
+    };
+    Some(sign * ((h1 * 10 + h2) * 3600 + (m1 * 10 + m2) * 60))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year containing `days` since 1970-01-01.
+fn civil_year(days: i64) -> i64 {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    era * 400 + year_of_era + i64::from(month_index >= 10)
+}
+
+/// The local zone's current offset from UTC in seconds.
+#[cfg(unix)]
+fn local_offset() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as libc::time_t);
+    // SAFETY: `localtime_r` only writes to the `tm` we pass in.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            0
+        } else {
+            tm.tm_gmtoff as i64
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn local_offset() -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    /// 2026-10-16T10:00:00Z.
+    const TEN_AM: u64 = 1_792_144_800;
+
+    #[test]
+    fn parses_built_in_formats() {
+        let parser = TimestampParser::auto().with_local_offset(3600);
+        let now = at(TEN_AM + 300);
+        let parse = |line: &str| parser.parse(line.as_bytes(), now);
+        assert_eq!(parse("2026-10-16T10:00:00Z started"), Some(at(TEN_AM)));
+        assert_eq!(
+            parse("ts=2026-10-16 12:00:00.250+02:00 ok"),
+            Some(at(TEN_AM) + Duration::from_millis(250))
+        );
+        assert_eq!(
+            parse("1.2.3.4 - - [16/Oct/2026:06:00:00 -0400] \"GET / HTTP/1.1\" 200"),
+            Some(at(TEN_AM))
+        );
+        assert_eq!(parse("Oct 16 11:00:00 host app: hi"), Some(at(TEN_AM)));
+        assert_eq!(parse("{\"ts\":1792144800000}"), Some(at(TEN_AM)));
+        assert_eq!(parse("2026-10-16 11:00:00 local"), Some(at(TEN_AM)));
+        assert_eq!(parse("no time here"), None);
+        assert_eq!(parse("2026-13-40T10:00:00Z"), None);
+    }
+
+    #[test]
+    fn puts_syslog_times_in_the_past_year() {
+        let parser = TimestampParser::new(TimestampFormat::Syslog)
+            .expect("parser")
+            .with_local_offset(0);
+        // Early January; a December line belongs to last year.
+        let now = at(1_767_225_600 + 3600); // 2026-01-01T01:00:00Z
+        assert_eq!(
+            parser.parse(b"Dec 31 23:59:00 host app: bye", now),
+            Some(at(1_767_225_600 - 60))
+        );
+    }
+
+    #[test]
+    fn parses_custom_strftime_patterns() {
+        let parser: TimestampParser = "%d.%m.%Y %T".parse().expect("parser");
+        let parser = parser.with_local_offset(0);
+        assert_eq!(
+            parser.parse(b"INFO 16.10.2026 10:00:00 ready", at(0)),
+            Some(at(TEN_AM))
+        );
+        assert!("%Q".parse::<TimestampParser>().is_err());
+        assert!("nonsense".parse::<TimestampParser>().is_err());
+    }
+
+    #[test]
+    fn reports_lag_over_the_threshold_once() {
+        let mut monitor = LagMonitor::new(Some(Duration::from_secs(60)));
+        assert_eq!(
+            LagMonitor::lag(at(TEN_AM), at(TEN_AM + 300)),
+            Duration::from_secs(300)
+        );
+        assert_eq!(LagMonitor::lag(at(TEN_AM + 5), at(TEN_AM)), Duration::ZERO);
+        assert_eq!(monitor.observe(Duration::from_secs(5)), None);
+        assert_eq!(
+            monitor.observe(Duration::from_secs(300)),
+            Some(LagChange::Exceeded(Duration::from_secs(300)))
+        );
+        assert_eq!(monitor.observe(Duration::from_secs(400)), None);
+        assert!(monitor.is_lagging());
+        assert_eq!(
+            monitor.observe(Duration::from_secs(1)),
+            Some(LagChange::Recovered(Duration::from_secs(1)))
+        );
+    }
+}
@@ -0,0 +1,696 @@
+use crate::hook::HookKind;
+use crate::stats::StreamStats;
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Most alerts sent in one request; a longer burst continues in the next batch.
+const MAX_BATCH: usize = 100;
+
+/// Built-in request bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WebhookFormat {
+    /// `{"source", "count", "events", "counters", "text"}`.
+    Json,
+    /// `{"text"}`, accepted by Slack, Mattermost and Discord-compatible endpoints.
+    Slack,
+}
+
+/// Stream counters at the time of an alert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AlertCounters {
+    pub lines: u64,
+    pub emitted: u64,
+    pub suppressed: u64,
+    pub urgent: u64,
+}
+
+impl From<&StreamStats> for AlertCounters {
+    fn from(stats: &StreamStats) -> Self {
+        Self {
+            lines: stats.lines,
+            emitted: stats.emitted,
+            suppressed: stats.suppressed(),
+            urgent: stats.urgent,
+        }
+    }
+}
+
+/// An urgent line, idle notice or missed notice to report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: HookKind,
+    /// The urgent line; `None` for idle notices.
+    pub line: Option<String>,
+    /// The regex or reason (such as `severity err`) that made the line
+    /// urgent, or the `--expect` regex that stopped matching.
+    pub pattern: Option<String>,
+    /// The idle interval; `None` for other alerts.
+    pub idle_seconds: Option<u64>,
+    /// The `--within` window of a missed notice; `None` for other alerts.
+    pub within_seconds: Option<u64>,
+    /// Named capture groups of the match, in pattern order.
+    pub groups: Vec<(String, String)>,
+    pub counters: AlertCounters,
+}
+
+impl Alert {
+    pub fn matched(line: &str, pattern: &str) -> Self {
+        Self {
+            kind: HookKind::Match,
+            line: Some(line.to_string()),
+            pattern: Some(pattern.to_string()),
+            idle_seconds: None,
+            within_seconds: None,
+            groups: Vec::new(),
+            counters: AlertCounters::default(),
+        }
+    }
+
+    pub fn idle(interval: Duration) -> Self {
+        Self {
+            kind: HookKind::Idle,
+            line: None,
+            pattern: None,
+            idle_seconds: Some(interval.as_secs()),
+            within_seconds: None,
+            groups: Vec::new(),
+            counters: AlertCounters::default(),
+        }
+    }
+
+    pub fn missed(pattern: &str, within: Duration) -> Self {
+        Self {
+            kind: HookKind::Missed,
+            line: None,
+            pattern: Some(pattern.to_string()),
+            idle_seconds: None,
+            within_seconds: Some(within.as_secs()),
+            groups: Vec::new(),
+            counters: AlertCounters::default(),
+        }
+    }
+
+    pub fn with_groups(mut self, groups: Vec<(String, String)>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        match self.kind {
+            HookKind::Match => json!({
+                "kind": "match",
+                "line": self.line,
+                "pattern": self.pattern,
+                "groups": self
+                    .groups
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                    .collect::<serde_json::Map<_, _>>(),
+            }),
+            HookKind::Idle => json!({
+                "kind": "idle",
+                "idle_seconds": self.idle_seconds,
+            }),
+            HookKind::Missed => json!({
+                "kind": "missed",
+                "pattern": self.pattern,
+                "within_seconds": self.within_seconds,
+            }),
+        }
+    }
+
+    fn summary(&self) -> String {
+        match self.kind {
+            HookKind::Match => self.line.clone().unwrap_or_default(),
+            HookKind::Idle => format!(
+                "no output for {} seconds",
+                self.idle_seconds.unwrap_or_default()
+            ),
+            HookKind::Missed => format!(
+                "no line matching '{}' for {} seconds",
+                self.pattern.as_deref().unwrap_or_default(),
+                self.within_seconds.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// A request body with `{{name}}` placeholders.
+///
+/// `{{text}}`, `{{source}}` and `{{pattern}}` expand to JSON-escaped string
+/// contents (write them inside quotes); `{{count}}`, `{{lines}}`,
+/// `{{emitted}}`, `{{suppressed}}` and `{{urgent}}` to numbers; and
+/// `{{events}}` to the JSON array of alerts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(String);
+
+impl Template {
+    /// Accept `template` if it renders to valid JSON.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let template = Self(template.to_string());
+        let sample = [Alert::matched("sample \"line\"", "ERR")];
+        let rendered = template.render("source", &sample);
+        serde_json::from_str::<Value>(&rendered)
+            .map_err(|err| format!("does not render to valid JSON ({err}): {rendered}"))?;
+        Ok(template)
+    }
+
+    fn render(&self, source: &str, alerts: &[Alert]) -> String {
+        let escape = |text: &str| {
+            let quoted = Value::String(text.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        let counters = alerts
+            .last()
+            .map(|alert| alert.counters)
+            .unwrap_or_default();
+        let pattern = alerts
+            .iter()
+            .find_map(|alert| alert.pattern.as_deref())
+            .unwrap_or_default();
+        let events: Vec<Value> = alerts.iter().map(Alert::to_json).collect();
+        let values = [
+            ("text", escape(&summary_text(source, alerts))),
+            ("source", escape(source)),
+            ("pattern", escape(pattern)),
+            ("count", alerts.len().to_string()),
+            ("lines", counters.lines.to_string()),
+            ("emitted", counters.emitted.to_string()),
+            ("suppressed", counters.suppressed.to_string()),
+            ("urgent", counters.urgent.to_string()),
+            ("events", Value::Array(events).to_string()),
+        ];
+
+        // One pass over the template: substituted values come from log lines,
+        // so they are never scanned for placeholders themselves.
+        let mut body = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find("{{") {
+            body.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let value = after.find("}}").and_then(|end| {
+                let (_, value) = values.iter().find(|(name, _)| *name == &after[..end])?;
+                Some((value, end))
+            });
+            match value {
+                Some((value, end)) => {
+                    body.push_str(value);
+                    rest = &after[end + 2..];
+                }
+                None => {
+                    body.push_str("{{");
+                    rest = after;
+                }
+            }
+        }
+        body.push_str(rest);
+        body
+    }
+}
+
+/// What the body of each request looks like.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Format(WebhookFormat),
+    Template(Template),
+}
+
+impl Body {
+    /// The request body for a batch of alerts.
+    pub fn render(&self, source: &str, alerts: &[Alert]) -> String {
+        match self {
+            Self::Format(WebhookFormat::Json) => {
+                let counters = alerts
+                    .last()
+                    .map(|alert| alert.counters)
+                    .unwrap_or_default();
+                let events: Vec<Value> = alerts.iter().map(Alert::to_json).collect();
+                json!({
+                    "source": source,
+                    "count": alerts.len(),
+                    "events": events,
+                    "counters": {
+                        "lines": counters.lines,
+                        "emitted": counters.emitted,
+                        "suppressed": counters.suppressed,
+                        "urgent": counters.urgent,
+                    },
+                    "text": summary_text(source, alerts),
+                })
+                .to_string()
+            }
+            Self::Format(WebhookFormat::Slack) => {
+                json!({ "text": summary_text(source, alerts) }).to_string()
+            }
+            Self::Template(template) => template.render(source, alerts),
+        }
+    }
+}
+
+/// `butt SOURCE: line`, or a count followed by one line per alert.
+fn summary_text(source: &str, alerts: &[Alert]) -> String {
+    match alerts {
+        [alert] => format!("butt {source}: {}", alert.summary()),
+        _ => {
+            let mut text = format!("butt {source}: {} alerts", alerts.len());
+            for alert in alerts {
+                text.push('\n');
+                text.push_str(&alert.summary());
+            }
+            text
+        }
+    }
+}
+
+/// Batching, retry and queue limits for a [`Webhook`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookOptions {
+    pub body: Body,
+    /// Alerts arriving within this long of the first one share a request.
+    pub batch_window: Duration,
+    /// Alerts waiting to be sent; more are dropped while the endpoint is failing.
+    pub max_queue: usize,
+    /// Further attempts after a failed request.
+    pub retries: u32,
+    /// Delay before the first retry, doubling up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Limit for a whole request, from connecting to reading the response.
+    pub request_timeout: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        Self {
+            body: Body::Format(WebhookFormat::Json),
+            batch_window: Duration::from_secs(5),
+            max_queue: 1000,
+            retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Why a batch was not delivered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryFailure {
+    pub alerts: usize,
+    pub attempts: u32,
+    pub error: String,
+}
+
+impl fmt::Display for DeliveryFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "webhook gave up on {} alert(s) after {} attempt(s): {}",
+            self.alerts, self.attempts, self.error
+        )
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    failures: Mutex<Vec<DeliveryFailure>>,
+}
+
+/// POSTs [`Alert`]s to an HTTP(S) endpoint from a background thread.
+///
+/// [`send`](Self::send) never blocks: alerts are queued, grouped into one
+/// request per batch window, and retried with exponential backoff. When the
+/// queue is full, new alerts are dropped and counted.
+pub struct Webhook {
+    url: String,
+    tx: Option<SyncSender<Alert>>,
+    shared: Arc<Shared>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Webhook {
+    /// Start delivering to `url`, labelling requests with `source`.
+    pub fn start(url: &str, source: &str, options: WebhookOptions) -> Self {
+        let (tx, rx) = mpsc::sync_channel(options.max_queue);
+        let shared = Arc::new(Shared::default());
+        let worker = Worker {
+            url: url.to_string(),
+            source: source.to_string(),
+            agent: ureq::Agent::config_builder()
+                .timeout_global(Some(options.request_timeout))
+                .build()
+                .into(),
+            shared: Arc::clone(&shared),
+            options,
+        };
+        let handle = thread::spawn(move || worker.run(rx));
+        Self {
+            url: url.to_string(),
+            tx: Some(tx),
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Queue an alert without waiting for it to be delivered.
+    pub fn send(&mut self, alert: Alert) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if let Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) = tx.try_send(alert) {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Alerts dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Alerts the endpoint accepted.
+    pub fn delivered(&self) -> u64 {
+        self.shared.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Batches given up on since the last call.
+    pub fn take_failures(&self) -> Vec<DeliveryFailure> {
+        std::mem::take(&mut *self.shared.failures.lock().expect("webhook lock"))
+    }
+
+    /// Send queued alerts without waiting out the batch window, waiting at
+    /// most `timeout`. Returns `false` if delivery was still in progress.
+    pub fn close(&mut self, timeout: Duration) -> bool {
+        self.tx = None;
+        let Some(handle) = self.handle.take() else {
+            return true;
+        };
+        let deadline = Instant::now() + timeout;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = handle.join();
+        true
+    }
+}
+
+struct Worker {
+    url: String,
+    source: String,
+    agent: ureq::Agent,
+    shared: Arc<Shared>,
+    options: WebhookOptions,
+}
+
+impl Worker {
+    fn run(self, rx: Receiver<Alert>) {
+        let mut closing = false;
+        while !closing {
+            let Ok(first) = rx.recv() else {
+                return;
+            };
+            let mut batch = vec![first];
+            let deadline = Instant::now() + self.options.batch_window;
+            while batch.len() < MAX_BATCH {
+                match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(alert) => batch.push(alert),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        closing = true;
+                        break;
+                    }
+                }
+            }
+            self.deliver(&batch);
+        }
+        // Closed while batching: send what is still queued in full batches.
+        let rest: Vec<Alert> = rx.try_iter().collect();
+        for batch in rest.chunks(MAX_BATCH) {
+            self.deliver(batch);
+        }
+    }
+
+    fn deliver(&self, batch: &[Alert]) {
+        let body = self.options.body.render(&self.source, batch);
+        let mut backoff = self.options.initial_backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match self.post(&body) {
+                Ok(()) => {
+                    self.shared
+                        .delivered
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                    return;
+                }
+                Err(error) => error,
+            };
+            if !error.retry || attempts > self.options.retries {
+                self.shared
+                    .failures
+                    .lock()
+                    .expect("webhook lock")
+                    .push(DeliveryFailure {
+                        alerts: batch.len(),
+                        attempts,
+                        error: error.message,
+                    });
+                return;
+            }
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.options.max_backoff);
+        }
+    }
+
+    fn post(&self, body: &str) -> Result<(), PostError> {
+        match self
+            .agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(body)
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::StatusCode(status)) => Err(PostError {
+                // Client errors other than rate limiting will fail the same way again.
+                retry: !(400..500).contains(&status) || status == 429,
+                message: format!("HTTP {status}"),
+            }),
+            Err(err) => Err(PostError {
+                retry: true,
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+struct PostError {
+    retry: bool,
+    message: String,
+}
+
+/// Accept only `http://` and `https://` URLs.
+pub fn parse_url(url: &str) -> Result<String, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .ok_or_else(|| format!("expected an http:// or https:// URL, got '{url}'"))?;
+    if rest.is_empty() || rest.starts_with('/') {
+        return Err(format!("missing host in '{url}'"));
+    }
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// A local HTTP stand-in answering with `statuses` in turn and returning the bodies.
+    fn stand_in(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let url = format!("http://{}/hook", listener.local_addr().expect("addr"));
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().expect("accept");
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).expect("header");
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().expect("length");
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).expect("body");
+                bodies.push(String::from_utf8(body).expect("utf-8 body"));
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .expect("respond");
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    fn options(body: Body) -> WebhookOptions {
+        WebhookOptions {
+            body,
+            batch_window: Duration::from_millis(200),
+            initial_backoff: Duration::from_millis(10),
+            ..WebhookOptions::default()
+        }
+    }
+
+    #[test]
+    fn batches_alerts_within_the_window_into_one_json_request() {
+        let (url, server) = stand_in(vec![200]);
+        let mut webhook =
+            Webhook::start(&url, "app.log", options(Body::Format(WebhookFormat::Json)));
+        let mut first = Alert::matched("ERR one", "ERR")
+            .with_groups(vec![("code".to_string(), "one".to_string())]);
+        first.counters.lines = 3;
+        webhook.send(first);
+        let mut idle = Alert::idle(Duration::from_secs(30));
+        idle.counters = AlertCounters {
+            lines: 5,
+            emitted: 2,
+            suppressed: 3,
+            urgent: 1,
+        };
+        webhook.send(idle);
+        assert!(webhook.close(Duration::from_secs(5)));
+
+        let bodies = server.join().expect("server");
+        let body: Value = serde_json::from_str(&bodies[0]).expect("json body");
+        assert_eq!(body["source"], "app.log");
+        assert_eq!(body["count"], 2);
+        assert_eq!(body["events"][0]["line"], "ERR one");
+        assert_eq!(body["events"][0]["pattern"], "ERR");
+        assert_eq!(body["events"][0]["groups"]["code"], "one");
+        assert_eq!(body["events"][1]["idle_seconds"], 30);
+        assert_eq!(body["counters"]["suppressed"], 3);
+        assert_eq!(
+            body["text"],
+            "butt app.log: 2 alerts\nERR one\nno output for 30 seconds"
+        );
+        assert_eq!(webhook.delivered(), 2);
+    }
+
+    #[test]
+    fn retries_server_errors_with_backoff_but_not_client_errors() {
+        let (url, server) = stand_in(vec![503, 500, 200, 400]);
+        let mut options = options(Body::Format(WebhookFormat::Slack));
+        options.batch_window = Duration::ZERO;
+        let mut webhook = Webhook::start(&url, "stdin", options);
+
+        webhook.send(Alert::matched("ERR retried", "ERR"));
+        for _ in 0..500 {
+            if webhook.delivered() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        webhook.send(Alert::matched("ERR rejected", "ERR"));
+        assert!(webhook.close(Duration::from_secs(5)));
+
+        let bodies = server.join().expect("server");
+        assert_eq!(bodies.len(), 4);
+        assert_eq!(bodies[0], r#"{"text":"butt stdin: ERR retried"}"#);
+        assert_eq!(bodies[2], bodies[0]);
+        assert_eq!(webhook.delivered(), 1);
+        let failures = webhook.take_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            failures[0].to_string(),
+            "webhook gave up on 1 alert(s) after 1 attempt(s): HTTP 400"
+        );
+    }
+
+    #[test]
+    fn full_queue_drops_alerts_while_the_endpoint_is_down() {
+        let mut options = options(Body::Format(WebhookFormat::Json));
+        options.max_queue = 2;
+        options.retries = 0;
+        // Nothing listens on port 9 (discard), so every attempt fails.
+        let mut webhook = Webhook::start("http://127.0.0.1:9/", "stdin", options);
+        for i in 0..20 {
+            webhook.send(Alert::matched(&format!("ERR {i}"), "ERR"));
+        }
+        assert!(webhook.dropped() > 0);
+        assert!(webhook.close(Duration::from_secs(10)));
+        assert!(!webhook.take_failures().is_empty());
+    }
+
+    #[test]
+    fn missed_alerts_name_the_expected_pattern() {
+        let alert = Alert::missed("health ok", Duration::from_secs(60));
+        let body = Body::Format(WebhookFormat::Json).render("svc", &[alert]);
+        let body: Value = serde_json::from_str(&body).expect("json body");
+        assert_eq!(body["events"][0]["kind"], "missed");
+        assert_eq!(body["events"][0]["pattern"], "health ok");
+        assert_eq!(body["events"][0]["within_seconds"], 60);
+        assert_eq!(body["events"][0].get("idle_seconds"), None);
+        assert_eq!(
+            body["text"],
+            "butt svc: no line matching 'health ok' for 60 seconds"
+        );
+    }
+
+    #[test]
+    fn template_values_are_not_expanded_again() {
+        let template = Template::parse(
+            r#"{"text": "{{text}}", "pattern": "{{pattern}}", "n": {{count}}, "events": {{events}}}"#,
+        )
+        .expect("valid template");
+        let alert = Alert::matched("ERR x {{events}} y {{count}}", "{{text}}");
+        let body = Body::Template(template).render("app", &[alert]);
+        let body: Value = serde_json::from_str(&body).expect("body stays valid JSON");
+        assert_eq!(body["text"], "butt app: ERR x {{events}} y {{count}}");
+        assert_eq!(body["pattern"], "{{text}}");
+        assert_eq!(body["n"], 1);
+        assert_eq!(body["events"][0]["line"], "ERR x {{events}} y {{count}}");
+    }
+
+    #[test]
+    fn templates_escape_strings_and_must_render_json() {
+        let template =
+            Template::parse(r#"{"text": "{{text}}", "n": {{count}}, "src": "{{source}}"}"#)
+                .expect("valid template");
+        let body = Body::Template(template).render("a \"b\"", &[Alert::matched("x\ty", "x")]);
+        assert_eq!(
+            body,
+            r#"{"text": "butt a \"b\": x\ty", "n": 1, "src": "a \"b\""}"#
+        );
+        assert!(Template::parse(r#"{"text": {{text}}}"#).is_err());
+        assert_eq!(
+            parse_url("https://hooks.slack.com/x"),
+            Ok("https://hooks.slack.com/x".to_string())
+        );
+        assert!(parse_url("ftp://host").is_err());
+        assert!(parse_url("http://").is_err());
+    }
+}
@@ -1,3 +1,8 @@
+use butt::progress::{ProgressChange, Reading};
+use butt::sim::{Simulation, VirtualClock};
+use butt::{
+    Clock, Event, Expectation, FollowOptions, Follower, Pattern, ProgressTracker, ThrottleConfig,
+};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::process::{Command, Stdio};
@@ -49,96 +54,61 @@ fn unique_marker(prefix: &str) -> String {
 }
 
 #[test]
-fn prints_idle_message_after_configured_interval() {
-    let tmp = tempfile::tempdir().expect("tempdir");
-    let log = tmp.path().join("app.log");
-    File::create(&log).expect("create log file");
-
-    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
-        .args([
-            log.to_str().expect("utf8 path"),
-            "--line-seconds",
-            "60",
-            "--idle-seconds",
-            "1",
-            "--poll-millis",
-            "50",
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("spawn butt");
+fn simulation_prints_idle_notice_after_configured_interval() {
+    let config = ThrottleConfig::new(Duration::from_secs(60)).idle_interval(Duration::from_secs(1));
+    let mut sim = Simulation::new(config);
 
-    let (stdout_buf, stdout_handle) =
-        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
-    let (_stderr_buf, stderr_handle) =
-        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+    sim.advance(Duration::from_millis(1_500));
 
-    let found = wait_for_contains(
-        &stdout_buf,
-        "[no output for 1 seconds]",
-        Duration::from_secs(4),
+    assert_eq!(
+        sim.events(),
+        [(
+            Duration::from_secs(1),
+            Event::Idle {
+                interval: Duration::from_secs(1)
+            }
+        )]
     );
-
-    let _ = child.kill();
-    let _ = child.wait();
-    let _ = stdout_handle.join();
-    let _ = stderr_handle.join();
-
-    assert!(found, "idle message did not appear in stdout");
 }
 
 #[test]
-fn throttles_non_matching_stdin_to_one_line_per_interval() {
-    let marker = unique_marker("throttle");
-
-    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
-        .args([
-            "--line-seconds",
-            "1",
-            "--idle-seconds",
-            "60",
-            "--poll-millis",
-            "20",
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("spawn butt");
-
-    let (stdout_buf, stdout_handle) =
-        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
-    let (_stderr_buf, stderr_handle) =
-        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+fn simulation_throttles_burst_to_one_line_per_interval() {
+    let config = ThrottleConfig::new(Duration::from_secs(1)).idle_interval(Duration::from_secs(60));
+    let mut sim = Simulation::new(config);
 
-    let mut stdin = child.stdin.take().expect("stdin pipe");
     for i in 0..10 {
-        writeln!(stdin, "{marker}-msg-{i}").expect("write line");
+        sim.feed(format!("msg-{i}"));
     }
-    stdin.flush().expect("flush stdin");
-
-    let saw_first = wait_for_contains(&stdout_buf, &marker, Duration::from_secs(4));
-    assert!(saw_first, "expected at least one throttled line");
-
-    thread::sleep(Duration::from_millis(300));
+    sim.advance(Duration::from_millis(1_300));
 
-    let _ = child.kill();
-    let _ = child.wait();
-    let _ = stdout_handle.join();
-    let _ = stderr_handle.join();
+    assert_eq!(sim.emitted_lines(), vec!["msg-9"]);
+    assert_eq!(sim.events()[0].0, Duration::from_secs(1));
+    assert_eq!(sim.throttler().stats().suppressed(), 9);
+}
 
-    let out = stdout_buf.lock().expect("lock poisoned").clone();
-    let count = out.lines().filter(|line| line.contains(&marker)).count();
+#[test]
+fn regex_case_insensitive_matches_immediately() {
+    let stdout = run_on_stdin(
+        &[
+            "--line-seconds",
+            "60",
+            "--regex",
+            "err",
+            "--case-insensitive",
+            "--color",
+            "green",
+        ],
+        b"ERR uppercase\n",
+    );
     assert_eq!(
-        count, 1,
-        "expected exactly one throttled line, got output: {out}"
+        String::from_utf8_lossy(&stdout),
+        "\x1b[32mERR\x1b[0m uppercase\n"
     );
 }
 
 #[test]
-fn regex_matches_print_immediately_and_reset_throttle_window() {
-    let marker = unique_marker("regex");
+fn no_color_disables_highlight_sequences() {
+    let marker = unique_marker("nocolor");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
@@ -153,8 +123,8 @@ fn regex_matches_print_immediately_and_reset_throttle_window() {
             "--color",
             "green",
         ])
+        .env("NO_COLOR", "1")
         .env("CLICOLOR_FORCE", "1")
-        .env_remove("NO_COLOR")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -167,79 +137,63 @@ fn regex_matches_print_immediately_and_reset_throttle_window() {
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
     let mut stdin = child.stdin.take().expect("stdin pipe");
-    writeln!(stdin, "{marker} regular message").expect("write non-match");
-    stdin.flush().expect("flush non-match");
-    thread::sleep(Duration::from_millis(150));
-    writeln!(stdin, "{marker} ERR first").expect("write first match");
-    writeln!(stdin, "{marker} ERR second").expect("write second match");
-    stdin.flush().expect("flush matches");
-
-    let saw_first = wait_for_contains(
+    writeln!(stdin, "{marker} ERR without color").expect("write line");
+    stdin.flush().expect("flush line");
+
+    let saw_plain = wait_for_contains(
         &stdout_buf,
-        &format!("{marker} \x1b[32mERR\x1b[0m first"),
+        &format!("{marker} ERR without color"),
         Duration::from_secs(2),
     );
-    let saw_second = wait_for_contains(
+    let saw_ansi = wait_for_contains(
         &stdout_buf,
-        &format!("{marker} \x1b[32mERR\x1b[0m second"),
-        Duration::from_secs(2),
+        "\x1b[32mERR\x1b[0m",
+        Duration::from_millis(300),
     );
 
-    thread::sleep(Duration::from_millis(600));
-
     let _ = child.kill();
     let _ = child.wait();
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    let out = stdout_buf.lock().expect("lock poisoned").clone();
-    assert!(saw_first, "first regex match was not printed");
-    assert!(saw_second, "second regex match was not printed");
-    assert!(
-        !out.contains(&format!("{marker} regular message")),
-        "non-matching line should remain throttled after regex reset, output: {out}"
-    );
+    assert!(saw_plain, "expected plain matched line");
+    assert!(!saw_ansi, "did not expect ANSI color when NO_COLOR is set");
 }
 
+#[cfg(unix)]
 #[test]
-fn regex_case_insensitive_matches_immediately() {
-    let marker = unique_marker("regex-ci");
+fn no_follow_symlinks_blocks_symlink_targets() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let real = tmp.path().join("real.log");
+    let link = tmp.path().join("link.log");
+    File::create(&real).expect("create real file");
+    symlink(&real, &link).expect("create symlink");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
+            link.to_str().expect("utf8 path"),
+            "--no-follow-symlinks",
             "--line-seconds",
-            "2",
+            "60",
             "--idle-seconds",
             "60",
             "--poll-millis",
-            "20",
-            "--regex",
-            "err",
-            "--case-insensitive",
-            "--color",
-            "green",
+            "25",
         ])
-        .env("CLICOLOR_FORCE", "1")
-        .env_remove("NO_COLOR")
-        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
         .expect("spawn butt");
 
-    let (stdout_buf, stdout_handle) =
+    let (_stdout_buf, stdout_handle) =
         spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
-    let (_stderr_buf, stderr_handle) =
+    let (stderr_buf, stderr_handle) =
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
-    let mut stdin = child.stdin.take().expect("stdin pipe");
-    writeln!(stdin, "{marker} ERR uppercase").expect("write uppercase match");
-    stdin.flush().expect("flush line");
-
-    let saw_highlight = wait_for_contains(
-        &stdout_buf,
-        &format!("{marker} \x1b[32mERR\x1b[0m uppercase"),
-        Duration::from_secs(2),
+    let blocked = wait_for_contains(
+        &stderr_buf,
+        "symlink targets are not allowed by --no-follow-symlinks",
+        Duration::from_secs(3),
     );
 
     let _ = child.kill();
@@ -247,108 +201,63 @@ fn regex_case_insensitive_matches_immediately() {
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    assert!(
-        saw_highlight,
-        "case-insensitive regex match was not printed immediately"
-    );
+    assert!(blocked, "expected symlink block message");
 }
 
 #[test]
-fn follows_rotated_file_and_prints_matching_line() {
-    let tmp = tempfile::tempdir().expect("tempdir");
-    let log = tmp.path().join("app.log");
-    File::create(&log).expect("create log file");
+fn allowed_root_blocks_paths_outside_root() {
+    let root = tempfile::tempdir().expect("root dir");
+    let outside = tempfile::tempdir().expect("outside dir");
+    let outside_log = outside.path().join("outside.log");
+    File::create(&outside_log).expect("create outside log");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
-            log.to_str().expect("utf8 path"),
+            outside_log.to_str().expect("utf8 path"),
+            "--allowed-root",
+            root.path().to_str().expect("utf8 root"),
             "--line-seconds",
             "60",
             "--idle-seconds",
             "60",
             "--poll-millis",
             "25",
-            "--regex",
-            "rotate",
         ])
-        .env("CLICOLOR_FORCE", "1")
-        .env_remove("NO_COLOR")
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
         .expect("spawn butt");
 
-    let (stdout_buf, stdout_handle) =
+    let (_stdout_buf, stdout_handle) =
         spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
     let (stderr_buf, stderr_handle) =
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
-    thread::sleep(Duration::from_millis(300));
-
-    let rotated = tmp.path().join("app.log.1");
-    fs::rename(&log, &rotated).expect("rename current log");
-
-    {
-        let mut new_file = File::create(&log).expect("create rotated replacement");
-        writeln!(new_file, "line after rotate").expect("write rotated line");
-    }
-
-    let deadline = Instant::now() + Duration::from_secs(5);
-    let mut saw_reopen = false;
-    let mut saw_line = false;
-    while Instant::now() < deadline {
-        if !saw_reopen {
-            saw_reopen = wait_for_contains(&stderr_buf, "reopened", Duration::from_millis(200));
-        }
-        if !saw_line {
-            saw_line = wait_for_contains(
-                &stdout_buf,
-                "\x1b[33mrotate\x1b[0m",
-                Duration::from_millis(200),
-            );
-        }
-        if saw_reopen && saw_line {
-            break;
-        }
-
-        let mut new_file = std::fs::OpenOptions::new()
-            .append(true)
-            .open(&log)
-            .expect("open rotated replacement for append");
-        writeln!(new_file, "line after rotate").expect("append rotated line");
-    }
+    let blocked = wait_for_contains(&stderr_buf, "outside allowed root", Duration::from_secs(3));
 
     let _ = child.kill();
     let _ = child.wait();
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    let _ = saw_reopen;
-    assert!(
-        saw_line,
-        "did not observe matching line from rotated file after rotation"
-    );
+    assert!(blocked, "expected allowed-root block message");
 }
 
 #[test]
-fn no_color_disables_highlight_sequences() {
-    let marker = unique_marker("nocolor");
+fn status_bar_is_disabled_when_stdout_is_not_a_terminal() {
+    let marker = unique_marker("status");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
             "--line-seconds",
-            "2",
+            "1",
             "--idle-seconds",
             "60",
             "--poll-millis",
             "20",
-            "--regex",
-            "ERR",
-            "--color",
-            "green",
         ])
-        .env("NO_COLOR", "1")
         .env("CLICOLOR_FORCE", "1")
+        .env_remove("NO_COLOR")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -361,49 +270,40 @@ fn no_color_disables_highlight_sequences() {
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
     let mut stdin = child.stdin.take().expect("stdin pipe");
-    writeln!(stdin, "{marker} ERR without color").expect("write line");
+    writeln!(stdin, "{marker} sampled").expect("write line");
     stdin.flush().expect("flush line");
 
-    let saw_plain = wait_for_contains(
-        &stdout_buf,
-        &format!("{marker} ERR without color"),
-        Duration::from_secs(2),
-    );
-    let saw_ansi = wait_for_contains(
-        &stdout_buf,
-        "\x1b[32mERR\x1b[0m",
-        Duration::from_millis(300),
-    );
+    let saw_line = wait_for_contains(&stdout_buf, &marker, Duration::from_secs(3));
 
     let _ = child.kill();
     let _ = child.wait();
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    assert!(saw_plain, "expected plain matched line");
-    assert!(!saw_ansi, "did not expect ANSI color when NO_COLOR is set");
+    let out = stdout_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_line, "expected sampled line, output: {out}");
+    assert!(
+        !out.contains("\x1b[2K") && !out.contains("lines/s"),
+        "status bar should not be drawn into a pipe, output: {out:?}"
+    );
 }
 
-#[cfg(unix)]
 #[test]
-fn no_follow_symlinks_blocks_symlink_targets() {
-    let tmp = tempfile::tempdir().expect("tempdir");
-    let real = tmp.path().join("real.log");
-    let link = tmp.path().join("link.log");
-    File::create(&real).expect("create real file");
-    symlink(&real, &link).expect("create symlink");
+fn prints_json_summary_on_stdin_eof() {
+    let marker = unique_marker("summary");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
-            link.to_str().expect("utf8 path"),
-            "--no-follow-symlinks",
             "--line-seconds",
             "60",
-            "--idle-seconds",
-            "60",
             "--poll-millis",
-            "25",
+            "20",
+            "--regex",
+            "ERR",
+            "--summary",
+            "json",
         ])
+        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
@@ -414,36 +314,44 @@ fn no_follow_symlinks_blocks_symlink_targets() {
     let (stderr_buf, stderr_handle) =
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
-    let blocked = wait_for_contains(
-        &stderr_buf,
-        "symlink targets are not allowed by --no-follow-symlinks",
-        Duration::from_secs(3),
-    );
+    {
+        let mut stdin = child.stdin.take().expect("stdin pipe");
+        writeln!(stdin, "{marker} one").expect("write line");
+        writeln!(stdin, "{marker} ERR two").expect("write line");
+        writeln!(stdin, "{marker} three").expect("write line");
+    }
 
+    let saw_summary = wait_for_contains(&stderr_buf, "\"duration_secs\"", Duration::from_secs(3));
     let _ = child.kill();
     let _ = child.wait();
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    assert!(blocked, "expected symlink block message");
+    let err = stderr_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_summary, "expected JSON summary on EOF, stderr: {err}");
+    let summary = err
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .expect("summary line");
+    assert!(summary.contains("\"lines\":3"), "summary: {summary}");
+    assert!(
+        summary.contains("\"by_pattern\":{\"ERR\":1}"),
+        "summary: {summary}"
+    );
 }
 
+#[cfg(unix)]
 #[test]
-fn allowed_root_blocks_paths_outside_root() {
-    let root = tempfile::tempdir().expect("root dir");
-    let outside = tempfile::tempdir().expect("outside dir");
-    let outside_log = outside.path().join("outside.log");
-    File::create(&outside_log).expect("create outside log");
+fn sigusr1_prints_summary_and_sigint_exits_cleanly() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let log = tmp.path().join("app.log");
+    File::create(&log).expect("create log file");
 
     let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
         .args([
-            outside_log.to_str().expect("utf8 path"),
-            "--allowed-root",
-            root.path().to_str().expect("utf8 root"),
+            log.to_str().expect("utf8 path"),
             "--line-seconds",
             "60",
-            "--idle-seconds",
-            "60",
             "--poll-millis",
             "25",
         ])
@@ -457,12 +365,1089 @@ fn allowed_root_blocks_paths_outside_root() {
     let (stderr_buf, stderr_handle) =
         spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
 
-    let blocked = wait_for_contains(&stderr_buf, "outside allowed root", Duration::from_secs(3));
+    thread::sleep(Duration::from_millis(300));
+    let pid = child.id().to_string();
+    Command::new("kill")
+        .args(["-USR1", &pid])
+        .status()
+        .expect("send SIGUSR1");
+    let saw_summary = wait_for_contains(&stderr_buf, "summary after", Duration::from_secs(3));
+    let still_running = child.try_wait().expect("try_wait").is_none();
+
+    Command::new("kill")
+        .args(["-INT", &pid])
+        .status()
+        .expect("send SIGINT");
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut exit_status = None;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().expect("try_wait") {
+            exit_status = Some(status);
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
 
     let _ = child.kill();
     let _ = child.wait();
     let _ = stdout_handle.join();
     let _ = stderr_handle.join();
 
-    assert!(blocked, "expected allowed-root block message");
+    let err = stderr_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_summary, "expected summary on SIGUSR1, stderr: {err}");
+    assert!(still_running, "SIGUSR1 should not stop butt");
+    assert!(
+        exit_status.and_then(|status| status.code()) == Some(130),
+        "expected exit status 130 after a clean shutdown on SIGINT, got {exit_status:?}"
+    );
+    assert_eq!(
+        err.matches("summary after").count(),
+        2,
+        "expected a second summary on exit, stderr: {err}"
+    );
+}
+
+#[test]
+fn flushes_pending_line_and_tail_on_stdin_eof() {
+    let marker = unique_marker("eof");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            "--tail-on-exit",
+            "2",
+            "--summary",
+            "off",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+
+    let (stdout_buf, stdout_handle) =
+        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
+    let (_stderr_buf, stderr_handle) =
+        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+
+    {
+        let mut stdin = child.stdin.take().expect("stdin pipe");
+        for i in 0..4 {
+            writeln!(stdin, "{marker}-msg-{i}").expect("write line");
+        }
+    }
+
+    let saw_tail = wait_for_contains(&stdout_buf, "[last 2 lines]", Duration::from_secs(3));
+    let _ = child.wait();
+    let _ = stdout_handle.join();
+    let _ = stderr_handle.join();
+
+    let out = stdout_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_tail, "expected exit tail, output: {out}");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("{marker}-msg-3").as_str(),
+            "[last 2 lines]",
+            format!("{marker}-msg-2").as_str(),
+            format!("{marker}-msg-3").as_str(),
+        ],
+        "unexpected output: {out}"
+    );
+}
+
+/// Runs butt over `input` on stdin with `args` and returns what it printed.
+fn run_on_stdin(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(args)
+        .args(["--poll-millis", "20", "--summary", "off"])
+        .env("CLICOLOR_FORCE", "1")
+        .env_remove("NO_COLOR")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn butt");
+    child
+        .stdin
+        .take()
+        .expect("stdin pipe")
+        .write_all(input)
+        .expect("write input");
+    child.wait_with_output().expect("wait for butt").stdout
+}
+
+#[test]
+fn escapes_invalid_utf8_and_control_characters() {
+    let stdout = run_on_stdin(
+        &["--regex", "ERR", "--line-seconds", "60"],
+        b"ERR \xff\xfe latin-1\nplain \xc3( \x1b]0;title\x07\n",
+    );
+    assert_eq!(
+        stdout,
+        b"\x1b[33mERR\x1b[0m \\xff\\xfe latin-1\nplain \\xc3( \\x1b]0;title\\x07\n".to_vec(),
+        "unexpected output: {:?}",
+        String::from_utf8_lossy(&stdout)
+    );
+}
+
+#[test]
+fn strips_input_colors_before_matching() {
+    let stdout = run_on_stdin(
+        &[
+            "--regex",
+            "^ERROR",
+            "--ansi",
+            "strip",
+            "--line-seconds",
+            "60",
+        ],
+        b"\x1b[31mERROR\x1b[0m: disk full\n\x1b[32mok\x1b[0m\n",
+    );
+    assert_eq!(
+        stdout,
+        b"\x1b[33mERROR\x1b[0m: disk full\nok\n".to_vec(),
+        "unexpected output: {:?}",
+        String::from_utf8_lossy(&stdout)
+    );
+}
+
+#[test]
+fn paints_whole_lines_in_a_truecolor_style() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--regex",
+            "ERR",
+            "--color",
+            "bold,#ff8800",
+            "--highlight",
+            "line",
+        ])
+        .args([
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            "--summary",
+            "off",
+        ])
+        .env("CLICOLOR_FORCE", "1")
+        .env("COLORTERM", "truecolor")
+        .env_remove("NO_COLOR")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn butt");
+    child
+        .stdin
+        .take()
+        .expect("stdin pipe")
+        .write_all(b"an ERR here\n")
+        .expect("write input");
+    let stdout = child.wait_with_output().expect("wait for butt").stdout;
+    assert_eq!(
+        String::from_utf8(stdout).expect("utf8 output"),
+        "\x1b[1;38;2;255;136;0man ERR here\x1b[0m\n"
+    );
+}
+
+#[test]
+fn paints_named_groups_and_rejects_unknown_ones() {
+    let stdout = run_on_stdin(
+        &[
+            "--regex",
+            r"status=(?P<status>\d+)",
+            "--group-color",
+            "status=bold,red",
+            "--line-seconds",
+            "60",
+        ],
+        b"GET / status=503\n",
+    );
+    assert_eq!(
+        String::from_utf8(stdout).expect("utf8 output"),
+        "GET / \x1b[33mstatus=\x1b[0m\x1b[1;31m503\x1b[0m\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--regex", "(?P<status>\\d+)", "--group-color", "code=red"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run butt");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("no group named 'code'"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn summarizes_binary_lines_and_decodes_latin1() {
+    let binary = run_on_stdin(&["--line-seconds", "60"], b"\x7fELF\x02\x01\x01\0\0\n");
+    assert_eq!(binary, b"[binary data: 9 bytes]\n".to_vec());
+
+    let latin1 = run_on_stdin(
+        &["--encoding", "latin-1", "--line-seconds", "60"],
+        b"caf\xe9 ouvert\n",
+    );
+    assert_eq!(
+        String::from_utf8(latin1).expect("utf8 output"),
+        "caf\u{e9} ouvert\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn flushes_pending_line_on_sigterm() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let all_path = tmp.path().join("all.log");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "25",
+            "--summary",
+            "off",
+        ])
+        .arg("--tee-all")
+        .arg(&all_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("spawn butt");
+    let mut stdin = child.stdin.take().expect("stdin pipe");
+    stdin.write_all(b"first\nlast\n").expect("write lines");
+
+    // --tee-all is written as lines are read, so it shows when both are pending.
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while !fs::read_to_string(&all_path).is_ok_and(|all| all.contains("last"))
+        && Instant::now() < deadline
+    {
+        thread::yield_now();
+    }
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("send SIGTERM");
+    let output = child.wait_with_output().expect("wait for butt");
+    drop(stdin);
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "last\n",
+        "only the pending line should be flushed"
+    );
+    assert_eq!(output.status.code(), Some(143));
+}
+
+#[test]
+fn simulation_flush_emits_the_pending_line() {
+    let mut sim = Simulation::new(ThrottleConfig::new(Duration::from_secs(60)));
+
+    sim.feed("first");
+    sim.feed("last");
+    sim.advance(Duration::from_millis(500));
+    assert!(sim.events().is_empty());
+    sim.flush();
+
+    assert_eq!(sim.emitted_lines(), vec!["last"]);
+    assert_eq!(sim.events()[0].0, Duration::from_millis(500));
+}
+
+#[test]
+fn print_config_merges_user_and_local_profiles() {
+    let home = tempfile::tempdir().expect("config home");
+    let work = tempfile::tempdir().expect("work dir");
+    fs::create_dir_all(home.path().join("butt")).expect("create config dir");
+    fs::write(
+        home.path().join("butt").join("config.toml"),
+        "[profile.java-service]\nline-seconds = 10\nregex = \"ERROR|FATAL\"\ncolor = \"red\"\n",
+    )
+    .expect("write user config");
+    fs::write(
+        work.path().join(".butt.toml"),
+        "[profile.java-service]\ncolor = \"cyan\"\n",
+    )
+    .expect("write local config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--profile", "java-service", "-i", "30", "--print-config"])
+        .env("XDG_CONFIG_HOME", home.path())
+        .current_dir(work.path())
+        .output()
+        .expect("run butt");
+
+    let out = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(out.contains("line-seconds = 10  # profile"), "{out}");
+    assert!(out.contains("regex = \"ERROR|FATAL\"  # profile"), "{out}");
+    assert!(out.contains("color = \"cyan\"  # profile"), "{out}");
+    assert!(out.contains("idle-seconds = 30  # cli"), "{out}");
+}
+
+#[test]
+fn unknown_profile_exits_with_error() {
+    let home = tempfile::tempdir().expect("config home");
+    let work = tempfile::tempdir().expect("work dir");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--profile", "nope"])
+        .env("XDG_CONFIG_HOME", home.path())
+        .current_dir(work.path())
+        .output()
+        .expect("run butt");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("profile 'nope' not found"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn environment_overrides_profile_but_not_command_line() {
+    let home = tempfile::tempdir().expect("config home");
+    let work = tempfile::tempdir().expect("work dir");
+    fs::write(
+        work.path().join(".butt.toml"),
+        "[profile.svc]\nline-seconds = 10\nidle-seconds = 60\n",
+    )
+    .expect("write local config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--idle-seconds", "5", "--print-config"])
+        .env("XDG_CONFIG_HOME", home.path())
+        .env("BUTT_PROFILE", "svc")
+        .env("BUTT_LINE_SECONDS", "7")
+        .env("BUTT_IDLE_SECONDS", "9")
+        .env("BUTT_REGEX", "ERROR|FATAL")
+        .env("BUTT_NO_STATUS_BAR", "yes")
+        .current_dir(work.path())
+        .output()
+        .expect("run butt");
+
+    let out = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        out.contains("# effective settings (profile 'svc')"),
+        "{out}"
+    );
+    assert!(out.contains("line-seconds = 7  # env"), "{out}");
+    assert!(out.contains("idle-seconds = 5  # cli"), "{out}");
+    assert!(out.contains("regex = \"ERROR|FATAL\"  # env"), "{out}");
+    assert!(out.contains("no-status-bar = true  # env"), "{out}");
+}
+
+#[test]
+fn invalid_environment_value_names_the_variable() {
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .env("BUTT_MAX_LINE_BYTES", "0")
+        .output()
+        .expect("run butt");
+
+    let err = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(err.contains("BUTT_MAX_LINE_BYTES='0'"), "stderr: {err}");
+    assert!(err.contains("value must be >= 1"), "stderr: {err}");
+}
+
+#[test]
+fn simulation_feeds_steady_stream_with_two_emissions_and_one_idle_notice() {
+    let config = ThrottleConfig::new(Duration::from_secs(6)).idle_interval(Duration::from_secs(7));
+    let mut sim = Simulation::new(config);
+
+    sim.feed_spread(
+        (0..100).map(|i| format!("msg-{i}")),
+        Duration::from_secs(12),
+    );
+    sim.advance(Duration::from_secs(7));
+
+    assert_eq!(sim.emitted_lines(), vec!["msg-49", "msg-99"]);
+    assert_eq!(sim.idle_notices(), 1);
+    let times: Vec<Duration> = sim.events().iter().map(|(at, _)| *at).collect();
+    assert_eq!(
+        times,
+        vec![
+            Duration::from_secs(6),
+            Duration::from_secs(12),
+            Duration::from_secs(19)
+        ]
+    );
+    assert_eq!(sim.throttler().stats().suppressed(), 98);
+}
+
+#[test]
+fn simulation_regex_match_resets_throttle_window() {
+    let mut sim = Simulation::new(ThrottleConfig::new(Duration::from_secs(2)))
+        .with_pattern(Some(Pattern::new("ERR", false).expect("pattern")));
+
+    sim.feed("regular message");
+    sim.advance(Duration::from_millis(1_500));
+    sim.feed("ERR first");
+    sim.feed("ERR second");
+    sim.advance(Duration::from_millis(1_900));
+
+    assert_eq!(sim.emitted_lines(), vec!["ERR first", "ERR second"]);
+    assert!(sim.events().iter().all(|(at, event)| {
+        *at == Duration::from_millis(1_500) && matches!(event, Event::Line(line) if line.urgent)
+    }));
+}
+
+#[test]
+fn simulation_follows_rotated_file_on_virtual_clock() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let log = tmp.path().join("app.log");
+    File::create(&log).expect("create log file");
+
+    let mut sim = Simulation::new(ThrottleConfig::new(Duration::from_secs(60)))
+        .with_pattern(Some(Pattern::new("rotate", false).expect("pattern")));
+    let mut follower =
+        Follower::file(&log, FollowOptions::default()).with_clock(sim.clock().clone());
+    let poll = Duration::from_millis(25);
+
+    assert!(sim.poll_source(&mut follower, poll).expect("poll"));
+    fs::rename(&log, tmp.path().join("app.log.1")).expect("rename current log");
+    fs::write(&log, "line after rotate\n").expect("write replacement");
+    for _ in 0..3 {
+        assert!(sim.poll_source(&mut follower, poll).expect("poll"));
+    }
+
+    assert_eq!(sim.emitted_lines(), vec!["line after rotate"]);
+    assert_eq!(sim.throttler().stats().rotations, 1);
+    assert_eq!(
+        sim.clock().elapsed(),
+        poll * 4,
+        "polls advance simulated time"
+    );
+}
+
+#[test]
+fn listens_on_tcp_and_labels_lines_by_peer() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--listen",
+            "tcp://127.0.0.1:0",
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            // The peer label is added when printing, so anchored patterns
+            // still see the start of the line.
+            "--regex",
+            "^ERR",
+            "--summary",
+            "off",
+        ])
+        .env("NO_COLOR", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+
+    let (stdout_buf, stdout_handle) =
+        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
+    let (stderr_buf, stderr_handle) =
+        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+
+    assert!(
+        wait_for_contains(&stderr_buf, "listening on tcp://", Duration::from_secs(4)),
+        "listener did not report its address"
+    );
+    let port = {
+        let err = stderr_buf.lock().expect("lock poisoned").clone();
+        let start = err.find("listening on tcp://").expect("address line") + 19;
+        err[start..].lines().next().expect("address").to_string()
+    };
+
+    let mut stream = std::net::TcpStream::connect(&port).expect("connect to butt");
+    let peer = stream.local_addr().expect("peer address").to_string();
+    writeln!(stream, "ERR from socket").expect("send line");
+
+    let expected = format!("[{peer}] ERR from socket");
+    let saw_line = wait_for_contains(&stdout_buf, &expected, Duration::from_secs(4));
+    let saw_connect = wait_for_contains(
+        &stderr_buf,
+        &format!("connection from {peer}"),
+        Duration::from_secs(1),
+    );
+
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = stdout_handle.join();
+    let _ = stderr_handle.join();
+
+    assert!(saw_line, "expected '{expected}' on stdout");
+    assert!(saw_connect, "expected a connection notice on stderr");
+}
+
+#[test]
+fn orders_listener_lines_by_timestamp_and_warns_about_lag() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--listen",
+            "tcp://127.0.0.1:0",
+            "--regex",
+            "ERR",
+            "--max-lag",
+            "60",
+        ])
+        .args([
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            "--summary",
+            "off",
+        ])
+        .env("NO_COLOR", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+
+    let (stdout_buf, stdout_handle) =
+        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
+    let (stderr_buf, stderr_handle) =
+        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+
+    assert!(
+        wait_for_contains(&stderr_buf, "listening on tcp://", Duration::from_secs(4)),
+        "listener did not report its address"
+    );
+    let port = {
+        let err = stderr_buf.lock().expect("lock poisoned").clone();
+        let start = err.find("listening on tcp://").expect("address line") + 19;
+        err[start..].lines().next().expect("address").to_string()
+    };
+
+    let mut stream = std::net::TcpStream::connect(&port).expect("connect to butt");
+    stream
+        .write_all(b"ERR 2020-01-01T00:00:02Z second\nERR 2020-01-01T00:00:01Z first\n")
+        .expect("send lines");
+
+    let saw_both = wait_for_contains(&stdout_buf, "second", Duration::from_secs(4))
+        && wait_for_contains(&stdout_buf, "first", Duration::from_secs(1));
+    let warned = wait_for_contains(&stderr_buf, "over --max-lag 60s", Duration::from_secs(1));
+
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = stdout_handle.join();
+    let _ = stderr_handle.join();
+
+    let out = stdout_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_both, "expected both lines on stdout, got: {out}");
+    assert!(
+        out.find("first") < out.find("second"),
+        "lines should be in timestamp order, got: {out}"
+    );
+    assert!(warned, "expected a lag warning on stderr");
+}
+
+#[test]
+fn syslog_severity_makes_lines_urgent_and_reformats_them() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--syslog",
+            "--urgent-severity",
+            "warning",
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            "--summary",
+            "off",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+
+    let (stdout_buf, stdout_handle) =
+        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
+    let (_stderr_buf, stderr_handle) =
+        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+
+    let mut stdin = child.stdin.take().expect("stdin pipe");
+    writeln!(
+        stdin,
+        "<14>1 2026-10-18T10:00:00Z web1 api 42 - - request ok"
+    )
+    .expect("write info");
+    writeln!(stdin, "<12>Oct 18 10:00:01 web1 api[42]: disk almost full").expect("write warning");
+    stdin.flush().expect("flush stdin");
+
+    let saw_warning = wait_for_contains(
+        &stdout_buf,
+        "web1 api: disk almost full",
+        Duration::from_secs(4),
+    );
+
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = stdout_handle.join();
+    let _ = stderr_handle.join();
+
+    let out = stdout_buf.lock().expect("lock poisoned").clone();
+    assert!(saw_warning, "warning should print immediately, got: {out}");
+    assert!(
+        !out.contains("<12>"),
+        "raw frame should not be shown: {out}"
+    );
+    assert!(
+        !out.contains("request ok"),
+        "info line stays throttled: {out}"
+    );
+}
+
+#[test]
+fn tees_every_input_line_and_the_emitted_output() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let all_path = tmp.path().join("all.log");
+    let emitted_path = tmp.path().join("emitted.log");
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--regex", "ERR", "--line-seconds", "60", "--summary", "off"])
+        .arg("--tee-all")
+        .arg(&all_path)
+        .arg("--tee-emitted")
+        .arg(&emitted_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin pipe")
+                .write_all(b"one\ntwo\nERR three\nfour\n")?;
+            child.wait_with_output()
+        })
+        .expect("run butt");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let all = std::fs::read_to_string(&all_path).expect("read --tee-all file");
+    let emitted = std::fs::read_to_string(&emitted_path).expect("read --tee-emitted file");
+    assert_eq!(all, "one\ntwo\nERR three\nfour\n");
+    assert_eq!(emitted, "ERR three\nfour\n");
+    assert_eq!(stdout, emitted, "the tee mirrors stdout without colors");
+}
+
+#[cfg(unix)]
+#[test]
+fn on_match_hook_receives_line_and_captures_and_is_debounced() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let out = tmp.path().join("hook.out");
+    let hook = format!(
+        "echo \"$BUTT_HOOK_EVENT|$BUTT_HOOK_SOURCE|$BUTT_HOOK_GROUP_CODE|$BUTT_HOOK_LINE\" >> '{}'",
+        out.display()
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--regex",
+            r"ERR (?P<code>\d+)",
+            "--summary",
+            "off",
+            "--hook-debounce-seconds",
+            "60",
+            "--on-match",
+            &hook,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin pipe")
+                .write_all(b"ok\nERR 42 disk\nERR 43 again\n")?;
+            child.wait_with_output()
+        })
+        .expect("run butt");
+
+    assert!(output.status.success());
+    let written = std::fs::read_to_string(&out).expect("hook output");
+    assert_eq!(written, "match|stdin|42|ERR 42 disk\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn failing_hook_is_reported_as_diagnostic() {
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--regex", "ERR", "--summary", "off", "--on-match", "exit 7"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin pipe")
+                .write_all(b"ERR boom\n")?;
+            child.wait_with_output()
+        })
+        .expect("run butt");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[butt] --on-match hook failed: exit status: 7"),
+        "stderr: {stderr}"
+    );
+}
+
+/// Accept one HTTP request on a local stand-in for a webhook endpoint and return its body.
+fn accept_webhook_request(listener: std::net::TcpListener) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept webhook request");
+        let mut reader = std::io::BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            std::io::BufRead::read_line(&mut reader, &mut header).expect("read header");
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().expect("content length");
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).expect("read body");
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .expect("respond");
+        String::from_utf8(body).expect("utf-8 body")
+    })
+}
+
+#[test]
+fn webhook_posts_batched_urgent_lines_to_local_endpoint() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind stand-in");
+    let url = format!("http://{}/alerts", listener.local_addr().expect("addr"));
+    let server = accept_webhook_request(listener);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args(["--regex", "ERR", "--summary", "off", "--webhook", &url])
+        .args(["--webhook-batch-seconds", "60"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin pipe")
+                .write_all(b"ok\nERR first\nfine\nERR second\n")?;
+            child.wait_with_output()
+        })
+        .expect("run butt");
+
+    assert!(output.status.success());
+    let body: serde_json::Value =
+        serde_json::from_str(&server.join().expect("stand-in")).expect("json body");
+    assert_eq!(body["source"], "stdin");
+    assert_eq!(body["count"], 2);
+    assert_eq!(body["events"][0]["line"], "ERR first");
+    assert_eq!(body["events"][1]["pattern"], "ERR");
+    assert_eq!(body["counters"]["lines"], 4);
+}
+
+#[test]
+fn serves_prometheus_metrics_while_following() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--regex",
+            "ERR",
+            "--metrics-addr",
+            "127.0.0.1:0",
+            "--poll-millis",
+            "20",
+            "--summary",
+            "off",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+    let (_stdout_buf, stdout_handle) =
+        spawn_capture_thread(child.stdout.take().expect("stdout pipe"));
+    let (stderr_buf, stderr_handle) =
+        spawn_capture_thread(child.stderr.take().expect("stderr pipe"));
+
+    let mut stdin = child.stdin.take().expect("stdin pipe");
+    stdin.write_all(b"one\nERR two\n").expect("write lines");
+    stdin.flush().expect("flush stdin");
+
+    assert!(wait_for_contains(
+        &stderr_buf,
+        "/metrics\n",
+        Duration::from_secs(4)
+    ));
+    let stderr = stderr_buf.lock().expect("lock poisoned").clone();
+    let addr = stderr
+        .split("serving metrics on http://")
+        .nth(1)
+        .and_then(|rest| rest.split('/').next())
+        .expect("metrics address")
+        .to_string();
+
+    let deadline = Instant::now() + Duration::from_secs(4);
+    let metrics = loop {
+        let mut stream = std::net::TcpStream::connect(&addr).expect("connect to metrics");
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .expect("request");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("response");
+        if response.contains("butt_lines_read_total 2\n") || Instant::now() > deadline {
+            break response;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    drop(stdin);
+    let _ = child.wait();
+    let _ = stdout_handle.join();
+    let _ = stderr_handle.join();
+
+    assert!(metrics.contains("butt_lines_read_total 2\n"), "{metrics}");
+    assert!(metrics.contains("butt_urgent_matches_total{pattern=\"ERR\"} 1\n"));
+    assert!(metrics.contains("# TYPE butt_seconds_since_last_input gauge\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn writes_json_heartbeat_and_notifies_systemd() {
+    use std::os::unix::net::UnixDatagram;
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let heartbeat = tmp.path().join("alive.json");
+    let notify = tmp.path().join("notify.sock");
+    let receiver = UnixDatagram::bind(&notify).expect("bind notify socket");
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(4)))
+        .expect("timeout");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--summary",
+            "off",
+            "--sd-notify",
+            "--heartbeat-format",
+            "json",
+        ])
+        .arg("--heartbeat-file")
+        .arg(&heartbeat)
+        .env("NOTIFY_SOCKET", &notify)
+        .env("WATCHDOG_USEC", "1000000")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child
+                .stdin
+                .take()
+                .expect("stdin pipe")
+                .write_all(b"one\ntwo\n")?;
+            child.wait_with_output()
+        })
+        .expect("run butt");
+    assert!(output.status.success());
+
+    let status: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&heartbeat).expect("heartbeat file"))
+            .expect("json heartbeat");
+    assert_eq!(status["source"], "stdin");
+    assert_eq!(status["lines"], 2);
+
+    let mut messages = Vec::new();
+    let mut buf = [0_u8; 256];
+    receiver.set_nonblocking(true).expect("nonblocking");
+    while let Ok(n) = receiver.recv(&mut buf) {
+        messages.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+    }
+    assert_eq!(
+        messages.first().map(String::as_str),
+        Some("READY=1\nSTATUS=following stdin")
+    );
+    assert!(messages.iter().any(|message| message == "WATCHDOG=1"));
+    assert_eq!(messages.last().map(String::as_str), Some("STOPPING=1"));
+}
+
+#[test]
+fn annotates_progress_lines() {
+    let stdout = run_on_stdin(
+        &[
+            "--progress",
+            r"Processed (\d+)/(\d+)",
+            "--line-seconds",
+            "60",
+        ],
+        b"Processed 20/100\n",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&stdout),
+        "Processed 20/100  [20%]\n"
+    );
+}
+
+#[test]
+fn progress_stall_and_eta_follow_the_virtual_clock() {
+    let clock = VirtualClock::new();
+    let mut progress = r"Processed (\d+)/(\d+)"
+        .parse::<ProgressTracker>()
+        .expect("tracker")
+        .with_stall_after(Some(Duration::from_secs(1)));
+
+    progress.observe(b"Processed 10/100", clock.now());
+    clock.advance(Duration::from_millis(200));
+    progress.observe(b"Processed 20/100", clock.now());
+    clock.advance(Duration::from_millis(500));
+    // Repeating the same number is not progress.
+    progress.observe(b"Processed 20/100", clock.now());
+    assert_eq!(progress.check_stall(clock.now()), None);
+    clock.advance(Duration::from_millis(500));
+
+    let stalled = Reading {
+        current: 20.0,
+        total: Some(100.0),
+    };
+    assert_eq!(
+        progress.check_stall(clock.now()),
+        Some(ProgressChange::Stalled(stalled, Duration::from_secs(1)))
+    );
+    assert_eq!(
+        progress.observe(b"Processed 30/100", clock.now()),
+        Some(ProgressChange::Resumed(Reading {
+            current: 30.0,
+            total: Some(100.0)
+        }))
+    );
+    assert_eq!(
+        progress
+            .annotate(b"Processed 30/100", clock.now())
+            .as_deref(),
+        Some("[30%, 16.7/s, ETA 4s]")
+    );
+}
+
+#[test]
+fn missed_expectation_runs_hook_and_exits() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let hook_out = tmp.path().join("missed.out");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_butt"))
+        .args([
+            "--expect",
+            "health ok",
+            "--within",
+            "1s",
+            "--exit-on-missed",
+        ])
+        .arg("--on-missed")
+        .arg(format!(
+            "echo \"$BUTT_HOOK_EVENT $BUTT_HOOK_PATTERN $BUTT_HOOK_WITHIN_SECONDS\" > '{}'",
+            hook_out.display()
+        ))
+        .args([
+            "--line-seconds",
+            "60",
+            "--poll-millis",
+            "20",
+            "--summary",
+            "off",
+        ])
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn butt");
+
+    // Keep stdin open so only the missed expectation can end the run.
+    let mut stdin = child.stdin.take().expect("stdin pipe");
+    stdin.write_all(b"tick: health ok\n").expect("write");
+    let output = child.wait_with_output().expect("wait for butt");
+    drop(stdin);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(
+        stdout.contains("[no line matching 'health ok' for 1 seconds]"),
+        "expected a missed notice, got: {stdout}"
+    );
+    assert!(
+        stderr.contains("exiting (--exit-on-missed)"),
+        "unexpected stderr: {stderr}"
+    );
+    assert_eq!(
+        fs::read_to_string(&hook_out).expect("hook output"),
+        "missed health ok 1\n"
+    );
+}
+
+#[test]
+fn simulation_repeats_missed_notices_until_the_expected_line_returns() {
+    let expectation = Expectation::new(
+        Pattern::new("health ok", false).expect("pattern"),
+        Duration::from_secs(1),
+    );
+    let mut sim = Simulation::new(ThrottleConfig::new(Duration::from_secs(60)))
+        .with_expectations(vec![expectation]);
+
+    sim.feed("tick: health ok");
+    sim.feed("busy");
+    sim.advance(Duration::from_millis(2_500));
+    assert_eq!(sim.missed_notices(), 2);
+    sim.feed("tick: health ok");
+    sim.advance(Duration::from_millis(900));
+    assert_eq!(sim.missed_notices(), 2, "a match restarts the window");
+    sim.advance(Duration::from_millis(200));
+
+    let missed: Vec<Duration> = sim
+        .events()
+        .iter()
+        .filter_map(|(at, event)| matches!(event, Event::Missed { .. }).then_some(*at))
+        .collect();
+    assert_eq!(
+        missed,
+        vec![
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_millis(3_600)
+        ]
+    );
+    assert_eq!(
+        sim.events()[0].1,
+        Event::Missed {
+            pattern: "health ok".to_string(),
+            within: Duration::from_secs(1)
+        }
+    );
 }